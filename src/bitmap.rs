@@ -0,0 +1,195 @@
+//! Bit-level operations on string values. Like in Redis, bit 0 is the most significant bit of the first byte, and
+//! bits past the end of a string read as zero.
+
+use bytes::Bytes;
+use std::str::FromStr;
+
+/// Bits past this offset would make a string longer than `MAX_STRING_LEN`.
+pub const MAX_BIT_OFFSET: u64 = (crate::store::MAX_STRING_LEN as u64) * 8 - 1;
+
+pub fn get_bit(bytes: &[u8], offset: u64) -> bool {
+    let Some(byte) = bytes.get((offset / 8) as usize) else {
+        return false;
+    };
+    byte & (0x80 >> (offset % 8)) != 0
+}
+
+/// Sets or clears the bit at `offset`, growing `bytes` with zeros as needed. Returns the previous value of the bit.
+pub fn set_bit(bytes: &mut Vec<u8>, offset: u64, on: bool) -> bool {
+    let index = (offset / 8) as usize;
+    if bytes.len() <= index {
+        bytes.resize(index + 1, 0);
+    }
+    let mask = 0x80 >> (offset % 8);
+    let previous = bytes[index] & mask != 0;
+    if on {
+        bytes[index] |= mask;
+    } else {
+        bytes[index] &= !mask;
+    }
+    previous
+}
+
+/// Number of set bits between the bit offsets `start` and `end`, both inclusive.
+pub fn count(bytes: &[u8], start: u64, end: u64) -> u64 {
+    let (first, last) = ((start / 8) as usize, (end / 8) as usize);
+    let mut total: u64 = bytes[first..=last].iter().map(|byte| byte.count_ones() as u64).sum();
+    // Whole bytes were counted, so the bits of the first and last bytes outside the range are taken back out.
+    total -= (bytes[first] & !(0xff >> (start % 8))).count_ones() as u64;
+    total -= (bytes[last] & (0xffu16 >> (end % 8 + 1)) as u8).count_ones() as u64;
+    total
+}
+
+/// Offset of the first bit equal to `bit` between the bit offsets `start` and `end`, both inclusive.
+pub fn position(bytes: &[u8], bit: bool, start: u64, end: u64) -> Option<u64> {
+    // Bytes that are all zeros or all ones when looking for the other value are skipped whole.
+    let skip = if bit { 0x00 } else { 0xff };
+    let mut offset = start;
+    while offset <= end {
+        if offset.is_multiple_of(8) && offset + 7 <= end && bytes[(offset / 8) as usize] == skip {
+            offset += 8;
+            continue;
+        }
+        if get_bit(bytes, offset) == bit {
+            return Some(offset);
+        }
+        offset += 1;
+    }
+    None
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOp {
+    And,
+    Or,
+    Xor,
+    Not,
+    /// Bits set in the first source and in none of the others.
+    Diff,
+}
+
+impl FromStr for BitOp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "and" => Ok(Self::And),
+            "or" => Ok(Self::Or),
+            "xor" => Ok(Self::Xor),
+            "not" => Ok(Self::Not),
+            "diff" => Ok(Self::Diff),
+            _ => Err(()),
+        }
+    }
+}
+
+impl BitOp {
+    /// Combines `sources` byte by byte. Shorter sources are padded with zeros to the length of the longest one.
+    pub fn apply(self, sources: &[Bytes]) -> Vec<u8> {
+        let len = sources.iter().map(Bytes::len).max().unwrap_or(0);
+        let byte = |source: &Bytes, index: usize| source.get(index).copied().unwrap_or(0);
+        (0..len)
+            .map(|index| {
+                let mut bytes = sources.iter().map(|source| byte(source, index));
+                let first = bytes.next().unwrap_or(0);
+                match self {
+                    Self::And => bytes.fold(first, |acc, byte| acc & byte),
+                    Self::Or => bytes.fold(first, |acc, byte| acc | byte),
+                    Self::Xor => bytes.fold(first, |acc, byte| acc ^ byte),
+                    Self::Not => !first,
+                    Self::Diff => first & !bytes.fold(0, |acc, byte| acc | byte),
+                }
+            })
+            .collect()
+    }
+}
+
+/// What `BITFIELD` does when a write does not fit the field.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Overflow {
+    /// Keep the low bits, wrapping around like two's complement arithmetic.
+    #[default]
+    Wrap,
+    /// Clamp to the smallest or largest value of the field.
+    Sat,
+    /// Leave the field untouched.
+    Fail,
+}
+
+/// The type of a `BITFIELD` field: a signed field of up to 64 bits, or an unsigned one of up to 63.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FieldType {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+impl FromStr for FieldType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let signed = match s.as_bytes().first() {
+            Some(b'i' | b'I') => true,
+            Some(b'u' | b'U') => false,
+            _ => return Err(()),
+        };
+        let bits = s[1..].parse::<u32>().map_err(|_| ())?;
+        let max_bits = if signed { 64 } else { 63 };
+        if bits == 0 || bits > max_bits {
+            return Err(());
+        }
+        Ok(Self { signed, bits })
+    }
+}
+
+impl FieldType {
+    fn min(self) -> i128 {
+        if self.signed {
+            -(1 << (self.bits - 1))
+        } else {
+            0
+        }
+    }
+
+    fn max(self) -> i128 {
+        if self.signed {
+            (1 << (self.bits - 1)) - 1
+        } else {
+            (1 << self.bits) - 1
+        }
+    }
+
+    /// Reads the field starting at the bit offset `offset`.
+    pub fn get(self, bytes: &[u8], offset: u64) -> i64 {
+        let raw = (0..self.bits as u64).fold(0u64, |value, bit| value << 1 | get_bit(bytes, offset + bit) as u64);
+        if self.signed && self.bits < 64 && raw >> (self.bits - 1) & 1 == 1 {
+            // Sign-extends the field.
+            (raw | !0 << self.bits) as i64
+        } else {
+            raw as i64
+        }
+    }
+
+    /// Writes the low bits of `value` into the field starting at the bit offset `offset`.
+    pub fn set(self, bytes: &mut Vec<u8>, offset: u64, value: i64) {
+        for bit in 0..self.bits as u64 {
+            let on = (value as u64) >> (self.bits as u64 - 1 - bit) & 1 == 1;
+            set_bit(bytes, offset + bit, on);
+        }
+    }
+
+    /// Fits `value` into the field according to `overflow`, or `None` when it does not fit and `overflow` is `Fail`.
+    pub fn fit(self, value: i128, overflow: Overflow) -> Option<i64> {
+        if (self.min()..=self.max()).contains(&value) {
+            return Some(value as i64);
+        }
+        match overflow {
+            Overflow::Wrap if self.signed => {
+                let shift = 128 - self.bits;
+                Some((value << shift >> shift) as i64)
+            }
+            Overflow::Wrap => Some(value.rem_euclid(1 << self.bits) as i64),
+            Overflow::Sat => Some(value.clamp(self.min(), self.max()) as i64),
+            Overflow::Fail => None,
+        }
+    }
+}
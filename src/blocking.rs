@@ -0,0 +1,199 @@
+use crate::{
+    command::{CommandError, CommandInfo},
+    message::Message,
+    registry::Context,
+    store::Store,
+};
+use anyhow::Result;
+use bytes::Bytes;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    time::Duration,
+};
+use tokio::sync::oneshot;
+
+/// Outcome of serving a blocked command: the reply for its client, the commands replicas must apply to reproduce
+/// what happened, and the keys that may now satisfy other blocked clients.
+#[derive(Debug)]
+pub struct Served {
+    pub reply: Message,
+    pub propagate: Vec<CommandInfo>,
+    pub ready_keys: Vec<Bytes>,
+}
+
+impl Served {
+    pub fn new(reply: Message, propagate: Vec<CommandInfo>) -> Self {
+        Self {
+            reply,
+            propagate,
+            ready_keys: Vec::new(),
+        }
+    }
+}
+
+/// Tries to serve a blocked command against the store. `Ok(None)` means the keys are not ready yet.
+pub type ServeFn = Box<dyn FnMut(&mut Store) -> Result<Option<Served>> + Send>;
+
+struct Waiter {
+    keys: Vec<Bytes>,
+    serve: ServeFn,
+    sender: oneshot::Sender<Served>,
+}
+
+/// Clients blocked on keys, queued per key in the order they blocked.
+#[derive(Default)]
+pub struct BlockedClients {
+    next_id: u64,
+    waiters: HashMap<u64, Waiter>,
+    queues: HashMap<Bytes, VecDeque<u64>>,
+}
+
+impl fmt::Debug for BlockedClients {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockedClients")
+            .field("waiters", &self.waiters.len())
+            .field("keys", &self.queues.len())
+            .finish()
+    }
+}
+
+impl BlockedClients {
+    fn add(&mut self, keys: Vec<Bytes>, serve: ServeFn) -> (u64, oneshot::Receiver<Served>) {
+        let id = self.next_id;
+        self.next_id += 1;
+        for key in &keys {
+            self.queues.entry(key.clone()).or_default().push_back(id);
+        }
+        let (sender, receiver) = oneshot::channel();
+        self.waiters.insert(id, Waiter { keys, serve, sender });
+        (id, receiver)
+    }
+
+    fn remove(&mut self, id: u64) {
+        if let Some(waiter) = self.waiters.remove(&id) {
+            self.unlink(id, &waiter.keys);
+        }
+    }
+
+    fn unlink(&mut self, id: u64, keys: &[Bytes]) {
+        for key in keys {
+            if let Some(queue) = self.queues.get_mut(key) {
+                queue.retain(|waiter| *waiter != id);
+                if queue.is_empty() {
+                    self.queues.remove(key);
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.waiters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+}
+
+/// Serves clients blocked on `keys` in the order they blocked, following any keys their commands make ready in
+/// turn. Returns the commands that replicas need to apply for the served clients.
+pub fn serve_blocked(store: &mut Store, keys: Vec<Bytes>) -> Vec<CommandInfo> {
+    let mut pending: VecDeque<Bytes> = keys.into();
+    let mut propagate = Vec::new();
+    while let Some(key) = pending.pop_front() {
+        let Some(queue) = store.blocked.queues.get(&key) else {
+            continue;
+        };
+        for id in queue.iter().copied().collect::<Vec<_>>() {
+            let Some(mut waiter) = store.blocked.waiters.remove(&id) else {
+                continue;
+            };
+            if waiter.sender.is_closed() {
+                store.blocked.unlink(id, &waiter.keys);
+                continue;
+            }
+            match (waiter.serve)(store) {
+                Ok(Some(served)) => {
+                    store.blocked.unlink(id, &waiter.keys);
+                    propagate.extend(served.propagate.iter().cloned());
+                    pending.extend(served.ready_keys.iter().cloned());
+                    _ = waiter.sender.send(served);
+                }
+                Ok(None) => {
+                    store.blocked.waiters.insert(id, waiter);
+                }
+                // Like in Redis, a command that fails once its keys are ready fails for good instead of blocking again.
+                Err(err) => {
+                    store.blocked.unlink(id, &waiter.keys);
+                    _ = waiter
+                        .sender
+                        .send(Served::new(Message::Error(err.to_string()), Vec::new()));
+                }
+            }
+        }
+    }
+    propagate
+}
+
+/// Runs a blocking command: serves it right away when possible, otherwise parks the client on `keys` until another
+/// client makes them ready, the timeout elapses or the client disconnects. `None` blocks forever.
+pub async fn block_on(
+    ctx: &mut Context,
+    keys: Vec<Bytes>,
+    timeout: Option<Duration>,
+    timeout_reply: Message,
+    mut serve: ServeFn,
+) -> Result<Message> {
+    let (id, mut receiver) = {
+        let mut store = ctx.store.lock().await;
+        if let Some(served) = serve(&mut store)? {
+            ctx.propagate = Some(served.propagate);
+            return Ok(served.reply);
+        }
+        store.blocked.add(keys, serve)
+    };
+    // Whatever happens to this client from now on is propagated by the client that serves it.
+    ctx.propagate = Some(Vec::new());
+    ctx.write_guard = None;
+
+    let disconnected = ctx.client.disconnected.clone();
+    let served = {
+        let wait = async {
+            match timeout {
+                Some(duration) => tokio::time::timeout(duration, &mut receiver).await.ok()?.ok(),
+                None => (&mut receiver).await.ok(),
+            }
+        };
+        tokio::select! {
+            served = wait => served,
+            _ = disconnected.notified() => None,
+        }
+    };
+    if let Some(served) = served {
+        return Ok(served.reply);
+    }
+
+    let mut store = ctx.store.lock().await;
+    store.blocked.remove(id);
+    // The client may have been served right before it was removed.
+    Ok(receiver.try_recv().map_or(timeout_reply, |served| served.reply))
+}
+
+/// Parses a timeout given in seconds, possibly fractional. Zero means blocking forever.
+pub fn parse_timeout(cmd: &CommandInfo, index: usize) -> Result<Option<Duration>, CommandError> {
+    let timeout = cmd
+        .parse_arg::<f64>(index)
+        .ok()
+        .filter(|timeout| timeout.is_finite())
+        .ok_or_else(|| CommandError::Other("ERR timeout is not a float or out of range".to_string()))?;
+    if timeout < 0.0 {
+        return Err(CommandError::Other("ERR timeout is negative".to_string()));
+    }
+    if timeout == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(timeout)
+        .map(Some)
+        .map_err(|_| CommandError::Other("ERR timeout is out of range".to_string()))
+}
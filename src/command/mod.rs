@@ -0,0 +1,534 @@
+use crate::{message::Message, stream::StreamData, utils::unix_millis};
+use bytes::Bytes;
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use table::CommandSpec;
+use thiserror::Error;
+
+pub mod table;
+
+#[derive(Debug, Error)]
+pub enum CommandError {
+    #[error("ERR unknown command '{0}', with args beginning with: {}", format_args_preview(.1))]
+    Unknown(String, Vec<Bytes>),
+    #[error("ERR wrong number of arguments for '{0}' command")]
+    WrongArity(String),
+    #[error("ERR syntax error")]
+    Syntax,
+    #[error("ERR value is not an integer or out of range")]
+    NotInteger,
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+    #[error("{0}")]
+    Other(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandInfo {
+    pub name: String,
+    pub args: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone)]
+pub struct XAddArgs {
+    pub key: Bytes,
+    pub id: String,
+    pub data: StreamData,
+}
+
+#[derive(Debug, Clone)]
+pub struct XRangArgs {
+    pub key: Bytes,
+    pub start: String,
+    pub end: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct XReadArgs {
+    /// How long to block for when no stream has new entries. `Some(Duration::ZERO)` blocks forever.
+    pub block: Option<Duration>,
+    pub requests: Vec<(Bytes, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HelloArgs {
+    pub protover: Option<String>,
+    pub auth: Option<(Bytes, Bytes)>,
+    pub setname: Option<Bytes>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanArgs {
+    pub cursor: u64,
+    pub pattern: Option<Bytes>,
+    pub count: usize,
+    pub novalues: bool,
+}
+
+/// An expiry given to `SET` or `GETEX`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expiry {
+    /// `EX` or `PX`: relative to now.
+    In(Duration),
+    /// `EXAT` or `PXAT`: an absolute time.
+    At(SystemTime),
+    /// `KEEPTTL`: whatever expiry the key already has.
+    Keep,
+    /// `PERSIST`: no expiry at all.
+    Persist,
+}
+
+/// The `NX`, `XX`, `GT` and `LT` options of the expire commands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpireCondition {
+    Always,
+    Nx,
+    Xx,
+    Gt,
+    Lt,
+}
+
+impl ExpireCondition {
+    /// Whether a key or field whose current expiry is `current` may be given `expiry`. No expiry counts as infinite.
+    pub fn allows(self, current: Option<SystemTime>, expiry: SystemTime) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Nx => current.is_none(),
+            Self::Xx => current.is_some(),
+            Self::Gt => current.is_some_and(|current| expiry > current),
+            Self::Lt => current.is_none_or(|current| expiry < current),
+        }
+    }
+}
+
+/// How the time argument of an expire command is interpreted.
+#[derive(Debug, Clone, Copy)]
+pub enum TimeUnit {
+    Seconds,
+    Millis,
+}
+
+/// Whether `SET` only writes keys that are missing (`NX`) or only keys that exist (`XX`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetCondition {
+    Always,
+    IfMissing,
+    IfExists,
+}
+
+#[derive(Debug, Clone)]
+pub struct SetArgs {
+    pub condition: SetCondition,
+    pub get: bool,
+    pub expiry: Option<Expiry>,
+}
+
+/// Arguments of `SINTERCARD` and `ZINTERCARD`. A `limit` of `usize::MAX` means no limit.
+#[derive(Debug, Clone)]
+pub struct InterCardArgs {
+    pub keys: Vec<Bytes>,
+    pub limit: usize,
+}
+
+/// Arguments of `LMPOP` and `ZMPOP`, and of their blocking variants after the timeout. `direction` is the end to pop
+/// from: `LEFT` or `RIGHT` for lists, `MIN` or `MAX` for sorted sets.
+#[derive(Debug, Clone)]
+pub struct MpopArgs<T> {
+    pub keys: Vec<Bytes>,
+    pub direction: T,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum CommandArgs {
+    List,
+    Count,
+    Info(Vec<String>),
+    Docs(Vec<String>),
+    GetKeys(CommandInfo),
+}
+
+impl XAddArgs {
+    pub fn parse(cmd: &CommandInfo) -> Result<Self, CommandError> {
+        if !cmd.args.len().is_multiple_of(2) {
+            return Err(CommandError::WrongArity(cmd.name.to_lowercase()));
+        }
+        Ok(Self {
+            key: cmd.args[0].clone(),
+            id: cmd.arg_string(1),
+            data: get_stream_data(cmd.args[2..].to_vec()),
+        })
+    }
+}
+
+impl XRangArgs {
+    pub fn parse(cmd: &CommandInfo) -> Result<Self, CommandError> {
+        Ok(Self {
+            key: cmd.args[0].clone(),
+            start: cmd.arg_string(1),
+            end: cmd.arg_string(2),
+        })
+    }
+}
+
+impl XReadArgs {
+    pub fn parse(cmd: &CommandInfo) -> Result<Self, CommandError> {
+        let mut marker = 0;
+        let mut block = None;
+
+        loop {
+            match cmd
+                .args
+                .get(marker)
+                .map(|arg| arg_to_string(arg).to_lowercase())
+                .as_deref()
+            {
+                Some("streams") => {
+                    marker += 1;
+                    break;
+                }
+                Some("block") => {
+                    let duration = cmd.parse_arg::<u64>(marker + 1)?;
+                    block = Some(Duration::from_millis(duration));
+                    marker += 2;
+                }
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+
+        let key_marker = marker;
+        let remaining = cmd.args.len() - key_marker;
+        if remaining == 0 || !remaining.is_multiple_of(2) {
+            return Err(CommandError::Other(
+                "ERR Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified."
+                    .to_string(),
+            ));
+        }
+        let amount_of_streams = remaining / 2;
+        let id_marker = key_marker + amount_of_streams;
+        let mut requests: Vec<(Bytes, String)> = Vec::with_capacity(amount_of_streams);
+
+        for i in 0..amount_of_streams {
+            let key = cmd.args[key_marker + i].clone();
+            let id = cmd.arg_string(id_marker + i);
+            requests.push((key, id));
+        }
+
+        Ok(Self { block, requests })
+    }
+}
+
+impl HelloArgs {
+    pub fn parse(cmd: &CommandInfo) -> Result<Self, CommandError> {
+        let mut args = Self {
+            protover: cmd.args.first().map(arg_to_string),
+            auth: None,
+            setname: None,
+        };
+        let mut index = 1;
+        while index < cmd.args.len() {
+            match cmd.arg_string(index).to_lowercase().as_str() {
+                "auth" if index + 2 < cmd.args.len() => {
+                    args.auth = Some((cmd.args[index + 1].clone(), cmd.args[index + 2].clone()));
+                    index += 3;
+                }
+                "setname" if index + 1 < cmd.args.len() => {
+                    args.setname = Some(cmd.args[index + 1].clone());
+                    index += 2;
+                }
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        Ok(args)
+    }
+}
+
+impl ScanArgs {
+    /// Parses `cursor [MATCH pattern] [COUNT count] [NOVALUES]` starting at `index`.
+    pub fn parse(cmd: &CommandInfo, index: usize) -> Result<Self, CommandError> {
+        let cursor = cmd
+            .parse_arg::<u64>(index)
+            .map_err(|_| CommandError::Other("ERR invalid cursor".to_string()))?;
+        let mut args = Self {
+            cursor,
+            pattern: None,
+            count: 10,
+            novalues: false,
+        };
+        let mut index = index + 1;
+        while index < cmd.args.len() {
+            match cmd.arg_string(index).to_lowercase().as_str() {
+                "match" if index + 1 < cmd.args.len() => {
+                    args.pattern = Some(cmd.args[index + 1].clone());
+                    index += 2;
+                }
+                "count" if index + 1 < cmd.args.len() => {
+                    let count = cmd.parse_arg::<i64>(index + 1)?;
+                    if count < 1 {
+                        return Err(CommandError::Syntax);
+                    }
+                    args.count = count as usize;
+                    index += 2;
+                }
+                "novalues" => {
+                    args.novalues = true;
+                    index += 1;
+                }
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        Ok(args)
+    }
+}
+
+impl Expiry {
+    /// Parses the value at `index` of an `EX`, `PX`, `EXAT` or `PXAT` option, given lowercase as `option`.
+    pub fn parse(cmd: &CommandInfo, option: &str, index: usize) -> Result<Self, CommandError> {
+        let value = cmd.parse_arg::<i64>(index)?;
+        let invalid = || {
+            CommandError::Other(format!(
+                "ERR invalid expire time in '{}' command",
+                cmd.name.to_lowercase()
+            ))
+        };
+        if value <= 0 {
+            return Err(invalid());
+        }
+        let millis = match option {
+            "ex" | "exat" => value.checked_mul(1000).ok_or_else(invalid)?,
+            _ => value,
+        };
+        // Relative times are replicated as absolute ones, which have to fit too.
+        if matches!(option, "ex" | "px") {
+            millis
+                .checked_add(unix_millis(SystemTime::now()) as i64)
+                .ok_or_else(invalid)?;
+        }
+        let millis = Duration::from_millis(millis as u64);
+        Ok(match option {
+            "ex" | "px" => Self::In(millis),
+            _ => Self::At(UNIX_EPOCH + millis),
+        })
+    }
+
+    /// The absolute expiry time this resolves to, given the one the key has now.
+    pub fn resolve(self, current: Option<SystemTime>) -> Option<SystemTime> {
+        match self {
+            Self::In(duration) => Some(SystemTime::now() + duration),
+            Self::At(time) => Some(time),
+            Self::Keep => current,
+            Self::Persist => None,
+        }
+    }
+}
+
+impl SetArgs {
+    /// Parses `[NX | XX] [GET] [EX seconds | PX milliseconds | EXAT timestamp | PXAT timestamp | KEEPTTL]` after the
+    /// key and value, in any order and case.
+    pub fn parse(cmd: &CommandInfo) -> Result<Self, CommandError> {
+        let mut args = Self {
+            condition: SetCondition::Always,
+            get: false,
+            expiry: None,
+        };
+        let mut index = 2;
+        while index < cmd.args.len() {
+            let option = cmd.arg_string(index).to_lowercase();
+            match option.as_str() {
+                "nx" if args.condition != SetCondition::IfExists => args.condition = SetCondition::IfMissing,
+                "xx" if args.condition != SetCondition::IfMissing => args.condition = SetCondition::IfExists,
+                "get" => args.get = true,
+                "keepttl" if args.expiry.is_none() => args.expiry = Some(Expiry::Keep),
+                "ex" | "px" | "exat" | "pxat" if args.expiry.is_none() && index + 1 < cmd.args.len() => {
+                    args.expiry = Some(Expiry::parse(cmd, &option, index + 1)?);
+                    index += 1;
+                }
+                _ => return Err(CommandError::Syntax),
+            }
+            index += 1;
+        }
+        Ok(args)
+    }
+}
+
+impl InterCardArgs {
+    /// Parses `numkeys key [key ...] [LIMIT limit]`.
+    pub fn parse(cmd: &CommandInfo) -> Result<Self, CommandError> {
+        let numkeys = cmd
+            .parse_arg::<i64>(0)
+            .ok()
+            .filter(|numkeys| *numkeys > 0)
+            .ok_or_else(|| CommandError::Other("ERR numkeys should be greater than 0".to_string()))?
+            as usize;
+        if numkeys >= cmd.args.len() {
+            return Err(CommandError::Other(
+                "ERR Number of keys can't be greater than number of args".to_string(),
+            ));
+        }
+
+        let mut limit = usize::MAX;
+        let mut index = numkeys + 1;
+        while index < cmd.args.len() {
+            match cmd.arg_string(index).to_lowercase().as_str() {
+                "limit" if index + 1 < cmd.args.len() => {
+                    let value = cmd.parse_arg::<i64>(index + 1)?;
+                    if value < 0 {
+                        return Err(CommandError::Other("ERR LIMIT can't be negative".to_string()));
+                    }
+                    // A limit of zero means no limit.
+                    limit = if value == 0 { usize::MAX } else { value as usize };
+                    index += 2;
+                }
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        Ok(Self {
+            keys: cmd.args[1..=numkeys].to_vec(),
+            limit,
+        })
+    }
+}
+
+impl<T> MpopArgs<T> {
+    /// Parses `numkeys key [key ...] direction [COUNT count]` from `index`, reading the direction token with
+    /// `parse_direction`.
+    pub fn parse(
+        cmd: &CommandInfo,
+        index: usize,
+        parse_direction: impl Fn(&CommandInfo, usize) -> Result<T, CommandError>,
+    ) -> Result<Self, CommandError> {
+        let numkeys = cmd
+            .parse_arg::<i64>(index)
+            .ok()
+            .filter(|numkeys| *numkeys > 0)
+            .ok_or_else(|| CommandError::Other("ERR numkeys should be greater than 0".to_string()))?
+            as usize;
+        let keys_start = index + 1;
+        if keys_start + numkeys >= cmd.args.len() {
+            return Err(CommandError::Syntax);
+        }
+        let keys = cmd.args[keys_start..keys_start + numkeys].to_vec();
+        let direction = parse_direction(cmd, keys_start + numkeys)?;
+
+        let mut count = None;
+        let mut index = keys_start + numkeys + 1;
+        while index < cmd.args.len() {
+            match cmd.arg_string(index).to_lowercase().as_str() {
+                "count" if count.is_none() && index + 1 < cmd.args.len() => {
+                    let value = cmd
+                        .parse_arg::<i64>(index + 1)
+                        .ok()
+                        .filter(|count| *count > 0)
+                        .ok_or_else(|| CommandError::Other("ERR count should be greater than 0".to_string()))?;
+                    count = Some(value as usize);
+                    index += 2;
+                }
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        Ok(Self {
+            keys,
+            direction,
+            count: count.unwrap_or(1),
+        })
+    }
+}
+
+impl CommandArgs {
+    pub fn parse(cmd: &CommandInfo) -> Result<Self, CommandError> {
+        let subcommand = cmd.args.first().map(|arg| arg_to_string(arg).to_lowercase());
+        let names = || cmd.args[1..].iter().map(arg_to_string).collect();
+        let args = match subcommand.as_deref() {
+            None => Self::List,
+            Some("count") if cmd.args.len() == 1 => Self::Count,
+            Some("info") => Self::Info(names()),
+            Some("docs") => Self::Docs(names()),
+            Some("getkeys") if cmd.args.len() >= 2 => {
+                Self::GetKeys(CommandInfo::new(cmd.arg_string(1), cmd.args[2..].to_vec()))
+            }
+            Some(subcommand @ ("count" | "getkeys")) => {
+                return Err(CommandError::WrongArity(format!("command|{}", subcommand)))
+            }
+            Some(subcommand) => {
+                return Err(CommandError::Other(format!(
+                    "ERR unknown subcommand '{}'. Try COMMAND HELP.",
+                    subcommand
+                )))
+            }
+        };
+        Ok(args)
+    }
+}
+
+impl CommandInfo {
+    pub fn new(name: String, args: Vec<Bytes>) -> Self {
+        CommandInfo { name, args }
+    }
+
+    pub fn to_message(&self) -> Message {
+        let mut array_values = Vec::with_capacity(self.args.len() + 1);
+        array_values.push(Message::Bulk(self.name.clone().into()));
+        for arg in &self.args {
+            array_values.push(Message::Bulk(arg.clone()));
+        }
+        Message::Array(array_values)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        self.to_message().encode()
+    }
+
+    /// Extracts the key arguments of the command using the key positions declared in its spec.
+    pub fn keys(&self, spec: &CommandSpec) -> Vec<Bytes> {
+        let mut argv = Vec::with_capacity(self.args.len() + 1);
+        argv.push(Bytes::from(self.name.clone()));
+        argv.extend(self.args.iter().cloned());
+        spec.key_positions(&argv)
+            .into_iter()
+            .filter_map(|position| argv.get(position).cloned())
+            .collect()
+    }
+
+    pub fn arg_string(&self, index: usize) -> String {
+        self.args.get(index).map(arg_to_string).unwrap_or_default()
+    }
+
+    /// Parses the count of `HRANDFIELD`, `SRANDMEMBER` or `ZRANDMEMBER` at `index`. Negative counts repeat elements,
+    /// and like Redis both signs are limited to half the integer range.
+    pub fn parse_random_count(&self, index: usize) -> Result<i64, CommandError> {
+        let count = self.parse_arg::<i64>(index)?;
+        if !(-(i64::MAX / 2)..=i64::MAX / 2).contains(&count) {
+            return Err(CommandError::Other("ERR value is out of range".to_string()));
+        }
+        Ok(count)
+    }
+
+    pub fn parse_arg<T: FromStr>(&self, index: usize) -> Result<T, CommandError> {
+        let arg = self.args.get(index).ok_or(CommandError::Syntax)?;
+        std::str::from_utf8(arg)
+            .ok()
+            .and_then(|arg| arg.parse::<T>().ok())
+            .ok_or(CommandError::NotInteger)
+    }
+}
+
+fn arg_to_string(arg: &Bytes) -> String {
+    String::from_utf8_lossy(arg).into_owned()
+}
+
+fn get_stream_data(args: Vec<Bytes>) -> StreamData {
+    let mut data = HashMap::new();
+    for i in (0..args.len()).step_by(2) {
+        data.insert(args[i].clone(), args[i + 1].clone());
+    }
+    StreamData { data }
+}
+
+fn format_args_preview(args: &[Bytes]) -> String {
+    args.iter()
+        .map(|arg| format!("'{}' ", String::from_utf8_lossy(arg)))
+        .collect()
+}
@@ -0,0 +1,580 @@
+use crate::message::Message;
+use bytes::Bytes;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandFlag {
+    Write,
+    ReadOnly,
+    DenyOom,
+    Admin,
+    NoScript,
+    Blocking,
+    Loading,
+    Stale,
+    Fast,
+    NoAuth,
+}
+
+impl fmt::Display for CommandFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            Self::Write => "write",
+            Self::ReadOnly => "readonly",
+            Self::DenyOom => "denyoom",
+            Self::Admin => "admin",
+            Self::NoScript => "noscript",
+            Self::Blocking => "blocking",
+            Self::Loading => "loading",
+            Self::Stale => "stale",
+            Self::Fast => "fast",
+            Self::NoAuth => "no_auth",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+/// Finds the positions of key arguments for commands whose keys cannot be described by `first_key`/`last_key`/`step`.
+/// Positions index into the full argument vector, where the command name is at position 0.
+pub type KeyFinder = fn(&[Bytes]) -> Vec<usize>;
+
+/// Static description of a command, mirroring the metadata Redis keeps in its own command table.
+///
+/// `arity` counts the command name itself; a negative value means "at least that many arguments".
+/// `first_key`, `last_key` and `step` locate the keys among the arguments the same way, with a negative
+/// `last_key` counting from the end and zeros meaning the command takes no keys.
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub arity: i64,
+    pub flags: &'static [CommandFlag],
+    pub first_key: i64,
+    pub last_key: i64,
+    pub step: i64,
+    pub key_finder: Option<KeyFinder>,
+    pub group: &'static str,
+    pub summary: &'static str,
+}
+
+impl CommandSpec {
+    pub const fn new(name: &'static str, arity: i64, flags: &'static [CommandFlag]) -> Self {
+        Self {
+            name,
+            arity,
+            flags,
+            first_key: 0,
+            last_key: 0,
+            step: 0,
+            key_finder: None,
+            group: "generic",
+            summary: "",
+        }
+    }
+
+    pub const fn keys(mut self, first_key: i64, last_key: i64, step: i64) -> Self {
+        self.first_key = first_key;
+        self.last_key = last_key;
+        self.step = step;
+        self
+    }
+
+    pub const fn movable_keys(mut self, key_finder: KeyFinder) -> Self {
+        self.key_finder = Some(key_finder);
+        self
+    }
+
+    pub const fn doc(mut self, group: &'static str, summary: &'static str) -> Self {
+        self.group = group;
+        self.summary = summary;
+        self
+    }
+
+    pub fn has_flag(&self, flag: CommandFlag) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    /// Checks `argc`, which includes the command name, against the declared arity.
+    pub fn check_arity(&self, argc: usize) -> bool {
+        let argc = argc as i64;
+        if self.arity >= 0 {
+            argc == self.arity
+        } else {
+            argc >= -self.arity
+        }
+    }
+
+    /// Returns the positions of the keys in `argv`, which starts with the command name.
+    pub fn key_positions(&self, argv: &[Bytes]) -> Vec<usize> {
+        if let Some(key_finder) = self.key_finder {
+            return key_finder(argv);
+        }
+        if self.first_key <= 0 {
+            return Vec::new();
+        }
+        let argc = argv.len() as i64;
+        let last_key = if self.last_key < 0 {
+            argc + self.last_key
+        } else {
+            self.last_key
+        };
+        (self.first_key..=last_key.min(argc - 1))
+            .step_by(self.step.max(1) as usize)
+            .map(|position| position as usize)
+            .collect()
+    }
+
+    /// Builds the reply entry describing this command for `COMMAND` and `COMMAND INFO`.
+    pub fn to_message(&self) -> Message {
+        let mut flags: Vec<Message> = self
+            .flags
+            .iter()
+            .map(|flag| Message::Simple(flag.to_string()))
+            .collect();
+        if self.key_finder.is_some() {
+            flags.push(Message::Simple("movablekeys".to_string()));
+        }
+        Message::Array(vec![
+            Message::Bulk(self.name.into()),
+            Message::Int(self.arity as isize),
+            Message::Set(flags),
+            Message::Int(self.first_key as isize),
+            Message::Int(self.last_key as isize),
+            Message::Int(self.step as isize),
+            Message::Set(vec![]),
+            Message::Array(vec![]),
+            Message::Array(vec![]),
+            Message::Array(vec![]),
+        ])
+    }
+
+    /// Builds the documentation map returned by `COMMAND DOCS`.
+    pub fn docs_message(&self) -> Message {
+        Message::Map(vec![
+            (Message::Bulk("summary".into()), Message::Bulk(self.summary.into())),
+            (Message::Bulk("group".into()), Message::Bulk(self.group.into())),
+        ])
+    }
+}
+
+use CommandFlag::*;
+
+pub static COMMAND_TABLE: &[CommandSpec] = &[
+    CommandSpec::new("ping", -1, &[Fast, Stale]).doc("connection", "Returns the server's liveliness response."),
+    CommandSpec::new("echo", 2, &[Fast]).doc("connection", "Returns the given string."),
+    CommandSpec::new("get", 2, &[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .doc("string", "Returns the string value of a key."),
+    CommandSpec::new("set", -3, &[Write, DenyOom])
+        .keys(1, 1, 1)
+        .doc("string", "Sets the string value of a key, ignoring its type."),
+    CommandSpec::new("setnx", 3, &[Write, DenyOom, Fast]).keys(1, 1, 1).doc("string", "Set the string value of a key only when the key doesn't exist."),
+    CommandSpec::new("setex", 4, &[Write, DenyOom]).keys(1, 1, 1).doc("string", "Sets the string value and expiration time of a key. Creates the key if it doesn't exist."),
+    CommandSpec::new("psetex", 4, &[Write, DenyOom]).keys(1, 1, 1).doc("string", "Sets both string value and expiration time in milliseconds of a key. The key is created if it doesn't exist."),
+    CommandSpec::new("getset", 3, &[Write, DenyOom, Fast]).keys(1, 1, 1).doc("string", "Returns the previous string value of a key after setting it to a new value."),
+    CommandSpec::new("getdel", 2, &[Write, Fast]).keys(1, 1, 1).doc("string", "Returns the string value of a key after deleting the key."),
+    CommandSpec::new("getex", -2, &[Write, Fast]).keys(1, 1, 1).doc("string", "Returns the string value of a key after setting its expiration time."),
+    CommandSpec::new("mget", -2, &[ReadOnly, Fast]).keys(1, -1, 1).doc("string", "Atomically returns the string values of one or more keys."),
+    CommandSpec::new("mset", -3, &[Write, DenyOom]).keys(1, -1, 2).doc("string", "Atomically creates or modifies the string values of one or more keys."),
+    CommandSpec::new("msetnx", -3, &[Write, DenyOom]).keys(1, -1, 2).doc("string", "Atomically modifies the string values of one or more keys only when all keys don't exist."),
+    CommandSpec::new("incr", 2, &[Write, DenyOom, Fast]).keys(1, 1, 1).doc("string", "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist."),
+    CommandSpec::new("decr", 2, &[Write, DenyOom, Fast]).keys(1, 1, 1).doc("string", "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist."),
+    CommandSpec::new("incrby", 3, &[Write, DenyOom, Fast]).keys(1, 1, 1).doc("string", "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist."),
+    CommandSpec::new("decrby", 3, &[Write, DenyOom, Fast]).keys(1, 1, 1).doc("string", "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist."),
+    CommandSpec::new("incrbyfloat", 3, &[Write, DenyOom, Fast]).keys(1, 1, 1).doc("string", "Increment the floating point value of a key by a number. Uses 0 as initial value if the key doesn't exist."),
+    CommandSpec::new("append", 3, &[Write, DenyOom, Fast]).keys(1, 1, 1).doc("string", "Appends a string to the value of a key. Creates the key if it doesn't exist."),
+    CommandSpec::new("strlen", 2, &[ReadOnly, Fast]).keys(1, 1, 1).doc("string", "Returns the length of a string value."),
+    CommandSpec::new("getrange", 4, &[ReadOnly]).keys(1, 1, 1).doc("string", "Returns a substring of the string stored at a key."),
+    CommandSpec::new("setrange", 4, &[Write, DenyOom]).keys(1, 1, 1).doc("string", "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist."),
+    CommandSpec::new("lcs", -3, &[ReadOnly]).keys(1, 2, 1).doc("string", "Finds the longest common substring."),
+    CommandSpec::new("setbit", 4, &[Write, DenyOom]).keys(1, 1, 1).doc("bitmap", "Sets or clears the bit at offset of the string value. Creates the key if it doesn't exist."),
+    CommandSpec::new("getbit", 3, &[ReadOnly, Fast]).keys(1, 1, 1).doc("bitmap", "Returns a bit value by offset."),
+    CommandSpec::new("bitcount", -2, &[ReadOnly]).keys(1, 1, 1).doc("bitmap", "Counts the number of set bits (population counting) in a string."),
+    CommandSpec::new("bitpos", -3, &[ReadOnly]).keys(1, 1, 1).doc("bitmap", "Finds the first set (1) or clear (0) bit in a string."),
+    CommandSpec::new("bitop", -4, &[Write, DenyOom]).keys(2, -1, 1).doc("bitmap", "Performs bitwise operations on multiple strings, and stores the result."),
+    CommandSpec::new("bitfield", -2, &[Write, DenyOom]).keys(1, 1, 1).doc("bitmap", "Performs arbitrary bitfield integer operations on strings."),
+    CommandSpec::new("bitfield_ro", -2, &[ReadOnly, Fast]).keys(1, 1, 1).doc("bitmap", "Performs arbitrary read-only bitfield integer operations on strings."),
+    CommandSpec::new("pfadd", -2, &[Write, DenyOom, Fast]).keys(1, 1, 1).doc("hyperloglog", "Adds elements to a HyperLogLog key. Creates the key if it doesn't exist."),
+    CommandSpec::new("pfcount", -2, &[ReadOnly]).keys(1, -1, 1).doc("hyperloglog", "Returns the approximated cardinality of the set(s) observed by the HyperLogLog key(s)."),
+    CommandSpec::new("pfmerge", -2, &[Write, DenyOom]).keys(1, -1, 1).doc("hyperloglog", "Merges one or more HyperLogLog values into a single key."),
+    CommandSpec::new("pfdebug", 3, &[Write, DenyOom, Admin]).keys(2, 2, 1).doc("hyperloglog", "Internal commands for debugging HyperLogLog values."),
+    CommandSpec::new("pfselftest", 1, &[Admin]).doc("hyperloglog", "An internal command for testing HyperLogLog values."),
+    CommandSpec::new("geoadd", -5, &[Write, DenyOom]).keys(1, 1, 1).doc("geo", "Adds one or more members to a geospatial index. The key is created if it doesn't exist."),
+    CommandSpec::new("geopos", -2, &[ReadOnly]).keys(1, 1, 1).doc("geo", "Returns the longitude and latitude of members from a geospatial index."),
+    CommandSpec::new("geodist", -4, &[ReadOnly]).keys(1, 1, 1).doc("geo", "Returns the distance between two members of a geospatial index."),
+    CommandSpec::new("geohash", -2, &[ReadOnly]).keys(1, 1, 1).doc("geo", "Returns members from a geospatial index as geohash strings."),
+    CommandSpec::new("geosearch", -7, &[ReadOnly]).keys(1, 1, 1).doc("geo", "Queries a geospatial index for members inside an area of a box or a circle."),
+    CommandSpec::new("geosearchstore", -8, &[Write, DenyOom]).keys(1, 2, 1).doc("geo", "Queries a geospatial index for members inside an area of a box or a circle, optionally stores the result."),
+    CommandSpec::new("info", -1, &[Loading, Stale])
+        .doc("server", "Returns information and statistics about the server."),
+    CommandSpec::new("replconf", -1, &[Admin, NoScript, Loading, Stale])
+        .doc("server", "An internal command for configuring the replication stream."),
+    CommandSpec::new("psync", -3, &[Admin, NoScript]).doc("server", "An internal command used in replication."),
+    CommandSpec::new("wait", 3, &[NoScript]).doc(
+        "generic",
+        "Blocks until the asynchronous replication of all preceding write commands is acknowledged.",
+    ),
+    CommandSpec::new("config", -2, &[Admin, NoScript, Loading, Stale])
+        .doc("server", "Gets or sets the server configuration."),
+    CommandSpec::new("keys", 2, &[ReadOnly]).doc("generic", "Returns all key names that match a pattern."),
+    CommandSpec::new("type", 2, &[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .doc("generic", "Determines the type of value stored at a key."),
+    CommandSpec::new("del", -2, &[Write]).keys(1, -1, 1).doc("generic", "Deletes one or more keys."),
+    CommandSpec::new("unlink", -2, &[Write, Fast]).keys(1, -1, 1).doc("generic", "Asynchronously deletes one or more keys."),
+    CommandSpec::new("exists", -2, &[ReadOnly, Fast]).keys(1, -1, 1).doc("generic", "Determines whether one or more keys exist."),
+    CommandSpec::new("expire", -3, &[Write, Fast]).keys(1, 1, 1).doc("generic", "Sets the expiration time of a key in seconds."),
+    CommandSpec::new("pexpire", -3, &[Write, Fast]).keys(1, 1, 1).doc("generic", "Sets the expiration time of a key in milliseconds."),
+    CommandSpec::new("expireat", -3, &[Write, Fast]).keys(1, 1, 1).doc("generic", "Sets the expiration time of a key to a Unix timestamp."),
+    CommandSpec::new("pexpireat", -3, &[Write, Fast]).keys(1, 1, 1).doc("generic", "Sets the expiration time of a key to a Unix milliseconds timestamp."),
+    CommandSpec::new("ttl", 2, &[ReadOnly, Fast]).keys(1, 1, 1).doc("generic", "Returns the expiration time in seconds of a key."),
+    CommandSpec::new("pttl", 2, &[ReadOnly, Fast]).keys(1, 1, 1).doc("generic", "Returns the expiration time in milliseconds of a key."),
+    CommandSpec::new("expiretime", 2, &[ReadOnly, Fast]).keys(1, 1, 1).doc("generic", "Returns the expiration time of a key as a Unix timestamp."),
+    CommandSpec::new("pexpiretime", 2, &[ReadOnly, Fast]).keys(1, 1, 1).doc("generic", "Returns the expiration time of a key as a Unix milliseconds timestamp."),
+    CommandSpec::new("persist", 2, &[Write, Fast]).keys(1, 1, 1).doc("generic", "Removes the expiration time of a key."),
+    CommandSpec::new("xadd", -5, &[Write, DenyOom, Fast]).keys(1, 1, 1).doc(
+        "stream",
+        "Appends a new message to a stream. Creates the key if it doesn't exist.",
+    ),
+    CommandSpec::new("xrange", -4, &[ReadOnly])
+        .keys(1, 1, 1)
+        .doc("stream", "Returns the messages from a stream within a range of IDs."),
+    CommandSpec::new("xread", -4, &[ReadOnly, Blocking])
+        .movable_keys(xread_keys)
+        .doc(
+            "stream",
+            "Returns messages from multiple streams with IDs greater than the ones requested.",
+        ),
+    CommandSpec::new("hello", -1, &[NoScript, Loading, Stale, Fast, NoAuth])
+        .doc("connection", "Handshakes with the server."),
+    CommandSpec::new("command", -1, &[Loading, Stale])
+        .doc("server", "Returns detailed information about all commands."),
+    CommandSpec::new("lpush", -3, &[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .doc(
+            "list",
+            "Prepends one or more elements to a list. Creates the key if it doesn't exist.",
+        ),
+    CommandSpec::new("rpush", -3, &[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .doc(
+            "list",
+            "Appends one or more elements to a list. Creates the key if it doesn't exist.",
+        ),
+    CommandSpec::new("lpop", -2, &[Write, Fast]).keys(1, 1, 1).doc(
+        "list",
+        "Returns the first elements in a list after removing it. Deletes the list if the last element was popped.",
+    ),
+    CommandSpec::new("rpop", -2, &[Write, Fast]).keys(1, 1, 1).doc(
+        "list",
+        "Returns and removes the last elements of the list. Deletes the list if the last element was popped.",
+    ),
+    CommandSpec::new("lrange", 4, &[ReadOnly])
+        .keys(1, 1, 1)
+        .doc("list", "Returns a range of elements from a list."),
+    CommandSpec::new("lindex", 3, &[ReadOnly])
+        .keys(1, 1, 1)
+        .doc("list", "Returns an element from a list by its index."),
+    CommandSpec::new("lset", 4, &[Write, DenyOom])
+        .keys(1, 1, 1)
+        .doc("list", "Sets the value of an element in a list by its index."),
+    CommandSpec::new("lrem", 4, &[Write]).keys(1, 1, 1).doc(
+        "list",
+        "Removes elements from a list. Deletes the list if the last element was removed.",
+    ),
+    CommandSpec::new("ltrim", 4, &[Write]).keys(1, 1, 1).doc(
+        "list",
+        "Removes elements from both ends a list. Deletes the list if all elements were trimmed.",
+    ),
+    CommandSpec::new("linsert", 5, &[Write, DenyOom])
+        .keys(1, 1, 1)
+        .doc("list", "Inserts an element before or after another element in a list."),
+    CommandSpec::new("llen", 2, &[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .doc("list", "Returns the length of a list."),
+    CommandSpec::new("lpos", -3, &[ReadOnly])
+        .keys(1, 1, 1)
+        .doc("list", "Returns the index of matching elements in a list."),
+    CommandSpec::new("lmove", 5, &[Write, DenyOom]).keys(1, 2, 1).doc(
+        "list",
+        "Returns an element after popping it from one list and pushing it to another. Deletes the list if the last \
+         element was moved.",
+    ),
+    CommandSpec::new("lmpop", -4, &[Write]).movable_keys(numkeys_first).doc(
+        "list",
+        "Returns multiple elements from a list after removing them. Deletes the list if the last element was popped.",
+    ),
+    CommandSpec::new("blpop", -3, &[Write, Blocking]).keys(1, -2, 1).doc(
+        "list",
+        "Removes and returns the first element in a list. Blocks until an element is available otherwise. Deletes \
+         the list if the last element was popped.",
+    ),
+    CommandSpec::new("brpop", -3, &[Write, Blocking]).keys(1, -2, 1).doc(
+        "list",
+        "Removes and returns the last element in a list. Blocks until an element is available otherwise. Deletes \
+         the list if the last element was popped.",
+    ),
+    CommandSpec::new("blmove", 6, &[Write, DenyOom, Blocking])
+        .keys(1, 2, 1)
+        .doc(
+            "list",
+            "Pops an element from a list, pushes it to another list and returns it. Blocks until an element is \
+         available otherwise. Deletes the list if the last element was moved.",
+        ),
+    CommandSpec::new("blmpop", -5, &[Write, Blocking])
+        .movable_keys(numkeys_second)
+        .doc(
+            "list",
+            "Pops the first element from one of multiple lists. Blocks until an element is available otherwise. \
+         Deletes the list if the last element was popped.",
+        ),
+    CommandSpec::new("hset", -4, &[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .doc("hash", "Creates or modifies the value of a field in a hash."),
+    CommandSpec::new("hmset", -4, &[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .doc("hash", "Sets the values of multiple fields."),
+    CommandSpec::new("hsetnx", 4, &[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .doc(
+            "hash",
+            "Sets the value of a field in a hash only when the field doesn't exist.",
+        ),
+    CommandSpec::new("hget", 3, &[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .doc("hash", "Returns the value of a field in a hash."),
+    CommandSpec::new("hmget", -3, &[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .doc("hash", "Returns the values of all fields in a hash."),
+    CommandSpec::new("hdel", -3, &[Write, Fast]).keys(1, 1, 1).doc(
+        "hash",
+        "Deletes one or more fields and their values from a hash. Deletes the hash if no fields remain.",
+    ),
+    CommandSpec::new("hlen", 2, &[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .doc("hash", "Returns the number of fields in a hash."),
+    CommandSpec::new("hstrlen", 3, &[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .doc("hash", "Returns the length of the value of a field."),
+    CommandSpec::new("hexists", 3, &[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .doc("hash", "Determines whether a field exists in a hash."),
+    CommandSpec::new("hkeys", 2, &[ReadOnly])
+        .keys(1, 1, 1)
+        .doc("hash", "Returns all fields in a hash."),
+    CommandSpec::new("hvals", 2, &[ReadOnly])
+        .keys(1, 1, 1)
+        .doc("hash", "Returns all values in a hash."),
+    CommandSpec::new("hgetall", 2, &[ReadOnly])
+        .keys(1, 1, 1)
+        .doc("hash", "Returns all fields and values in a hash."),
+    CommandSpec::new("hincrby", 4, &[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .doc(
+            "hash",
+            "Increments the integer value of a field in a hash by a number. Uses 0 as initial value if the field \
+         doesn't exist.",
+        ),
+    CommandSpec::new("hincrbyfloat", 4, &[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .doc(
+            "hash",
+            "Increments the floating point value of a field by a number. Uses 0 as initial value if the field doesn't \
+         exist.",
+        ),
+    CommandSpec::new("hscan", -3, &[ReadOnly])
+        .keys(1, 1, 1)
+        .doc("hash", "Iterates over fields and values of a hash."),
+    CommandSpec::new("hrandfield", -2, &[ReadOnly])
+        .keys(1, 1, 1)
+        .doc("hash", "Returns one or more random fields from a hash."),
+    CommandSpec::new("hexpire", -6, &[Write, Fast]).keys(1, 1, 1).doc(
+        "hash",
+        "Sets the expiry of hash fields using a relative time in seconds.",
+    ),
+    CommandSpec::new("hpexpire", -6, &[Write, Fast]).keys(1, 1, 1).doc(
+        "hash",
+        "Sets the expiry of hash fields using a relative time in milliseconds.",
+    ),
+    CommandSpec::new("hexpireat", -6, &[Write, Fast]).keys(1, 1, 1).doc(
+        "hash",
+        "Sets the expiry of hash fields using an absolute Unix timestamp in seconds.",
+    ),
+    CommandSpec::new("hpexpireat", -6, &[Write, Fast]).keys(1, 1, 1).doc(
+        "hash",
+        "Sets the expiry of hash fields using an absolute Unix timestamp in milliseconds.",
+    ),
+    CommandSpec::new("httl", -5, &[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .doc("hash", "Returns the TTL in seconds of a hash field."),
+    CommandSpec::new("hpttl", -5, &[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .doc("hash", "Returns the TTL in milliseconds of a hash field."),
+    CommandSpec::new("hexpiretime", -5, &[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .doc(
+            "hash",
+            "Returns the expiration time of a hash field as a Unix timestamp, in seconds.",
+        ),
+    CommandSpec::new("hpexpiretime", -5, &[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .doc(
+            "hash",
+            "Returns the expiration time of a hash field as a Unix timestamp, in msec.",
+        ),
+    CommandSpec::new("hpersist", -5, &[Write, Fast])
+        .keys(1, 1, 1)
+        .doc("hash", "Removes the expiration time of hash fields."),
+    CommandSpec::new("hgetex", -5, &[Write, Fast]).keys(1, 1, 1).doc(
+        "hash",
+        "Returns the values of hash fields and optionally sets their expiration time.",
+    ),
+    CommandSpec::new("sadd", -3, &[Write, DenyOom, Fast]).keys(1, 1, 1).doc(
+        "set",
+        "Adds one or more members to a set. Creates the key if it doesn't exist.",
+    ),
+    CommandSpec::new("srem", -3, &[Write, Fast]).keys(1, 1, 1).doc(
+        "set",
+        "Removes one or more members from a set. Deletes the set if the last member was removed.",
+    ),
+    CommandSpec::new("smembers", 2, &[ReadOnly])
+        .keys(1, 1, 1)
+        .doc("set", "Returns all members of a set."),
+    CommandSpec::new("sismember", 3, &[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .doc("set", "Determines whether a member belongs to a set."),
+    CommandSpec::new("smismember", -3, &[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .doc("set", "Determines whether multiple members belong to a set."),
+    CommandSpec::new("scard", 2, &[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .doc("set", "Returns the number of members in a set."),
+    CommandSpec::new("spop", -2, &[Write, Fast]).keys(1, 1, 1).doc(
+        "set",
+        "Returns one or more random members from a set after removing them. Deletes the set if the last member was \
+         popped.",
+    ),
+    CommandSpec::new("srandmember", -2, &[ReadOnly])
+        .keys(1, 1, 1)
+        .doc("set", "Get one or multiple random members from a set."),
+    CommandSpec::new("sinter", -2, &[ReadOnly])
+        .keys(1, -1, 1)
+        .doc("set", "Returns the intersect of multiple sets."),
+    CommandSpec::new("sunion", -2, &[ReadOnly])
+        .keys(1, -1, 1)
+        .doc("set", "Returns the union of multiple sets."),
+    CommandSpec::new("sdiff", -2, &[ReadOnly])
+        .keys(1, -1, 1)
+        .doc("set", "Returns the difference of multiple sets."),
+    CommandSpec::new("sinterstore", -3, &[Write, DenyOom])
+        .keys(1, -1, 1)
+        .doc("set", "Stores the intersect of multiple sets in a key."),
+    CommandSpec::new("sunionstore", -3, &[Write, DenyOom])
+        .keys(1, -1, 1)
+        .doc("set", "Stores the union of multiple sets in a key."),
+    CommandSpec::new("sdiffstore", -3, &[Write, DenyOom])
+        .keys(1, -1, 1)
+        .doc("set", "Stores the difference of multiple sets in a key."),
+    CommandSpec::new("sintercard", -3, &[ReadOnly])
+        .movable_keys(numkeys_first)
+        .doc(
+            "set",
+            "Returns the number of members of the intersect of multiple sets.",
+        ),
+    CommandSpec::new("smove", 4, &[Write, Fast])
+        .keys(1, 2, 1)
+        .doc("set", "Moves a member from one set to another."),
+    CommandSpec::new("sscan", -3, &[ReadOnly])
+        .keys(1, 1, 1)
+        .doc("set", "Iterates over members of a set."),
+    CommandSpec::new("zadd", -4, &[Write, DenyOom, Fast]).keys(1, 1, 1).doc("sorted-set", "Adds one or more members to a sorted set, or updates their scores. Creates the key if it doesn't exist."),
+    CommandSpec::new("zincrby", 4, &[Write, DenyOom, Fast]).keys(1, 1, 1).doc("sorted-set", "Increments the score of a member in a sorted set."),
+    CommandSpec::new("zrem", -3, &[Write, Fast]).keys(1, 1, 1).doc("sorted-set", "Removes one or more members from a sorted set. Deletes the sorted set if all members were removed."),
+    CommandSpec::new("zcard", 2, &[ReadOnly, Fast]).keys(1, 1, 1).doc("sorted-set", "Returns the number of members in a sorted set."),
+    CommandSpec::new("zscore", 3, &[ReadOnly, Fast]).keys(1, 1, 1).doc("sorted-set", "Returns the score of a member in a sorted set."),
+    CommandSpec::new("zmscore", -3, &[ReadOnly, Fast]).keys(1, 1, 1).doc("sorted-set", "Returns the score of one or more members in a sorted set."),
+    CommandSpec::new("zrank", -3, &[ReadOnly, Fast]).keys(1, 1, 1).doc("sorted-set", "Returns the index of a member in a sorted set ordered by ascending scores."),
+    CommandSpec::new("zrevrank", -3, &[ReadOnly, Fast]).keys(1, 1, 1).doc("sorted-set", "Returns the index of a member in a sorted set ordered by descending scores."),
+    CommandSpec::new("zcount", 4, &[ReadOnly, Fast]).keys(1, 1, 1).doc("sorted-set", "Returns the count of members in a sorted set that have scores within a range."),
+    CommandSpec::new("zlexcount", 4, &[ReadOnly, Fast]).keys(1, 1, 1).doc("sorted-set", "Returns the number of members in a sorted set within a lexicographical range."),
+    CommandSpec::new("zrange", -4, &[ReadOnly]).keys(1, 1, 1).doc("sorted-set", "Returns members in a sorted set within a range of indexes."),
+    CommandSpec::new("zrangestore", -5, &[Write, DenyOom]).keys(1, 2, 1).doc("sorted-set", "Stores a range of members from sorted set in a key."),
+    CommandSpec::new("zrevrange", -4, &[ReadOnly]).keys(1, 1, 1).doc("sorted-set", "Returns members in a sorted set within a range of indexes in reverse order."),
+    CommandSpec::new("zrangebyscore", -4, &[ReadOnly]).keys(1, 1, 1).doc("sorted-set", "Returns members in a sorted set within a range of scores."),
+    CommandSpec::new("zrevrangebyscore", -4, &[ReadOnly]).keys(1, 1, 1).doc("sorted-set", "Returns members in a sorted set within a range of scores in reverse order."),
+    CommandSpec::new("zrangebylex", -4, &[ReadOnly]).keys(1, 1, 1).doc("sorted-set", "Returns members in a sorted set within a lexicographical range."),
+    CommandSpec::new("zrevrangebylex", -4, &[ReadOnly]).keys(1, 1, 1).doc("sorted-set", "Returns members in a sorted set within a lexicographical range in reverse order."),
+    CommandSpec::new("zpopmin", -2, &[Write, Fast]).keys(1, 1, 1).doc("sorted-set", "Returns the lowest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped."),
+    CommandSpec::new("zpopmax", -2, &[Write, Fast]).keys(1, 1, 1).doc("sorted-set", "Returns the highest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped."),
+    CommandSpec::new("zrandmember", -2, &[ReadOnly]).keys(1, 1, 1).doc("sorted-set", "Returns one or more random members from a sorted set."),
+    CommandSpec::new("zscan", -3, &[ReadOnly]).keys(1, 1, 1).doc("sorted-set", "Iterates over members and scores of a sorted set."),
+    CommandSpec::new("zunion", -3, &[ReadOnly]).movable_keys(numkeys_first).doc("sorted-set", "Returns the union of multiple sorted sets."),
+    CommandSpec::new("zinter", -3, &[ReadOnly]).movable_keys(numkeys_first).doc("sorted-set", "Returns the intersect of multiple sorted sets."),
+    CommandSpec::new("zdiff", -3, &[ReadOnly]).movable_keys(numkeys_first).doc("sorted-set", "Returns the difference between multiple sorted sets."),
+    CommandSpec::new("zunionstore", -4, &[Write, DenyOom]).movable_keys(destination_and_numkeys).doc("sorted-set", "Stores the union of multiple sorted sets in a key."),
+    CommandSpec::new("zinterstore", -4, &[Write, DenyOom]).movable_keys(destination_and_numkeys).doc("sorted-set", "Stores the intersect of multiple sorted sets in a key."),
+    CommandSpec::new("zdiffstore", -4, &[Write, DenyOom]).movable_keys(destination_and_numkeys).doc("sorted-set", "Stores the difference of multiple sorted sets in a key."),
+    CommandSpec::new("zintercard", -3, &[ReadOnly]).movable_keys(numkeys_first).doc("sorted-set", "Returns the number of members of the intersect of multiple sorted sets."),
+    CommandSpec::new("zmpop", -4, &[Write]).movable_keys(numkeys_first).doc(
+        "sorted-set",
+        "Returns the highest- or lowest-scoring members from one or more sorted sets after removing them. Deletes \
+         the sorted set if the last member was popped.",
+    ),
+    CommandSpec::new("bzpopmin", -3, &[Write, Fast, Blocking]).keys(1, -2, 1).doc(
+        "sorted-set",
+        "Removes and returns the member with the lowest score from one or more sorted sets. Blocks until a member \
+         is available otherwise. Deletes the sorted set if the last element was popped.",
+    ),
+    CommandSpec::new("bzpopmax", -3, &[Write, Fast, Blocking]).keys(1, -2, 1).doc(
+        "sorted-set",
+        "Removes and returns the member with the highest score from one or more sorted sets. Blocks until a member \
+         is available otherwise. Deletes the sorted set if the last element was popped.",
+    ),
+    CommandSpec::new("bzmpop", -5, &[Write, Blocking]).movable_keys(numkeys_second).doc(
+        "sorted-set",
+        "Removes and returns a member by score from one or more sorted sets. Blocks until a member is available \
+         otherwise. Deletes the sorted set if the last element was popped.",
+    ),
+    CommandSpec::new("object", -2, &[ReadOnly])
+        .keys(2, 2, 1)
+        .doc("generic", "A container for object introspection commands."),
+    CommandSpec::new("save", 1, &[Admin, NoScript]).doc("server", "Synchronously saves the database(s) to disk."),
+];
+
+pub fn lookup_command(name: &str) -> Option<&'static CommandSpec> {
+    COMMAND_TABLE.iter().find(|spec| spec.name.eq_ignore_ascii_case(name))
+}
+
+fn xread_keys(argv: &[Bytes]) -> Vec<usize> {
+    let Some(streams) = argv.iter().position(|arg| arg.eq_ignore_ascii_case(b"streams")) else {
+        return Vec::new();
+    };
+    let count = (argv.len() - streams - 1) / 2;
+    (streams + 1..streams + 1 + count).collect()
+}
+
+fn numkeys_first(argv: &[Bytes]) -> Vec<usize> {
+    numkeys_keys(argv, 1)
+}
+
+fn numkeys_second(argv: &[Bytes]) -> Vec<usize> {
+    numkeys_keys(argv, 2)
+}
+
+/// Key positions for commands that write to the key in their first argument and take `numkeys` source keys after it.
+fn destination_and_numkeys(argv: &[Bytes]) -> Vec<usize> {
+    let mut keys = vec![1];
+    keys.extend(numkeys_keys(argv, 2));
+    keys
+}
+
+/// Key positions for commands that take a `numkeys` argument at `index` followed by that many keys.
+fn numkeys_keys(argv: &[Bytes], index: usize) -> Vec<usize> {
+    let numkeys = argv
+        .get(index)
+        .and_then(|arg| std::str::from_utf8(arg).ok())
+        .and_then(|arg| arg.parse::<usize>().ok())
+        .unwrap_or(0);
+    // `numkeys` comes straight from the client and is only validated by the command itself, after key lookup.
+    let first = index + 1;
+    (first..first.saturating_add(numkeys).min(argv.len())).collect()
+}
@@ -1,22 +1,30 @@
-use crate::message::Message;
+use crate::message::{Message, Protocol, RequestDecoder};
 use anyhow::Result;
-use bytes::{Buf, BytesMut};
+use bytes::BytesMut;
 use std::collections::VecDeque;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::TcpStream,
 };
 
+const READ_CHUNK_SIZE: usize = 4096;
+
 pub struct Connection {
     pub stream: TcpStream,
+    pub buffer: BytesMut,
     pub cache: VecDeque<Message>,
+    decoder: RequestDecoder,
+    protocol_error: Option<String>,
 }
 
 impl Connection {
     pub fn bind(stream: TcpStream) -> Self {
         Self {
             stream,
+            buffer: BytesMut::with_capacity(READ_CHUNK_SIZE),
             cache: VecDeque::new(),
+            decoder: RequestDecoder::default(),
+            protocol_error: None,
         }
     }
 
@@ -28,27 +36,74 @@ impl Connection {
     }
 
     pub async fn write_message(&mut self, message: Message) -> Result<()> {
-        self.write_bytes(message.encode().as_bytes()).await
+        self.write_bytes(&message.encode()).await
+    }
+
+    pub async fn write_reply(&mut self, message: Message, protocol: Protocol) -> Result<()> {
+        self.write_bytes(&message.encode_for(protocol)).await
     }
 
+    /// Returns the next complete message, reading from the socket as often as needed. `None` means the peer
+    /// closed the connection or sent data that violates the protocol, in which case an error reply was sent.
     pub async fn read_message(&mut self) -> Option<Message> {
-        if self.cache.is_empty() {
-            self.read_stream().await;
+        loop {
+            if let Some(message) = self.cache.pop_front() {
+                return Some(message);
+            }
+            if let Some(err) = self.protocol_error.take() {
+                _ = self
+                    .write_message(Message::Error(format!("ERR Protocol error: {}", err)))
+                    .await;
+                return None;
+            }
+            match self.read_stream().await {
+                Ok(true) => continue,
+                Ok(false) | Err(_) => return None,
+            }
+        }
+    }
+
+    /// Resolves once the peer closes the connection. Data received in the meantime stays buffered for
+    /// `read_message`, so this can run alongside a command that is still executing.
+    pub async fn closed(&mut self) {
+        loop {
+            self.buffer.reserve(READ_CHUNK_SIZE);
+            match self.stream.read_buf(&mut self.buffer).await {
+                Ok(0) | Err(_) => return,
+                Ok(_) => continue,
+            }
+        }
+    }
+
+    async fn read_stream(&mut self) -> Result<bool> {
+        self.decode_buffer();
+        if !self.cache.is_empty() || self.protocol_error.is_some() {
+            return Ok(true);
+        }
+        self.buffer.reserve(READ_CHUNK_SIZE);
+        if self.stream.read_buf(&mut self.buffer).await? == 0 {
+            return Ok(false);
         }
-        self.cache.pop_front()
-    }
-
-    async fn read_stream(&mut self) {
-        let mut buffer = BytesMut::with_capacity(512);
-        if let Ok(length) = self.stream.read_buf(&mut buffer).await {
-            let mut index = 0;
-            while index < length {
-                if let Ok((message, offset)) = Message::decode(buffer.clone()) {
-                    self.cache.push_back(message);
-                    buffer.advance(offset);
-                    index += offset;
-                } else {
-                    println!("Invalid data structure");
+        self.decode_buffer();
+        Ok(true)
+    }
+
+    fn decode_buffer(&mut self) {
+        if self.protocol_error.is_some() {
+            return;
+        }
+        loop {
+            match self.decoder.decode(&mut self.buffer) {
+                Ok(Some(message)) => {
+                    // Blank inline lines and empty arrays are ignored, as Redis does.
+                    if !matches!(&message, Message::Array(items) if items.is_empty()) {
+                        self.cache.push_back(message);
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    // Commands decoded ahead of the bad frame are still served before the error is reported.
+                    self.protocol_error = Some(err.to_string());
                     break;
                 }
             }
@@ -0,0 +1,128 @@
+//! The active expire cycle. Keys with an expiry are also deleted when a command touches them, but keys nobody touches
+//! again would stay in memory forever, so a background task walks the expiry times like Redis does: a few times a
+//! second it checks batches of keys and reclaims the expired ones, going on while batches are mostly expired and
+//! stopping once its share of time is spent. Hash fields with an expiry time get the same treatment, walking the
+//! hashes that have some.
+
+use crate::{
+    registry::propagate_to_replicas,
+    store::Store,
+    stream::{StreamInfo, StreamType},
+};
+use bytes::Bytes;
+use std::{
+    ops::Bound,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+/// Cycles per second, Redis' default `hz`.
+const HZ: u64 = 10;
+/// Keys checked per batch, Redis' `ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP`.
+const KEYS_PER_LOOP: usize = 20;
+/// Percentage of expired keys in a batch below which the cycle stops, as with Redis' default `active-expire-effort`.
+const ACCEPTABLE_STALE: usize = 10;
+/// Percentage of each period a cycle may take, Redis' `ACTIVE_EXPIRE_CYCLE_SLOW_TIME_PERC`.
+const TIME_PERCENT: u32 = 25;
+
+/// Runs the active expire cycle for as long as the server runs. Replicas do not run it: they receive the deletions of
+/// their master instead.
+pub async fn run(store: Arc<Mutex<Store>>, stream_info: Arc<StreamInfo>) {
+    if stream_info.role != StreamType::Master {
+        return;
+    }
+    let period = Duration::from_millis(1000 / HZ);
+    let budget = period * TIME_PERCENT / 100;
+    let mut interval = tokio::time::interval(period);
+    let mut cursors = Cursors::default();
+    loop {
+        interval.tick().await;
+        let deletions = {
+            let mut store = store.lock().await;
+            cycle(&mut store, &mut cursors, budget);
+            store.take_deletions()
+        };
+        propagate_to_replicas(&stream_info, deletions).await;
+    }
+}
+
+/// Where the walks of the previous cycle stopped: the last key checked among the keys with an expiry time, and among
+/// the hashes with expiring fields.
+#[derive(Debug, Default)]
+struct Cursors {
+    keys: Option<Bytes>,
+    hashes: Option<Bytes>,
+}
+
+/// Reclaims expired keys, then expired hash fields, for up to `budget` in all. Returns the number of keys and hashes
+/// something was reclaimed from.
+fn cycle(store: &mut Store, cursors: &mut Cursors, budget: Duration) -> usize {
+    let deadline = Instant::now() + budget;
+    let keys = walk(
+        store,
+        &mut cursors.keys,
+        deadline,
+        expiring_keys,
+        Store::expire_if_needed,
+    );
+    keys + walk(
+        store,
+        &mut cursors.hashes,
+        deadline,
+        expiring_hashes,
+        Store::reclaim_expired_fields,
+    )
+}
+
+/// The keys with an expiry time that come after `from`, up to a batch of them.
+fn expiring_keys(store: &Store, from: Bound<&[u8]>) -> Vec<Bytes> {
+    store
+        .expires
+        .range::<[u8], _>((from, Bound::Unbounded))
+        .take(KEYS_PER_LOOP)
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+/// The hashes with expiring fields that come after `from`, up to a batch of them.
+fn expiring_hashes(store: &Store, from: Bound<&[u8]>) -> Vec<Bytes> {
+    store
+        .expiring_hashes
+        .range::<[u8], _>((from, Bound::Unbounded))
+        .take(KEYS_PER_LOOP)
+        .cloned()
+        .collect()
+}
+
+/// Walks the keys `batch` lists in batches after `cursor`, the last key the previous walk checked, calling `reclaim`
+/// on each until `deadline` or until a batch is mostly left alone. Returns the number of keys `reclaim` acted on.
+fn walk(
+    store: &mut Store,
+    cursor: &mut Option<Bytes>,
+    deadline: Instant,
+    batch: fn(&Store, Bound<&[u8]>) -> Vec<Bytes>,
+    reclaim: fn(&mut Store, &[u8]) -> bool,
+) -> usize {
+    let mut reclaimed = 0;
+    loop {
+        let from = match cursor {
+            Some(key) => Bound::Excluded(&key[..]),
+            None => Bound::Unbounded,
+        };
+        let keys = batch(store, from);
+        let checked = keys.len();
+
+        // A short batch means the walk reached the end and starts over.
+        *cursor = match checked < KEYS_PER_LOOP {
+            true => None,
+            false => keys.last().cloned(),
+        };
+        let expired = keys.iter().filter(|key| reclaim(store, key)).count();
+        reclaimed += expired;
+
+        if checked == 0 || expired * 100 <= checked * ACCEPTABLE_STALE || Instant::now() >= deadline {
+            return reclaimed;
+        }
+    }
+}
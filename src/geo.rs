@@ -0,0 +1,358 @@
+//! Geospatial indexing on top of sorted sets. Like in Redis, each location is stored as a member whose score is a
+//! 52-bit geohash: 26 bits of latitude and 26 bits of longitude, interleaved with latitude in the even bits. Searches
+//! cover the area around a point with the geohash box of the point and its eight neighbours, read each box as a score
+//! range, and filter the candidates by their exact distance.
+
+use crate::sorted_set::SortedSet;
+use bytes::Bytes;
+use std::ops::Bound;
+
+pub const LONGITUDE_MIN: f64 = -180.0;
+pub const LONGITUDE_MAX: f64 = 180.0;
+/// Latitudes are limited to those of the square Web Mercator projection.
+pub const LATITUDE_MIN: f64 = -85.05112878;
+pub const LATITUDE_MAX: f64 = 85.05112878;
+
+/// Steps of precision of stored geohashes, each adding one bit of latitude and one of longitude.
+const STEPS: u8 = 26;
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+const MERCATOR_MAX: f64 = 20037726.37;
+const ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Range {
+    min: f64,
+    max: f64,
+}
+
+const LONGITUDE_RANGE: Range = Range {
+    min: LONGITUDE_MIN,
+    max: LONGITUDE_MAX,
+};
+const LATITUDE_RANGE: Range = Range {
+    min: LATITUDE_MIN,
+    max: LATITUDE_MAX,
+};
+
+/// A geohash of `step` bits per coordinate. A zero step marks a box left out of a search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GeoHash {
+    bits: u64,
+    step: u8,
+}
+
+/// The area a geohash covers.
+#[derive(Debug, Clone, Copy)]
+struct Area {
+    longitude: Range,
+    latitude: Range,
+}
+
+/// Whether a longitude, latitude pair can be indexed.
+pub fn is_valid(longitude: f64, latitude: f64) -> bool {
+    (LONGITUDE_MIN..=LONGITUDE_MAX).contains(&longitude) && (LATITUDE_MIN..=LATITUDE_MAX).contains(&latitude)
+}
+
+/// The score storing a location, which must be valid.
+pub fn encode(longitude: f64, latitude: f64) -> f64 {
+    hash(LONGITUDE_RANGE, LATITUDE_RANGE, longitude, latitude, STEPS).bits as f64
+}
+
+/// The longitude and latitude at the centre of the area a stored score covers.
+pub fn decode(score: f64) -> (f64, f64) {
+    let area = area(
+        LONGITUDE_RANGE,
+        LATITUDE_RANGE,
+        GeoHash {
+            bits: score as u64,
+            step: STEPS,
+        },
+    );
+    let longitude = ((area.longitude.min + area.longitude.max) / 2.0).clamp(LONGITUDE_MIN, LONGITUDE_MAX);
+    let latitude = ((area.latitude.min + area.latitude.max) / 2.0).clamp(LATITUDE_MIN, LATITUDE_MAX);
+    (longitude, latitude)
+}
+
+/// The standard 11 character geohash of a stored score. Standard geohashes span latitudes from -90 to 90 degrees,
+/// so the location is encoded again against that range.
+pub fn geohash_string(score: f64) -> String {
+    let (longitude, latitude) = decode(score);
+    let standard_latitude = Range { min: -90.0, max: 90.0 };
+    let bits = hash(LONGITUDE_RANGE, standard_latitude, longitude, latitude, STEPS).bits;
+    (0..11)
+        .map(|i| {
+            // 52 bits make 10 characters and a bit, and the last character always reads as zero, like in Redis.
+            let index = if i == 10 {
+                0
+            } else {
+                (bits >> (52 - (i + 1) * 5)) & 0x1f
+            };
+            ALPHABET[index as usize] as char
+        })
+        .collect()
+}
+
+/// Great-circle distance in meters between two points, with the haversine formula.
+pub fn distance(longitude1: f64, latitude1: f64, longitude2: f64, latitude2: f64) -> f64 {
+    let (lat1, lat2) = (latitude1.to_radians(), latitude2.to_radians());
+    let v = ((longitude2.to_radians() - longitude1.to_radians()) / 2.0).sin();
+    // Points on the same meridian only differ in latitude.
+    if v == 0.0 {
+        return latitude_distance(latitude1, latitude2);
+    }
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let a = u * u + lat1.cos() * lat2.cos() * v * v;
+    2.0 * EARTH_RADIUS_IN_METERS * a.sqrt().asin()
+}
+
+fn latitude_distance(latitude1: f64, latitude2: f64) -> f64 {
+    EARTH_RADIUS_IN_METERS * (latitude2.to_radians() - latitude1.to_radians()).abs()
+}
+
+/// Meters in one `unit` of the geo commands, which are `m`, `km`, `ft` and `mi`.
+pub fn unit_to_meters(unit: &str) -> Option<f64> {
+    match unit.to_lowercase().as_str() {
+        "m" => Some(1.0),
+        "km" => Some(1000.0),
+        "ft" => Some(0.3048),
+        "mi" => Some(1609.34),
+        _ => None,
+    }
+}
+
+/// The area around a point a search covers, with its sizes in meters.
+#[derive(Debug, Clone, Copy)]
+pub enum Shape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+/// A member found by a search.
+#[derive(Debug, Clone)]
+pub struct Found {
+    pub member: Bytes,
+    pub score: f64,
+    pub longitude: f64,
+    pub latitude: f64,
+    /// Distance in meters from the centre of the search.
+    pub distance: f64,
+}
+
+/// Members of `zset` within `shape` around the point, in the order the geohash boxes are read. Stops once `limit`
+/// members are found, when there is one.
+pub fn search(zset: &SortedSet, longitude: f64, latitude: f64, shape: Shape, limit: Option<usize>) -> Vec<Found> {
+    let boxes = search_boxes(longitude, latitude, shape);
+    let mut found = Vec::new();
+    let mut last_processed = 0;
+    for (i, geohash) in boxes.iter().enumerate() {
+        if geohash.step == 0 {
+            continue;
+        }
+        // Huge areas make neighbours repeat, which would find the same members twice. Like in Redis, the first box
+        // is not compared against.
+        if last_processed != 0 && *geohash == boxes[last_processed] {
+            continue;
+        }
+        if limit.is_some_and(|limit| !found.is_empty() && found.len() >= limit) {
+            break;
+        }
+        let shift = 52 - geohash.step as u32 * 2;
+        let min = (geohash.bits << shift) as f64;
+        let max = ((geohash.bits + 1) << shift) as f64;
+        for (member, score) in zset.range_by_score(Bound::Included(min), Bound::Excluded(max)) {
+            let (x, y) = decode(score);
+            let Some(distance) = within(longitude, latitude, shape, x, y) else {
+                continue;
+            };
+            found.push(Found {
+                member,
+                score,
+                longitude: x,
+                latitude: y,
+                distance,
+            });
+            if limit.is_some_and(|limit| found.len() >= limit) {
+                break;
+            }
+        }
+        last_processed = i;
+    }
+    found
+}
+
+/// The distance of the point `x`, `y` from the centre when it lies within `shape`.
+fn within(longitude: f64, latitude: f64, shape: Shape, x: f64, y: f64) -> Option<f64> {
+    match shape {
+        Shape::Radius(radius) => Some(distance(longitude, latitude, x, y)).filter(|&distance| distance <= radius),
+        Shape::Box { width, height } => {
+            // The latitude distance is the cheaper one, so it is checked first.
+            if latitude_distance(y, latitude) > height / 2.0 || distance(x, y, longitude, y) > width / 2.0 {
+                return None;
+            }
+            Some(distance(longitude, latitude, x, y))
+        }
+    }
+}
+
+/// The geohash boxes covering `shape` around the point: the box of the point first, then north, south, east, west,
+/// north-east, north-west, south-east and south-west. Boxes that cannot hold results have a zero step.
+fn search_boxes(longitude: f64, latitude: f64, shape: Shape) -> [GeoHash; 9] {
+    let (half_width, half_height, radius) = match shape {
+        Shape::Radius(radius) => (radius, radius, radius),
+        Shape::Box { width, height } => (width / 2.0, height / 2.0, (width / 2.0).hypot(height / 2.0)),
+    };
+
+    // The bounding box of the shape.
+    let latitude_delta = (half_height / EARTH_RADIUS_IN_METERS).to_degrees();
+    let longitude_delta =
+        |latitude: f64| (half_width / EARTH_RADIUS_IN_METERS / latitude.to_radians().cos()).to_degrees();
+    // Meridians converge towards the poles, so the edge nearest the pole spans the most degrees of longitude.
+    let widest = if latitude < 0.0 {
+        longitude_delta(latitude - latitude_delta)
+    } else {
+        longitude_delta(latitude + latitude_delta)
+    };
+    let (min_longitude, max_longitude) = (longitude - widest, longitude + widest);
+    let (min_latitude, max_latitude) = (latitude - latitude_delta, latitude + latitude_delta);
+
+    let mut steps = estimate_steps(radius, latitude);
+    let mut center = hash(LONGITUDE_RANGE, LATITUDE_RANGE, longitude, latitude, steps);
+    let mut boxes = neighbours(center);
+
+    // Near the edges of the centre box a neighbour may not reach far enough, in which case the boxes are doubled.
+    let [_, north, south, east, west, ..] = boxes.map(|geohash| area(LONGITUDE_RANGE, LATITUDE_RANGE, geohash));
+    let too_small = north.latitude.max < max_latitude
+        || south.latitude.min > min_latitude
+        || east.longitude.max < max_longitude
+        || west.longitude.min > min_longitude;
+    if steps > 1 && too_small {
+        steps -= 1;
+        center = hash(LONGITUDE_RANGE, LATITUDE_RANGE, longitude, latitude, steps);
+        boxes = neighbours(center);
+    }
+
+    // Neighbours on a side the shape does not reach past the centre box are left out.
+    if steps >= 2 {
+        let area = area(LONGITUDE_RANGE, LATITUDE_RANGE, center);
+        let mut exclude = |indexes: [usize; 3]| indexes.iter().for_each(|&i| boxes[i] = GeoHash { bits: 0, step: 0 });
+        if area.latitude.min < min_latitude {
+            exclude([2, 7, 8]);
+        }
+        if area.latitude.max > max_latitude {
+            exclude([1, 5, 6]);
+        }
+        if area.longitude.min < min_longitude {
+            exclude([4, 8, 6]);
+        }
+        if area.longitude.max > max_longitude {
+            exclude([3, 7, 5]);
+        }
+    }
+    boxes
+}
+
+/// The number of steps whose boxes are about as large as `radius` at `latitude`.
+fn estimate_steps(radius: f64, latitude: f64) -> u8 {
+    if radius == 0.0 {
+        return STEPS;
+    }
+    let mut range = radius;
+    let mut steps: i32 = 1;
+    while range < MERCATOR_MAX {
+        range *= 2.0;
+        steps += 1;
+    }
+    // Makes sure the radius is included in most cases.
+    steps -= 2;
+    // Boxes get narrower towards the poles.
+    if !(-66.0..=66.0).contains(&latitude) {
+        steps -= 1;
+        if !(-80.0..=80.0).contains(&latitude) {
+            steps -= 1;
+        }
+    }
+    steps.clamp(1, STEPS as i32) as u8
+}
+
+fn hash(longitude_range: Range, latitude_range: Range, longitude: f64, latitude: f64, step: u8) -> GeoHash {
+    let scale = (1u64 << step) as f64;
+    let latitude_offset = (latitude - latitude_range.min) / (latitude_range.max - latitude_range.min) * scale;
+    let longitude_offset = (longitude - longitude_range.min) / (longitude_range.max - longitude_range.min) * scale;
+    GeoHash {
+        bits: interleave(latitude_offset as u32, longitude_offset as u32),
+        step,
+    }
+}
+
+fn area(longitude_range: Range, latitude_range: Range, geohash: GeoHash) -> Area {
+    let (latitude, longitude) = deinterleave(geohash.bits);
+    let scale = (1u64 << geohash.step) as f64;
+    let part = |range: Range, index: u32| Range {
+        min: range.min + (index as f64 / scale) * (range.max - range.min),
+        max: range.min + ((index as f64 + 1.0) / scale) * (range.max - range.min),
+    };
+    Area {
+        longitude: part(longitude_range, longitude),
+        latitude: part(latitude_range, latitude),
+    }
+}
+
+/// Interleaves the bits of `even` and `odd`, with those of `even` in the even positions.
+fn interleave(even: u32, odd: u32) -> u64 {
+    let spread = |value: u32| (0..32).fold(0u64, |bits, i| bits | (((value as u64) >> i) & 1) << (2 * i));
+    spread(even) | spread(odd) << 1
+}
+
+/// Splits interleaved bits back into their even and odd halves.
+fn deinterleave(bits: u64) -> (u32, u32) {
+    let gather = |bits: u64| (0..32).fold(0u32, |value, i| value | (((bits >> (2 * i)) & 1) as u32) << i);
+    (gather(bits), gather(bits >> 1))
+}
+
+/// The geohash and its eight neighbours, in the order of [`search_boxes`].
+fn neighbours(geohash: GeoHash) -> [GeoHash; 9] {
+    let shifted = |east: i8, north: i8| move_latitude(move_longitude(geohash, east), north);
+    [
+        geohash,
+        shifted(0, 1),
+        shifted(0, -1),
+        shifted(1, 0),
+        shifted(-1, 0),
+        shifted(1, 1),
+        shifted(-1, 1),
+        shifted(1, -1),
+        shifted(-1, -1),
+    ]
+}
+
+/// Moves one box east or west, wrapping around at the antimeridian. Longitude bits are the odd ones.
+fn move_longitude(geohash: GeoHash, direction: i8) -> GeoHash {
+    move_bits(geohash, direction, 0xaaaaaaaaaaaaaaaa)
+}
+
+/// Moves one box north or south. Latitude bits are the even ones.
+fn move_latitude(geohash: GeoHash, direction: i8) -> GeoHash {
+    move_bits(geohash, direction, 0x5555555555555555)
+}
+
+/// Adds `direction` to the coordinate held in the bits of `mask`, carrying through the bits of the other coordinate.
+fn move_bits(geohash: GeoHash, direction: i8, mask: u64) -> GeoHash {
+    if direction == 0 {
+        return geohash;
+    }
+    let shift = 64 - geohash.step as u32 * 2;
+    let mut moving = geohash.bits & mask;
+    let other = geohash.bits & !mask;
+    // Filling the other coordinate's bits with ones makes additions carry straight across them.
+    let filler = !mask >> shift;
+    if direction > 0 {
+        moving = moving.wrapping_add(filler + 1);
+    } else {
+        moving = (moving | filler).wrapping_sub(filler + 1);
+    }
+    moving &= mask >> shift;
+    GeoHash {
+        bits: moving | other,
+        step: geohash.step,
+    }
+}
@@ -0,0 +1,344 @@
+use crate::{
+    bitmap::{self, BitOp, FieldType, Overflow, MAX_BIT_OFFSET},
+    command::{CommandError, CommandInfo},
+    message::Message,
+    registry::{Context, HandlerFuture, Registry},
+    store::Entry,
+    utils::clamp_range,
+};
+use bytes::Bytes;
+
+pub fn register(registry: &mut Registry) {
+    registry.register_fn("setbit", process_setbit);
+    registry.register_fn("getbit", process_getbit);
+    registry.register_fn("bitcount", process_bitcount);
+    registry.register_fn("bitpos", process_bitpos);
+    registry.register_fn("bitop", process_bitop);
+    registry.register_fn("bitfield", process_bitfield);
+    registry.register_fn("bitfield_ro", process_bitfield_ro);
+}
+
+fn offset_error() -> CommandError {
+    CommandError::Other("ERR bit offset is not an integer or out of range".to_string())
+}
+
+/// Parses the bit offset at `index`, which must leave room for `bits` bits before the maximum string length. With
+/// `multiply` set, an offset written `#N` stands for the `N`th field of `bits` bits.
+fn parse_offset(cmd: &CommandInfo, index: usize, bits: u32, multiply: bool) -> Result<u64, CommandError> {
+    let arg = cmd.arg_string(index);
+    let (arg, factor) = match arg.strip_prefix('#') {
+        Some(rest) if multiply => (rest, bits as u64),
+        _ => (arg.as_str(), 1),
+    };
+    let offset = arg
+        .parse::<u64>()
+        .ok()
+        .and_then(|offset| offset.checked_mul(factor))
+        .ok_or_else(offset_error)?;
+    if offset.saturating_add(bits as u64 - 1) > MAX_BIT_OFFSET {
+        return Err(offset_error());
+    }
+    Ok(offset)
+}
+
+/// Parses a `0` or `1` bit argument, which `error` describes when it is neither.
+fn parse_bit(cmd: &CommandInfo, index: usize, error: &str) -> Result<bool, CommandError> {
+    match cmd.parse_arg::<i64>(index)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(CommandError::Other(error.to_string())),
+    }
+}
+
+fn process_setbit(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let offset = parse_offset(&cmd, 1, 1, false)?;
+        let on = match cmd.parse_arg::<i64>(2) {
+            Ok(0) => false,
+            Ok(1) => true,
+            _ => return Err(CommandError::Other("ERR bit is not an integer or out of range".to_string()).into()),
+        };
+        let mut store = ctx.store.lock().await;
+        let entry = store.get_or_create_string(cmd.args[0].clone())?;
+        let previous = entry.value.edit(|bytes| bitmap::set_bit(bytes, offset, on));
+        Ok(Message::Int(previous as isize))
+    })
+}
+
+fn process_getbit(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let offset = parse_offset(&cmd, 1, 1, false)?;
+        let store = ctx.store.lock().await;
+        let bit = store
+            .get_string(&cmd.args[0])?
+            .is_some_and(|entry| bitmap::get_bit(&entry.value.as_bytes(), offset));
+        Ok(Message::Int(bit as isize))
+    })
+}
+
+/// The optional `start end [BYTE|BIT]` range of `BITCOUNT` and `BITPOS`, starting at `index`.
+#[derive(Debug, Default)]
+struct BitRange {
+    start: Option<i64>,
+    end: Option<i64>,
+    bit_unit: bool,
+}
+
+impl BitRange {
+    fn parse(cmd: &CommandInfo, index: usize) -> Result<Self, CommandError> {
+        let mut range = Self::default();
+        if cmd.args.len() > index {
+            range.start = Some(cmd.parse_arg::<i64>(index)?);
+        }
+        if cmd.args.len() > index + 1 {
+            range.end = Some(cmd.parse_arg::<i64>(index + 1)?);
+        }
+        if cmd.args.len() > index + 2 {
+            range.bit_unit = match cmd.arg_string(index + 2).to_lowercase().as_str() {
+                "bit" => true,
+                "byte" => false,
+                _ => return Err(CommandError::Syntax),
+            };
+        }
+        if cmd.args.len() > index + 3 {
+            return Err(CommandError::Syntax);
+        }
+        Ok(range)
+    }
+
+    /// The inclusive bit offsets the range covers in a string of `len` bytes, or `None` when it covers nothing.
+    fn resolve(&self, len: usize) -> Option<(u64, u64)> {
+        let (start, end) = (self.start.unwrap_or(0), self.end.unwrap_or(-1));
+        if self.bit_unit {
+            let (start, end) = clamp_range(start, end, len * 8)?;
+            Some((start as u64, end as u64))
+        } else {
+            let (start, end) = clamp_range(start, end, len)?;
+            Some((start as u64 * 8, end as u64 * 8 + 7))
+        }
+    }
+}
+
+fn process_bitcount(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let range = BitRange::parse(&cmd, 1)?;
+        // A start without an end is not a range.
+        if range.start.is_some() && range.end.is_none() {
+            return Err(CommandError::Syntax.into());
+        }
+        let store = ctx.store.lock().await;
+        let bytes = store
+            .get_string(&cmd.args[0])?
+            .map(|entry| entry.value.as_bytes())
+            .unwrap_or_default();
+        let count = range
+            .resolve(bytes.len())
+            .map_or(0, |(start, end)| bitmap::count(&bytes, start, end));
+        Ok(Message::Int(count as isize))
+    })
+}
+
+fn process_bitpos(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let bit = parse_bit(&cmd, 1, "ERR The bit argument must be 1 or 0.")?;
+        let range = BitRange::parse(&cmd, 2)?;
+        let store = ctx.store.lock().await;
+        let Some(entry) = store.get_string(&cmd.args[0])? else {
+            // A missing key is an endless run of zeros.
+            return Ok(Message::Int(if bit { -1 } else { 0 }));
+        };
+        let bytes = entry.value.as_bytes();
+        let Some((start, end)) = range.resolve(bytes.len()) else {
+            return Ok(Message::Int(-1));
+        };
+        let position = match bitmap::position(&bytes, bit, start, end) {
+            Some(position) => position as isize,
+            // Without an explicit end, the zeros past the end of the string count.
+            None if !bit && range.end.is_none() => (bytes.len() * 8) as isize,
+            None => -1,
+        };
+        Ok(Message::Int(position))
+    })
+}
+
+fn process_bitop(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let op = cmd.arg_string(0).parse::<BitOp>().map_err(|_| CommandError::Syntax)?;
+        let (destination, keys) = (&cmd.args[1], &cmd.args[2..]);
+        match op {
+            BitOp::Not if keys.len() != 1 => {
+                return Err(
+                    CommandError::Other("ERR BITOP NOT must be called with a single source key.".to_string()).into(),
+                )
+            }
+            BitOp::Diff if keys.len() < 2 => {
+                return Err(CommandError::Other(
+                    "ERR BITOP DIFF must be called with at least two source keys.".to_string(),
+                )
+                .into())
+            }
+            _ => {}
+        }
+
+        let mut store = ctx.store.lock().await;
+        let sources = keys
+            .iter()
+            .map(|key| {
+                Ok(store
+                    .get_string(key)?
+                    .map(|entry| entry.value.to_bytes())
+                    .unwrap_or_default())
+            })
+            .collect::<anyhow::Result<Vec<Bytes>>>()?;
+        let result = op.apply(&sources);
+        let len = result.len();
+        if result.is_empty() {
+            store.remove(destination);
+        } else {
+            store.set_kv(destination.clone(), Entry::new(result.into()))?;
+        }
+        Ok(Message::Int(len as isize))
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FieldOp {
+    Get,
+    Set(i64),
+    IncrBy(i64),
+}
+
+/// One `GET`, `SET` or `INCRBY` of a `BITFIELD`, with the overflow policy in effect where it appears.
+#[derive(Debug)]
+struct FieldCommand {
+    op: FieldOp,
+    field: FieldType,
+    offset: u64,
+    overflow: Overflow,
+}
+
+fn parse_bitfield(cmd: &CommandInfo, read_only: bool) -> Result<Vec<FieldCommand>, CommandError> {
+    let mut commands = Vec::new();
+    let mut overflow = Overflow::default();
+    let mut index = 1;
+    while index < cmd.args.len() {
+        let subcommand = cmd.arg_string(index).to_lowercase();
+        if subcommand == "overflow" && index + 1 < cmd.args.len() {
+            overflow = match cmd.arg_string(index + 1).to_lowercase().as_str() {
+                "wrap" => Overflow::Wrap,
+                "sat" => Overflow::Sat,
+                "fail" => Overflow::Fail,
+                _ => return Err(CommandError::Other("ERR Invalid OVERFLOW type specified".to_string())),
+            };
+            index += 2;
+            continue;
+        }
+
+        let arity = match subcommand.as_str() {
+            "get" => 3,
+            "set" | "incrby" => 4,
+            _ => return Err(CommandError::Syntax),
+        };
+        if index + arity > cmd.args.len() {
+            return Err(CommandError::Syntax);
+        }
+        if read_only && subcommand != "get" {
+            return Err(CommandError::Other(
+                "ERR BITFIELD_RO only supports the GET subcommand".to_string(),
+            ));
+        }
+        let field = cmd.arg_string(index + 1).parse::<FieldType>().map_err(|_| {
+            CommandError::Other(
+                "ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is."
+                    .to_string(),
+            )
+        })?;
+        let offset = parse_offset(cmd, index + 2, field.bits, true)?;
+        let op = match subcommand.as_str() {
+            "get" => FieldOp::Get,
+            "set" => FieldOp::Set(cmd.parse_arg::<i64>(index + 3)?),
+            _ => FieldOp::IncrBy(cmd.parse_arg::<i64>(index + 3)?),
+        };
+        commands.push(FieldCommand {
+            op,
+            field,
+            offset,
+            overflow,
+        });
+        index += arity;
+    }
+    Ok(commands)
+}
+
+/// Runs `commands` against `bytes`, returning one reply per command.
+fn run_bitfield(bytes: &mut Vec<u8>, commands: &[FieldCommand]) -> Vec<Message> {
+    let reply = |value: Option<i64>| value.map_or(Message::Null, |value| Message::Int(value as isize));
+    commands
+        .iter()
+        .map(|command| {
+            let FieldCommand {
+                op,
+                field,
+                offset,
+                overflow,
+            } = *command;
+            let current = field.get(bytes, offset);
+            match op {
+                FieldOp::Get => reply(Some(current)),
+                FieldOp::Set(value) => {
+                    // Unsigned fields read the value as unsigned, so negative values overflow them.
+                    let value = if field.signed {
+                        value as i128
+                    } else {
+                        value as u64 as i128
+                    };
+                    let Some(value) = field.fit(value, overflow) else {
+                        return Message::Null;
+                    };
+                    field.set(bytes, offset, value);
+                    reply(Some(current))
+                }
+                FieldOp::IncrBy(by) => {
+                    let value = field.fit(current as i128 + by as i128, overflow);
+                    if let Some(value) = value {
+                        field.set(bytes, offset, value);
+                    }
+                    reply(value)
+                }
+            }
+        })
+        .collect()
+}
+
+fn process_bitfield(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let commands = parse_bitfield(&cmd, false)?;
+        let writes = commands.iter().any(|command| !matches!(command.op, FieldOp::Get));
+        if !writes {
+            ctx.propagate = Some(Vec::new());
+            return bitfield_read(ctx, &cmd.args[0], &commands).await;
+        }
+
+        let mut store = ctx.store.lock().await;
+        let entry = store.get_or_create_string(cmd.args[0].clone())?;
+        let replies = entry.value.edit(|bytes| run_bitfield(bytes, &commands));
+        Ok(Message::Array(replies))
+    })
+}
+
+fn process_bitfield_ro(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let commands = parse_bitfield(&cmd, true)?;
+        bitfield_read(ctx, &cmd.args[0], &commands).await
+    })
+}
+
+/// Runs `BITFIELD` commands that only read, leaving a missing key missing.
+async fn bitfield_read(ctx: &mut Context, key: &Bytes, commands: &[FieldCommand]) -> anyhow::Result<Message> {
+    let store = ctx.store.lock().await;
+    let mut bytes = store
+        .get_string(key)?
+        .map(|entry| entry.value.as_bytes().into_owned())
+        .unwrap_or_default();
+    Ok(Message::Array(run_bitfield(&mut bytes, commands)))
+}
@@ -0,0 +1,180 @@
+use crate::{
+    command::{CommandError, CommandInfo, ExpireCondition, TimeUnit},
+    message::Message,
+    registry::{Context, HandlerFuture, Registry},
+    utils::unix_millis,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Replies of the TTL commands for keys without a time to live, as defined by Redis.
+const NO_SUCH_KEY: isize = -2;
+const NO_EXPIRY: isize = -1;
+
+pub fn register(registry: &mut Registry) {
+    registry.register_fn("expire", process_expire);
+    registry.register_fn("pexpire", process_pexpire);
+    registry.register_fn("expireat", process_expireat);
+    registry.register_fn("pexpireat", process_pexpireat);
+    registry.register_fn("ttl", process_ttl);
+    registry.register_fn("pttl", process_pttl);
+    registry.register_fn("expiretime", process_expiretime);
+    registry.register_fn("pexpiretime", process_pexpiretime);
+    registry.register_fn("persist", process_persist);
+}
+
+/// Parses the `NX`, `XX`, `GT` and `LT` options following the time. `XX` may be combined with `GT` or `LT`, so every
+/// condition given has to allow the new expiry.
+fn parse_conditions(cmd: &CommandInfo) -> Result<Vec<ExpireCondition>, CommandError> {
+    let mut conditions = Vec::new();
+    for index in 2..cmd.args.len() {
+        let condition = match cmd.arg_string(index).to_lowercase().as_str() {
+            "nx" => ExpireCondition::Nx,
+            "xx" => ExpireCondition::Xx,
+            "gt" => ExpireCondition::Gt,
+            "lt" => ExpireCondition::Lt,
+            _ => {
+                return Err(CommandError::Other(format!(
+                    "ERR Unsupported option {}",
+                    cmd.arg_string(index)
+                )))
+            }
+        };
+        conditions.push(condition);
+    }
+    let has = |condition| conditions.contains(&condition);
+    if has(ExpireCondition::Nx) && (has(ExpireCondition::Xx) || has(ExpireCondition::Gt) || has(ExpireCondition::Lt)) {
+        return Err(CommandError::Other(
+            "ERR NX and XX, GT or LT options at the same time are not compatible".to_string(),
+        ));
+    }
+    if has(ExpireCondition::Gt) && has(ExpireCondition::Lt) {
+        return Err(CommandError::Other(
+            "ERR GT and LT options at the same time are not compatible".to_string(),
+        ));
+    }
+    Ok(conditions)
+}
+
+/// Resolves the time argument into an absolute expiry time. Unlike for hash fields, times in the past are valid and
+/// delete the key.
+fn parse_expiry(cmd: &CommandInfo, unit: TimeUnit, absolute: bool) -> Result<SystemTime, CommandError> {
+    let time = cmd.parse_arg::<i64>(1)?;
+    let invalid = || {
+        CommandError::Other(format!(
+            "ERR invalid expire time in '{}' command",
+            cmd.name.to_lowercase()
+        ))
+    };
+    let millis = match unit {
+        TimeUnit::Seconds => time.checked_mul(1000).ok_or_else(invalid)?,
+        TimeUnit::Millis => time,
+    };
+    let millis = match absolute {
+        true => millis,
+        false => millis
+            .checked_add(unix_millis(SystemTime::now()) as i64)
+            .ok_or_else(invalid)?,
+    };
+    // Times before the epoch compare like the epoch itself, which has long passed either way.
+    UNIX_EPOCH
+        .checked_add(Duration::from_millis(millis.max(0) as u64))
+        .ok_or_else(invalid)
+}
+
+fn process_expire(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { expire(ctx, cmd, TimeUnit::Seconds, false).await })
+}
+
+fn process_pexpire(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { expire(ctx, cmd, TimeUnit::Millis, false).await })
+}
+
+fn process_expireat(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { expire(ctx, cmd, TimeUnit::Seconds, true).await })
+}
+
+fn process_pexpireat(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { expire(ctx, cmd, TimeUnit::Millis, true).await })
+}
+
+async fn expire(ctx: &mut Context, cmd: CommandInfo, unit: TimeUnit, absolute: bool) -> anyhow::Result<Message> {
+    let conditions = parse_conditions(&cmd)?;
+    let expiry = parse_expiry(&cmd, unit, absolute)?;
+
+    let key = &cmd.args[0];
+    let mut store = ctx.store.lock().await;
+    if store.get_store_item(key).is_none() {
+        ctx.propagate = Some(Vec::new());
+        return Ok(Message::Int(0));
+    }
+    let current = store.expiry(key);
+    if !conditions.iter().all(|condition| condition.allows(current, expiry)) {
+        ctx.propagate = Some(Vec::new());
+        return Ok(Message::Int(0));
+    }
+
+    if expiry <= SystemTime::now() {
+        store.remove(key);
+        ctx.propagate = Some(vec![CommandInfo::new("DEL".to_string(), vec![key.clone()])]);
+    } else {
+        store.set_expiry(key, Some(expiry));
+        // Relative times are replicated as absolute ones so replicas expire the key at the same moment.
+        ctx.propagate = Some(vec![CommandInfo::new(
+            "PEXPIREAT".to_string(),
+            vec![key.clone(), unix_millis(expiry).to_string().into()],
+        )]);
+    }
+    Ok(Message::Int(1))
+}
+
+fn process_ttl(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { ttl(ctx, cmd, TimeUnit::Seconds, false).await })
+}
+
+fn process_pttl(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { ttl(ctx, cmd, TimeUnit::Millis, false).await })
+}
+
+fn process_expiretime(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { ttl(ctx, cmd, TimeUnit::Seconds, true).await })
+}
+
+fn process_pexpiretime(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { ttl(ctx, cmd, TimeUnit::Millis, true).await })
+}
+
+/// Replies with the remaining time to live of the key, or its absolute expiry time when `absolute` is set.
+async fn ttl(ctx: &mut Context, cmd: CommandInfo, unit: TimeUnit, absolute: bool) -> anyhow::Result<Message> {
+    let key = &cmd.args[0];
+    let mut store = ctx.store.lock().await;
+    if store.get_store_item(key).is_none() {
+        return Ok(Message::Int(NO_SUCH_KEY));
+    }
+    let Some(expiry) = store.expiry(key) else {
+        return Ok(Message::Int(NO_EXPIRY));
+    };
+    let millis = if absolute {
+        unix_millis(expiry)
+    } else {
+        expiry.duration_since(SystemTime::now()).unwrap_or_default().as_millis() as u64
+    };
+    let value = match unit {
+        TimeUnit::Seconds if absolute => millis / 1000,
+        TimeUnit::Seconds => (millis + 500) / 1000,
+        TimeUnit::Millis => millis,
+    };
+    Ok(Message::Int(value as isize))
+}
+
+fn process_persist(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let key = &cmd.args[0];
+        let mut store = ctx.store.lock().await;
+        if store.get_store_item(key).is_none() || store.expiry(key).is_none() {
+            ctx.propagate = Some(Vec::new());
+            return Ok(Message::Int(0));
+        }
+        store.set_expiry(key, None);
+        Ok(Message::Int(1))
+    })
+}
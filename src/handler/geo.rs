@@ -0,0 +1,381 @@
+use super::sorted_set::process_zadd;
+use crate::{
+    command::{CommandError, CommandInfo},
+    geo::{self, Found, Shape},
+    message::Message,
+    registry::{Context, HandlerFuture, Registry},
+    sorted_set::SortedSet,
+    store::StoreItem,
+};
+use bytes::Bytes;
+
+pub fn register(registry: &mut Registry) {
+    registry.register_fn("geoadd", process_geoadd);
+    registry.register_fn("geopos", process_geopos);
+    registry.register_fn("geodist", process_geodist);
+    registry.register_fn("geohash", process_geohash);
+    registry.register_fn("geosearch", process_geosearch);
+    registry.register_fn("geosearchstore", process_geosearchstore);
+}
+
+fn parse_float(cmd: &CommandInfo, index: usize, error: &str) -> Result<f64, CommandError> {
+    cmd.parse_arg::<f64>(index)
+        .ok()
+        .filter(|value| !value.is_nan())
+        .ok_or_else(|| CommandError::Other(error.to_string()))
+}
+
+/// Parses the longitude and latitude at `index`.
+fn parse_coordinates(cmd: &CommandInfo, index: usize) -> Result<(f64, f64), CommandError> {
+    let longitude = parse_float(cmd, index, "ERR value is not a valid float")?;
+    let latitude = parse_float(cmd, index + 1, "ERR value is not a valid float")?;
+    if !geo::is_valid(longitude, latitude) {
+        return Err(CommandError::Other(format!(
+            "ERR invalid longitude,latitude pair {longitude:.6},{latitude:.6}"
+        )));
+    }
+    Ok((longitude, latitude))
+}
+
+/// Parses the unit at `index` into the meters it stands for.
+fn parse_unit(cmd: &CommandInfo, index: usize) -> Result<f64, CommandError> {
+    geo::unit_to_meters(&cmd.arg_string(index))
+        .ok_or_else(|| CommandError::Other("ERR unsupported unit provided. please use M, KM, FT, MI".to_string()))
+}
+
+/// Distances are replied as strings with four decimals, in whatever unit was asked for.
+fn distance_message(distance: f64) -> Message {
+    Message::Bulk(format!("{distance:.4}").into())
+}
+
+fn coordinates_message(longitude: f64, latitude: f64) -> Message {
+    Message::Array(vec![Message::Double(longitude), Message::Double(latitude)])
+}
+
+fn process_geoadd(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut index = 1;
+        let (mut nx, mut xx) = (false, false);
+        let mut options = Vec::new();
+        while index < cmd.args.len() {
+            match cmd.arg_string(index).to_lowercase().as_str() {
+                "nx" => nx = true,
+                "xx" => xx = true,
+                "ch" => {}
+                _ => break,
+            }
+            options.push(cmd.args[index].clone());
+            index += 1;
+        }
+        let triples = &cmd.args[index..];
+        if triples.is_empty() || !triples.len().is_multiple_of(3) || (nx && xx) {
+            return Err(CommandError::Syntax.into());
+        }
+
+        // Like Redis, GEOADD is a ZADD of geohash scores, and replicas are sent that ZADD.
+        let mut args = vec![cmd.args[0].clone()];
+        args.extend(options);
+        for offset in (0..triples.len()).step_by(3) {
+            let (longitude, latitude) = parse_coordinates(&cmd, index + offset)?;
+            let score = geo::encode(longitude, latitude) as u64;
+            args.extend([score.to_string().into(), triples[offset + 2].clone()]);
+        }
+        let zadd = CommandInfo::new("ZADD".to_string(), args);
+        ctx.propagate = Some(vec![zadd.clone()]);
+        process_zadd(ctx, zadd).await
+    })
+}
+
+fn process_geopos(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let store = ctx.store.lock().await;
+        let zset = store.get_sorted_set(&cmd.args[0])?;
+        let positions = cmd.args[1..]
+            .iter()
+            .map(|member| match zset.and_then(|zset| zset.score(member)) {
+                Some(score) => {
+                    let (longitude, latitude) = geo::decode(score);
+                    coordinates_message(longitude, latitude)
+                }
+                None => Message::NullArray,
+            })
+            .collect();
+        Ok(Message::Array(positions))
+    })
+}
+
+fn process_geodist(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let to_meters = match cmd.args.len() {
+            3 => 1.0,
+            4 => parse_unit(&cmd, 3)?,
+            _ => return Err(CommandError::Syntax.into()),
+        };
+        let store = ctx.store.lock().await;
+        let zset = store.get_sorted_set(&cmd.args[0])?;
+        let scores = zset.and_then(|zset| Some((zset.score(&cmd.args[1])?, zset.score(&cmd.args[2])?)));
+        let Some((first, second)) = scores else {
+            return Ok(Message::Null);
+        };
+        let (longitude1, latitude1) = geo::decode(first);
+        let (longitude2, latitude2) = geo::decode(second);
+        let distance = geo::distance(longitude1, latitude1, longitude2, latitude2);
+        Ok(distance_message(distance / to_meters))
+    })
+}
+
+fn process_geohash(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let store = ctx.store.lock().await;
+        let zset = store.get_sorted_set(&cmd.args[0])?;
+        let hashes = cmd.args[1..]
+            .iter()
+            .map(|member| match zset.and_then(|zset| zset.score(member)) {
+                Some(score) => Message::Bulk(geo::geohash_string(score).into()),
+                None => Message::Null,
+            })
+            .collect();
+        Ok(Message::Array(hashes))
+    })
+}
+
+#[derive(Debug, Clone)]
+enum Center {
+    Member(Bytes),
+    Coordinates(f64, f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Order {
+    Unsorted,
+    Ascending,
+    Descending,
+}
+
+/// Arguments of `GEOSEARCH`, and of `GEOSEARCHSTORE` after its destination.
+#[derive(Debug)]
+struct SearchArgs {
+    center: Center,
+    shape: Shape,
+    /// Meters in the unit of the shape, which distances in replies are given in.
+    to_meters: f64,
+    order: Order,
+    count: Option<usize>,
+    any: bool,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+    store_dist: bool,
+}
+
+impl SearchArgs {
+    /// Parses the arguments following the source key, found at `index`. Only `GEOSEARCHSTORE` takes `STOREDIST`, and
+    /// it takes none of the `WITH` options.
+    fn parse(cmd: &CommandInfo, index: usize, store: bool) -> Result<Self, CommandError> {
+        let mut center = None;
+        let mut shape = None;
+        let mut args = Self {
+            center: Center::Coordinates(0.0, 0.0),
+            shape: Shape::Radius(0.0),
+            to_meters: 1.0,
+            order: Order::Unsorted,
+            count: None,
+            any: false,
+            with_coord: false,
+            with_dist: false,
+            with_hash: false,
+            store_dist: false,
+        };
+
+        let mut index = index;
+        while index < cmd.args.len() {
+            let remaining = cmd.args.len() - index - 1;
+            match cmd.arg_string(index).to_lowercase().as_str() {
+                "withdist" => args.with_dist = true,
+                "withhash" => args.with_hash = true,
+                "withcoord" => args.with_coord = true,
+                "any" => args.any = true,
+                "asc" => args.order = Order::Ascending,
+                "desc" => args.order = Order::Descending,
+                "storedist" if store => args.store_dist = true,
+                "count" if remaining > 0 => {
+                    let count = cmd.parse_arg::<i64>(index + 1)?;
+                    if count <= 0 {
+                        return Err(CommandError::Other("ERR COUNT must be > 0".to_string()));
+                    }
+                    args.count = Some(count as usize);
+                    index += 1;
+                }
+                "frommember" if remaining > 0 => {
+                    if center.is_some() {
+                        return Err(incompatible("FROMMEMBER and FROMLONLAT"));
+                    }
+                    center = Some(Center::Member(cmd.args[index + 1].clone()));
+                    index += 1;
+                }
+                "fromlonlat" if remaining > 1 => {
+                    if center.is_some() {
+                        return Err(incompatible("FROMMEMBER and FROMLONLAT"));
+                    }
+                    let (longitude, latitude) = parse_coordinates(cmd, index + 1)?;
+                    center = Some(Center::Coordinates(longitude, latitude));
+                    index += 2;
+                }
+                "byradius" if remaining > 1 => {
+                    if shape.is_some() {
+                        return Err(incompatible("BYRADIUS and BYBOX"));
+                    }
+                    let radius = parse_float(cmd, index + 1, "ERR need numeric radius")?;
+                    if radius < 0.0 {
+                        return Err(CommandError::Other("ERR radius cannot be negative".to_string()));
+                    }
+                    args.to_meters = parse_unit(cmd, index + 2)?;
+                    shape = Some(Shape::Radius(radius));
+                    index += 2;
+                }
+                "bybox" if remaining > 2 => {
+                    if shape.is_some() {
+                        return Err(incompatible("BYRADIUS and BYBOX"));
+                    }
+                    let width = parse_float(cmd, index + 1, "ERR need numeric width")?;
+                    let height = parse_float(cmd, index + 2, "ERR need numeric height")?;
+                    if width < 0.0 || height < 0.0 {
+                        return Err(CommandError::Other(
+                            "ERR height or width cannot be negative".to_string(),
+                        ));
+                    }
+                    args.to_meters = parse_unit(cmd, index + 3)?;
+                    shape = Some(Shape::Box { width, height });
+                    index += 3;
+                }
+                _ => return Err(CommandError::Syntax),
+            }
+            index += 1;
+        }
+
+        if store && (args.with_dist || args.with_hash || args.with_coord) {
+            return Err(CommandError::Other(
+                "ERR GEOSEARCHSTORE is not compatible with WITHDIST, WITHHASH and WITHCOORD options".to_string(),
+            ));
+        }
+        let name = cmd.name.to_lowercase();
+        args.center = center.ok_or_else(|| {
+            CommandError::Other(format!(
+                "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for {name}"
+            ))
+        })?;
+        let shape = shape.ok_or_else(|| {
+            CommandError::Other(format!(
+                "ERR exactly one of BYRADIUS and BYBOX can be specified for {name}"
+            ))
+        })?;
+        if args.any && args.count.is_none() {
+            return Err(CommandError::Other(
+                "ERR the ANY argument requires COUNT argument".to_string(),
+            ));
+        }
+        // The nearest members are only found by sorting, unless any members will do.
+        if args.count.is_some() && args.order == Order::Unsorted && !args.any {
+            args.order = Order::Ascending;
+        }
+        args.shape = match shape {
+            Shape::Radius(radius) => Shape::Radius(radius * args.to_meters),
+            Shape::Box { width, height } => Shape::Box {
+                width: width * args.to_meters,
+                height: height * args.to_meters,
+            },
+        };
+        Ok(args)
+    }
+
+    /// Runs the search over `zset`.
+    fn search(&self, zset: &SortedSet) -> Result<Vec<Found>, CommandError> {
+        let (longitude, latitude) = match &self.center {
+            Center::Coordinates(longitude, latitude) => (*longitude, *latitude),
+            Center::Member(member) => {
+                let score = zset
+                    .score(member)
+                    .ok_or_else(|| CommandError::Other("ERR could not decode requested zset member".to_string()))?;
+                geo::decode(score)
+            }
+        };
+        let limit = if self.any { self.count } else { None };
+        let mut found = geo::search(zset, longitude, latitude, self.shape, limit);
+        match self.order {
+            Order::Unsorted => {}
+            Order::Ascending => found.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+            Order::Descending => found.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+        }
+        found.truncate(self.count.unwrap_or(usize::MAX));
+        Ok(found)
+    }
+}
+
+fn incompatible(options: &str) -> CommandError {
+    CommandError::Other(format!("ERR {options} options at the same time are not compatible"))
+}
+
+fn process_geosearch(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = SearchArgs::parse(&cmd, 1, false)?;
+        let store = ctx.store.lock().await;
+        let Some(zset) = store.get_sorted_set(&cmd.args[0])? else {
+            return Ok(Message::Array(vec![]));
+        };
+        let found = args.search(zset)?;
+
+        let with_options = args.with_dist || args.with_hash || args.with_coord;
+        let replies = found
+            .into_iter()
+            .map(|found| {
+                let member = Message::Bulk(found.member);
+                if !with_options {
+                    return member;
+                }
+                let mut reply = vec![member];
+                if args.with_dist {
+                    reply.push(distance_message(found.distance / args.to_meters));
+                }
+                if args.with_hash {
+                    reply.push(Message::Int(found.score as isize));
+                }
+                if args.with_coord {
+                    reply.push(coordinates_message(found.longitude, found.latitude));
+                }
+                Message::Array(reply)
+            })
+            .collect();
+        Ok(Message::Array(replies))
+    })
+}
+
+fn process_geosearchstore(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = SearchArgs::parse(&cmd, 2, true)?;
+        let destination = cmd.args[0].clone();
+        let mut store = ctx.store.lock().await;
+        let found = match store.get_sorted_set(&cmd.args[1])? {
+            Some(zset) => args.search(zset)?,
+            None => Vec::new(),
+        };
+
+        let zset: SortedSet = found
+            .into_iter()
+            .map(|found| {
+                let score = if args.store_dist {
+                    found.distance / args.to_meters
+                } else {
+                    found.score
+                };
+                (found.member, score)
+            })
+            .collect();
+        let len = zset.len();
+        if zset.is_empty() {
+            store.remove(&destination);
+        } else {
+            store.set_item(destination, StoreItem::SortedSet(zset));
+        }
+        Ok(Message::Int(len as isize))
+    })
+}
@@ -0,0 +1,602 @@
+use crate::{
+    command::{CommandError, CommandInfo, ExpireCondition, ScanArgs, TimeUnit},
+    message::{Message, Protocol},
+    registry::{Context, HandlerFuture, Registry},
+    utils::{format_float, glob_match, scan, unix_millis},
+};
+use bytes::Bytes;
+use rand::seq::{IteratorRandom, SliceRandom};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Field replies of the hash TTL commands, as defined by Redis.
+const NO_SUCH_FIELD: isize = -2;
+const NO_EXPIRY: isize = -1;
+const CONDITION_NOT_MET: isize = 0;
+const EXPIRY_SET: isize = 1;
+const FIELD_DELETED: isize = 2;
+
+pub fn register(registry: &mut Registry) {
+    registry.register_fn("hset", process_hset);
+    registry.register_fn("hmset", process_hmset);
+    registry.register_fn("hsetnx", process_hsetnx);
+    registry.register_fn("hget", process_hget);
+    registry.register_fn("hmget", process_hmget);
+    registry.register_fn("hdel", process_hdel);
+    registry.register_fn("hlen", process_hlen);
+    registry.register_fn("hstrlen", process_hstrlen);
+    registry.register_fn("hexists", process_hexists);
+    registry.register_fn("hkeys", process_hkeys);
+    registry.register_fn("hvals", process_hvals);
+    registry.register_fn("hgetall", process_hgetall);
+    registry.register_fn("hincrby", process_hincrby);
+    registry.register_fn("hincrbyfloat", process_hincrbyfloat);
+    registry.register_fn("hscan", process_hscan);
+    registry.register_fn("hrandfield", process_hrandfield);
+    registry.register_fn("hexpire", process_hexpire);
+    registry.register_fn("hpexpire", process_hpexpire);
+    registry.register_fn("hexpireat", process_hexpireat);
+    registry.register_fn("hpexpireat", process_hpexpireat);
+    registry.register_fn("httl", process_httl);
+    registry.register_fn("hpttl", process_hpttl);
+    registry.register_fn("hexpiretime", process_hexpiretime);
+    registry.register_fn("hpexpiretime", process_hpexpiretime);
+    registry.register_fn("hpersist", process_hpersist);
+    registry.register_fn("hgetex", process_hgetex);
+}
+
+fn process_hset(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let added = set_fields(ctx, &cmd).await?;
+        Ok(Message::Int(added as isize))
+    })
+}
+
+fn process_hmset(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        set_fields(ctx, &cmd).await?;
+        Ok(Message::Simple("OK".to_string()))
+    })
+}
+
+/// Applies the field/value pairs of `HSET` and `HMSET`, returning how many fields were created.
+async fn set_fields(ctx: &mut Context, cmd: &CommandInfo) -> anyhow::Result<usize> {
+    if cmd.args.len().is_multiple_of(2) {
+        return Err(CommandError::WrongArity(cmd.name.to_lowercase()).into());
+    }
+    let mut store = ctx.store.lock().await;
+    let hash = store.get_or_create_hash(cmd.args[0].clone())?;
+    let added = cmd.args[1..]
+        .chunks_exact(2)
+        .filter(|pair| hash.insert(pair[0].clone(), pair[1].clone()))
+        .count();
+    Ok(added)
+}
+
+fn process_hsetnx(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        let hash = store.get_or_create_hash(cmd.args[0].clone())?;
+        if hash.contains(&cmd.args[1]) {
+            return Ok(Message::Int(0));
+        }
+        hash.insert(cmd.args[1].clone(), cmd.args[2].clone());
+        Ok(Message::Int(1))
+    })
+}
+
+fn process_hget(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        let value = store.get_hash(&cmd.args[0])?.and_then(|hash| hash.get(&cmd.args[1]));
+        Ok(value.cloned().map_or(Message::Null, Message::Bulk))
+    })
+}
+
+fn process_hmget(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        let hash = store.get_hash(&cmd.args[0])?;
+        let values = cmd.args[1..]
+            .iter()
+            .map(|field| {
+                hash.and_then(|hash| hash.get(field))
+                    .cloned()
+                    .map_or(Message::Null, Message::Bulk)
+            })
+            .collect();
+        Ok(Message::Array(values))
+    })
+}
+
+fn process_hdel(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let key = &cmd.args[0];
+        let mut store = ctx.store.lock().await;
+        let Some(hash) = store.get_hash_mut(key)? else {
+            return Ok(Message::Int(0));
+        };
+        let removed = cmd.args[1..].iter().filter(|field| hash.remove(field)).count();
+        store.remove_if_empty(key);
+        Ok(Message::Int(removed as isize))
+    })
+}
+
+fn process_hlen(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        let len = store.get_hash(&cmd.args[0])?.map_or(0, |hash| hash.len());
+        Ok(Message::Int(len as isize))
+    })
+}
+
+fn process_hstrlen(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        let len = store
+            .get_hash(&cmd.args[0])?
+            .and_then(|hash| hash.get(&cmd.args[1]))
+            .map_or(0, |value| value.len());
+        Ok(Message::Int(len as isize))
+    })
+}
+
+fn process_hexists(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        let exists = store
+            .get_hash(&cmd.args[0])?
+            .is_some_and(|hash| hash.contains(&cmd.args[1]));
+        Ok(Message::Int(exists as isize))
+    })
+}
+
+fn process_hkeys(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        let fields = store
+            .get_hash(&cmd.args[0])?
+            .map(|hash| hash.iter().map(|(field, _)| Message::Bulk(field.clone())).collect())
+            .unwrap_or_default();
+        Ok(Message::Array(fields))
+    })
+}
+
+fn process_hvals(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        let values = store
+            .get_hash(&cmd.args[0])?
+            .map(|hash| hash.iter().map(|(_, value)| Message::Bulk(value.clone())).collect())
+            .unwrap_or_default();
+        Ok(Message::Array(values))
+    })
+}
+
+fn process_hgetall(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        let pairs = store
+            .get_hash(&cmd.args[0])?
+            .map(|hash| {
+                hash.iter()
+                    .map(|(field, value)| (Message::Bulk(field.clone()), Message::Bulk(value.clone())))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Message::Map(pairs))
+    })
+}
+
+fn process_hincrby(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let increment = cmd.parse_arg::<i64>(2)?;
+        let mut store = ctx.store.lock().await;
+        let current = match store.get_hash(&cmd.args[0])?.and_then(|hash| hash.get(&cmd.args[1])) {
+            Some(value) => std::str::from_utf8(value)
+                .ok()
+                .and_then(|value| value.parse::<i64>().ok())
+                .ok_or_else(|| CommandError::Other("ERR hash value is not an integer".to_string()))?,
+            None => 0,
+        };
+        let Some(value) = current.checked_add(increment) else {
+            return Err(CommandError::Other("ERR increment or decrement would overflow".to_string()).into());
+        };
+        let hash = store.get_or_create_hash(cmd.args[0].clone())?;
+        hash.update(cmd.args[1].clone(), value.to_string().into());
+        Ok(Message::Int(value as isize))
+    })
+}
+
+fn process_hincrbyfloat(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let increment = cmd
+            .parse_arg::<f64>(2)
+            .map_err(|_| CommandError::Other("ERR value is not a valid float".to_string()))?;
+        let mut store = ctx.store.lock().await;
+        let current = match store.get_hash(&cmd.args[0])?.and_then(|hash| hash.get(&cmd.args[1])) {
+            Some(value) => std::str::from_utf8(value)
+                .ok()
+                .and_then(|value| value.parse::<f64>().ok())
+                .ok_or_else(|| CommandError::Other("ERR hash value is not a float".to_string()))?,
+            None => 0.0,
+        };
+        let value = current + increment;
+        if !value.is_finite() {
+            return Err(CommandError::Other("ERR increment would produce NaN or Infinity".to_string()).into());
+        }
+        let value: Bytes = format_float(value).into();
+        let hash = store.get_or_create_hash(cmd.args[0].clone())?;
+        hash.update(cmd.args[1].clone(), value.clone());
+
+        // Replicas get the resulting value so float rounding cannot make them drift. `HSET` clears the field TTL,
+        // so it is restored right after.
+        let mut propagate = vec![CommandInfo::new(
+            "HSET".to_string(),
+            vec![cmd.args[0].clone(), cmd.args[1].clone(), value.clone()],
+        )];
+        if let Some(expiry) = hash.expiry(&cmd.args[1]) {
+            propagate.push(pexpireat_command(&cmd.args[0], expiry, vec![cmd.args[1].clone()]));
+        }
+        ctx.propagate = Some(propagate);
+        Ok(Message::Bulk(value))
+    })
+}
+
+fn process_hscan(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = ScanArgs::parse(&cmd, 1)?;
+        let mut store = ctx.store.lock().await;
+        let Some(hash) = store.get_hash(&cmd.args[0])? else {
+            return Ok(Message::Array(vec![Message::Bulk("0".into()), Message::Array(vec![])]));
+        };
+
+        let (cursor, batch) = scan(hash.iter(), args.cursor, args.count);
+        let mut elements = Vec::new();
+        for (field, value) in batch {
+            if args.pattern.as_ref().is_some_and(|pattern| !glob_match(pattern, field)) {
+                continue;
+            }
+            elements.push(Message::Bulk(field.clone()));
+            if !args.novalues {
+                elements.push(Message::Bulk(value.clone()));
+            }
+        }
+        Ok(Message::Array(vec![
+            Message::Bulk(cursor.to_string().into()),
+            Message::Array(elements),
+        ]))
+    })
+}
+
+fn process_hrandfield(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let count = match cmd.args.get(1) {
+            Some(_) => Some(cmd.parse_random_count(1)?),
+            None => None,
+        };
+        let with_values = match cmd.args.get(2) {
+            Some(arg) if cmd.args.len() == 3 && arg.eq_ignore_ascii_case(b"withvalues") => true,
+            Some(_) => return Err(CommandError::Syntax.into()),
+            None => false,
+        };
+
+        let mut store = ctx.store.lock().await;
+        let hash = store.get_hash(&cmd.args[0])?;
+        let mut rng = rand::thread_rng();
+        let Some(count) = count else {
+            let field = hash.and_then(|hash| hash.iter().choose(&mut rng));
+            return Ok(field.map_or(Message::Null, |(field, _)| Message::Bulk(field.clone())));
+        };
+        let Some(hash) = hash else {
+            return Ok(Message::Array(vec![]));
+        };
+
+        let picked: Vec<(&Bytes, &Bytes)> = if count >= 0 {
+            // Sampling reserves room for `count` fields up front, so it must not exceed what the hash holds.
+            hash.iter().choose_multiple(&mut rng, (count as usize).min(hash.len()))
+        } else {
+            // A negative count allows the same field to be returned several times.
+            let fields: Vec<_> = hash.iter().collect();
+            (0..count.unsigned_abs())
+                .filter_map(|_| fields.choose(&mut rng).copied())
+                .collect()
+        };
+
+        let response = if !with_values {
+            picked
+                .into_iter()
+                .map(|(field, _)| Message::Bulk(field.clone()))
+                .collect()
+        } else if ctx.client.protocol == Protocol::Resp3 {
+            picked
+                .into_iter()
+                .map(|(field, value)| Message::Array(vec![Message::Bulk(field.clone()), Message::Bulk(value.clone())]))
+                .collect()
+        } else {
+            picked
+                .into_iter()
+                .flat_map(|(field, value)| [Message::Bulk(field.clone()), Message::Bulk(value.clone())])
+                .collect()
+        };
+        Ok(Message::Array(response))
+    })
+}
+
+/// Parses a `FIELDS numfields field...` block that starts at `index` and must end the command.
+fn parse_fields(cmd: &CommandInfo, index: usize) -> Result<Vec<Bytes>, CommandError> {
+    if !cmd
+        .args
+        .get(index)
+        .is_some_and(|arg| arg.eq_ignore_ascii_case(b"fields"))
+    {
+        return Err(CommandError::Other(
+            "ERR Mandatory argument FIELDS is missing or not at the right position".to_string(),
+        ));
+    }
+    let numfields = cmd
+        .parse_arg::<i64>(index + 1)
+        .ok()
+        .filter(|numfields| *numfields > 0)
+        .ok_or_else(|| CommandError::Other("ERR Parameter `numFields` should be greater than 0".to_string()))?;
+    let fields = cmd.args.get(index + 2..).unwrap_or_default();
+    if fields.len() as i64 != numfields {
+        return Err(CommandError::Other(
+            "ERR The `numfields` parameter must match the number of arguments".to_string(),
+        ));
+    }
+    Ok(fields.to_vec())
+}
+
+/// Resolves the time argument at `index` of an expire command into an absolute expiry time.
+fn parse_expiry(cmd: &CommandInfo, index: usize, unit: TimeUnit, absolute: bool) -> Result<SystemTime, CommandError> {
+    let time = cmd.parse_arg::<i64>(index)?;
+    let invalid = || {
+        CommandError::Other(format!(
+            "ERR invalid expire time in '{}' command",
+            cmd.name.to_lowercase()
+        ))
+    };
+    let millis = match unit {
+        TimeUnit::Seconds => time.checked_mul(1000),
+        TimeUnit::Millis => Some(time),
+    }
+    .filter(|millis| *millis >= 0)
+    .ok_or_else(invalid)?;
+    let base = if absolute { UNIX_EPOCH } else { SystemTime::now() };
+    base.checked_add(Duration::from_millis(millis as u64))
+        .ok_or_else(invalid)
+}
+
+fn pexpireat_command(key: &Bytes, expiry: SystemTime, fields: Vec<Bytes>) -> CommandInfo {
+    let mut args = vec![
+        key.clone(),
+        unix_millis(expiry).to_string().into(),
+        "FIELDS".into(),
+        fields.len().to_string().into(),
+    ];
+    args.extend(fields);
+    CommandInfo::new("HPEXPIREAT".to_string(), args)
+}
+
+fn hpersist_command(key: &Bytes, fields: Vec<Bytes>) -> CommandInfo {
+    let mut args = vec![key.clone(), "FIELDS".into(), fields.len().to_string().into()];
+    args.extend(fields);
+    CommandInfo::new("HPERSIST".to_string(), args)
+}
+
+fn hdel_command(key: &Bytes, fields: Vec<Bytes>) -> CommandInfo {
+    let mut args = vec![key.clone()];
+    args.extend(fields);
+    CommandInfo::new("HDEL".to_string(), args)
+}
+
+fn process_hexpire(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { expire_fields(ctx, cmd, TimeUnit::Seconds, false).await })
+}
+
+fn process_hpexpire(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { expire_fields(ctx, cmd, TimeUnit::Millis, false).await })
+}
+
+fn process_hexpireat(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { expire_fields(ctx, cmd, TimeUnit::Seconds, true).await })
+}
+
+fn process_hpexpireat(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { expire_fields(ctx, cmd, TimeUnit::Millis, true).await })
+}
+
+async fn expire_fields(ctx: &mut Context, cmd: CommandInfo, unit: TimeUnit, absolute: bool) -> anyhow::Result<Message> {
+    let expiry = parse_expiry(&cmd, 1, unit, absolute)?;
+    let condition = match cmd.arg_string(2).to_lowercase().as_str() {
+        "nx" => ExpireCondition::Nx,
+        "xx" => ExpireCondition::Xx,
+        "gt" => ExpireCondition::Gt,
+        "lt" => ExpireCondition::Lt,
+        _ => ExpireCondition::Always,
+    };
+    let fields_index = if condition == ExpireCondition::Always { 2 } else { 3 };
+    let fields = parse_fields(&cmd, fields_index)?;
+
+    let key = &cmd.args[0];
+    let mut store = ctx.store.lock().await;
+    let Some(hash) = store.get_hash_mut(key)? else {
+        ctx.propagate = Some(Vec::new());
+        return Ok(Message::Array(vec![Message::Int(NO_SUCH_FIELD); fields.len()]));
+    };
+
+    let mut replies = Vec::with_capacity(fields.len());
+    let mut updated = Vec::new();
+    let mut deleted = Vec::new();
+    for field in fields {
+        let reply = if !hash.contains(&field) {
+            NO_SUCH_FIELD
+        } else if !condition.allows(hash.expiry(&field), expiry) {
+            CONDITION_NOT_MET
+        } else if expiry <= SystemTime::now() {
+            hash.remove(&field);
+            deleted.push(field);
+            FIELD_DELETED
+        } else {
+            hash.set_expiry(field.clone(), expiry);
+            updated.push(field);
+            EXPIRY_SET
+        };
+        replies.push(Message::Int(reply));
+    }
+    store.remove_if_empty(key);
+    store.track_expiring_fields(key);
+
+    // Relative times are replicated as absolute ones so replicas expire the fields at the same moment.
+    let mut propagate = Vec::new();
+    if !updated.is_empty() {
+        propagate.push(pexpireat_command(key, expiry, updated));
+    }
+    if !deleted.is_empty() {
+        propagate.push(hdel_command(key, deleted));
+    }
+    ctx.propagate = Some(propagate);
+    Ok(Message::Array(replies))
+}
+
+fn process_httl(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { field_ttls(ctx, cmd, TimeUnit::Seconds, false).await })
+}
+
+fn process_hpttl(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { field_ttls(ctx, cmd, TimeUnit::Millis, false).await })
+}
+
+fn process_hexpiretime(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { field_ttls(ctx, cmd, TimeUnit::Seconds, true).await })
+}
+
+fn process_hpexpiretime(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { field_ttls(ctx, cmd, TimeUnit::Millis, true).await })
+}
+
+/// Replies with the remaining time to live of each field, or its absolute expiry time when `absolute` is set.
+async fn field_ttls(ctx: &mut Context, cmd: CommandInfo, unit: TimeUnit, absolute: bool) -> anyhow::Result<Message> {
+    let fields = parse_fields(&cmd, 1)?;
+    let mut store = ctx.store.lock().await;
+    let hash = store.get_hash(&cmd.args[0])?;
+    let now = SystemTime::now();
+    let replies = fields
+        .iter()
+        .map(|field| {
+            let Some(hash) = hash.filter(|hash| hash.contains(field)) else {
+                return Message::Int(NO_SUCH_FIELD);
+            };
+            let Some(expiry) = hash.expiry(field) else {
+                return Message::Int(NO_EXPIRY);
+            };
+            let millis = if absolute {
+                unix_millis(expiry)
+            } else {
+                expiry.duration_since(now).unwrap_or_default().as_millis() as u64
+            };
+            let value = match unit {
+                TimeUnit::Seconds if absolute => millis / 1000,
+                TimeUnit::Seconds => (millis + 500) / 1000,
+                TimeUnit::Millis => millis,
+            };
+            Message::Int(value as isize)
+        })
+        .collect();
+    Ok(Message::Array(replies))
+}
+
+fn process_hpersist(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let fields = parse_fields(&cmd, 1)?;
+        let key = &cmd.args[0];
+        let mut store = ctx.store.lock().await;
+        let hash = store.get_hash_mut(key)?;
+        let mut persisted = Vec::new();
+        let replies = match hash {
+            Some(hash) => fields
+                .into_iter()
+                .map(|field| {
+                    let reply = if !hash.contains(&field) {
+                        NO_SUCH_FIELD
+                    } else if hash.persist(&field) {
+                        persisted.push(field);
+                        EXPIRY_SET
+                    } else {
+                        NO_EXPIRY
+                    };
+                    Message::Int(reply)
+                })
+                .collect(),
+            None => vec![Message::Int(NO_SUCH_FIELD); fields.len()],
+        };
+        ctx.propagate = Some(if persisted.is_empty() {
+            Vec::new()
+        } else {
+            vec![hpersist_command(key, persisted)]
+        });
+        Ok(Message::Array(replies))
+    })
+}
+
+/// The expiry change requested by `HGETEX`.
+enum GetExExpiry {
+    Keep,
+    Persist,
+    At(SystemTime),
+}
+
+fn process_hgetex(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let (change, fields_index) = match cmd.arg_string(1).to_lowercase().as_str() {
+            "ex" => (GetExExpiry::At(parse_expiry(&cmd, 2, TimeUnit::Seconds, false)?), 3),
+            "px" => (GetExExpiry::At(parse_expiry(&cmd, 2, TimeUnit::Millis, false)?), 3),
+            "exat" => (GetExExpiry::At(parse_expiry(&cmd, 2, TimeUnit::Seconds, true)?), 3),
+            "pxat" => (GetExExpiry::At(parse_expiry(&cmd, 2, TimeUnit::Millis, true)?), 3),
+            "persist" => (GetExExpiry::Persist, 2),
+            "fields" => (GetExExpiry::Keep, 1),
+            _ => return Err(CommandError::Syntax.into()),
+        };
+        let fields = parse_fields(&cmd, fields_index)?;
+
+        let key = &cmd.args[0];
+        let now = SystemTime::now();
+        let mut store = ctx.store.lock().await;
+        let Some(hash) = store.get_hash_mut(key)? else {
+            ctx.propagate = Some(Vec::new());
+            return Ok(Message::Array(vec![Message::Null; fields.len()]));
+        };
+
+        let mut values = Vec::with_capacity(fields.len());
+        let mut changed = Vec::new();
+        for field in fields {
+            let Some(value) = hash.get(&field).cloned() else {
+                values.push(Message::Null);
+                continue;
+            };
+            values.push(Message::Bulk(value));
+            match change {
+                GetExExpiry::Keep => continue,
+                GetExExpiry::Persist if !hash.persist(&field) => continue,
+                GetExExpiry::Persist => {}
+                GetExExpiry::At(expiry) if expiry <= now => {
+                    hash.remove(&field);
+                }
+                GetExExpiry::At(expiry) => hash.set_expiry(field.clone(), expiry),
+            }
+            changed.push(field);
+        }
+        store.remove_if_empty(key);
+        store.track_expiring_fields(key);
+
+        let propagate = match change {
+            _ if changed.is_empty() => Vec::new(),
+            GetExExpiry::Keep => Vec::new(),
+            GetExExpiry::Persist => vec![hpersist_command(key, changed)],
+            GetExExpiry::At(expiry) if expiry <= now => vec![hdel_command(key, changed)],
+            GetExExpiry::At(expiry) => vec![pexpireat_command(key, expiry, changed)],
+        };
+        ctx.propagate = Some(propagate);
+        Ok(Message::Array(values))
+    })
+}
@@ -0,0 +1,160 @@
+use crate::{
+    command::{CommandError, CommandInfo},
+    hyperloglog::{self, Corrupted},
+    message::Message,
+    registry::{Context, HandlerFuture, Registry},
+    store::{Store, StringValue},
+};
+use bytes::Bytes;
+use std::borrow::Cow;
+
+pub fn register(registry: &mut Registry) {
+    registry.register_fn("pfadd", process_pfadd);
+    registry.register_fn("pfcount", process_pfcount);
+    registry.register_fn("pfmerge", process_pfmerge);
+    registry.register_fn("pfdebug", process_pfdebug);
+    registry.register_fn("pfselftest", process_pfselftest);
+}
+
+fn not_hll() -> CommandError {
+    CommandError::Other("WRONGTYPE Key is not a valid HyperLogLog string value.".to_string())
+}
+
+impl From<Corrupted> for CommandError {
+    fn from(_: Corrupted) -> Self {
+        CommandError::Other("INVALIDOBJ Corrupted HLL object detected".to_string())
+    }
+}
+
+/// The HyperLogLog at `key`, or an error when the key holds anything else.
+fn get_hll<'a>(store: &'a Store, key: &[u8]) -> anyhow::Result<Option<Cow<'a, [u8]>>> {
+    let Some(entry) = store.get_string(key)? else {
+        return Ok(None);
+    };
+    let hll = entry.value.as_bytes();
+    if !hyperloglog::is_valid(&hll) {
+        return Err(not_hll().into());
+    }
+    Ok(Some(hll))
+}
+
+/// Runs `f` on the HyperLogLog at `key`, creating an empty one when the key is missing. `f` is also told whether the
+/// HyperLogLog was just created.
+fn edit_hll<R>(
+    store: &mut Store,
+    key: &Bytes,
+    f: impl FnOnce(&mut Vec<u8>, bool) -> Result<R, Corrupted>,
+) -> anyhow::Result<R> {
+    let created = get_hll(store, key)?.is_none();
+    let entry = store.get_or_create_string(key.clone())?;
+    if created {
+        entry.value = StringValue::Raw(hyperloglog::new());
+    }
+    Ok(entry.value.edit(|hll| f(hll, created)).map_err(CommandError::from)?)
+}
+
+fn process_pfadd(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        let updated = edit_hll(&mut store, &cmd.args[0], |hll, created| {
+            let mut updated = created;
+            for element in &cmd.args[1..] {
+                updated |= hyperloglog::add(hll, element)?;
+            }
+            Ok(updated)
+        })?;
+        Ok(Message::Int(updated as isize))
+    })
+}
+
+fn process_pfcount(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        if let [key] = cmd.args.as_slice() {
+            if get_hll(&store, key)?.is_none() {
+                return Ok(Message::Int(0));
+            }
+            // The estimate of a single key is cached in its header.
+            let card = edit_hll(&mut store, key, |hll, _| hyperloglog::count(hll))?;
+            return Ok(Message::Int(card as isize));
+        }
+
+        let hlls = cmd
+            .args
+            .iter()
+            .map(|key| get_hll(&store, key))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let registers =
+            hyperloglog::union(hlls.iter().flatten().map(|hll| hll.as_ref())).map_err(CommandError::from)?;
+        Ok(Message::Int(hyperloglog::estimate(&registers) as isize))
+    })
+}
+
+fn process_pfmerge(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        // The destination is part of the union too.
+        let hlls = cmd
+            .args
+            .iter()
+            .map(|key| get_hll(&store, key))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let hlls: Vec<&[u8]> = hlls.iter().flatten().map(|hll| hll.as_ref()).collect();
+        let registers = hyperloglog::union(hlls.iter().copied()).map_err(CommandError::from)?;
+        // Like Redis, the result is dense as soon as one of the inputs is.
+        let dense = hlls.iter().any(|hll| !hyperloglog::is_sparse(hll));
+        edit_hll(&mut store, &cmd.args[0], |hll, _| {
+            hyperloglog::merge(hll, &registers, dense)
+        })?;
+        Ok(Message::Simple("OK".to_string()))
+    })
+}
+
+fn process_pfdebug(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let subcommand = cmd.arg_string(0).to_lowercase();
+        let key = &cmd.args[1];
+        let mut store = ctx.store.lock().await;
+        let Some(hll) = get_hll(&store, key)? else {
+            return Err(CommandError::Other("ERR The specified key does not exist".to_string()).into());
+        };
+        match subcommand.as_str() {
+            "getreg" => {
+                edit_hll(&mut store, key, |hll, _| hyperloglog::to_dense(hll))?;
+                let hll = get_hll(&store, key)?.unwrap_or_default();
+                let registers = hyperloglog::registers(&hll).map_err(CommandError::from)?;
+                Ok(Message::Array(
+                    registers
+                        .into_iter()
+                        .map(|register| Message::Int(register as isize))
+                        .collect(),
+                ))
+            }
+            "decode" if hyperloglog::is_sparse(&hll) => Ok(Message::Simple(hyperloglog::describe_sparse(&hll))),
+            "decode" => Err(CommandError::Other("ERR HLL encoding is not sparse".to_string()).into()),
+            "encoding" => {
+                let encoding = if hyperloglog::is_sparse(&hll) {
+                    "sparse"
+                } else {
+                    "dense"
+                };
+                Ok(Message::Simple(encoding.to_string()))
+            }
+            "todense" => {
+                let converted = edit_hll(&mut store, key, |hll, _| hyperloglog::to_dense(hll))?;
+                Ok(Message::Int(converted as isize))
+            }
+            _ => Err(CommandError::Other(format!("ERR Unknown PFDEBUG subcommand '{}'", cmd.arg_string(0))).into()),
+        }
+    })
+}
+
+fn process_pfselftest(_ctx: &mut Context, _cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        // Adds ten million elements, so it runs off the async workers.
+        tokio::task::spawn_blocking(hyperloglog::self_test)
+            .await?
+            .map_err(CommandError::Other)?;
+        Ok(Message::Simple("OK".to_string()))
+    })
+}
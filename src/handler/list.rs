@@ -0,0 +1,495 @@
+use crate::{
+    blocking::{block_on, parse_timeout, Served},
+    command::{CommandError, CommandInfo, MpopArgs},
+    message::Message,
+    registry::{Context, HandlerFuture, Registry},
+    store::Store,
+    utils::normalize_range,
+};
+use bytes::Bytes;
+use std::collections::VecDeque;
+
+pub fn register(registry: &mut Registry) {
+    registry.register_fn("lpush", process_lpush);
+    registry.register_fn("rpush", process_rpush);
+    registry.register_fn("lpop", process_lpop);
+    registry.register_fn("rpop", process_rpop);
+    registry.register_fn("lrange", process_lrange);
+    registry.register_fn("lindex", process_lindex);
+    registry.register_fn("lset", process_lset);
+    registry.register_fn("lrem", process_lrem);
+    registry.register_fn("ltrim", process_ltrim);
+    registry.register_fn("linsert", process_linsert);
+    registry.register_fn("llen", process_llen);
+    registry.register_fn("lpos", process_lpos);
+    registry.register_fn("lmove", process_lmove);
+    registry.register_fn("lmpop", process_lmpop);
+    registry.register_fn("blpop", process_blpop);
+    registry.register_fn("brpop", process_brpop);
+    registry.register_fn("blmove", process_blmove);
+    registry.register_fn("blmpop", process_blmpop);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+impl ListEnd {
+    pub fn parse(cmd: &CommandInfo, index: usize) -> Result<Self, CommandError> {
+        match cmd.arg_string(index).to_lowercase().as_str() {
+            "left" => Ok(Self::Left),
+            "right" => Ok(Self::Right),
+            _ => Err(CommandError::Syntax),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Left => "LEFT",
+            Self::Right => "RIGHT",
+        }
+    }
+
+    fn pop_command(self) -> &'static str {
+        match self {
+            Self::Left => "LPOP",
+            Self::Right => "RPOP",
+        }
+    }
+}
+
+fn process_lpush(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { push(ctx, cmd, ListEnd::Left).await })
+}
+
+fn process_rpush(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { push(ctx, cmd, ListEnd::Right).await })
+}
+
+async fn push(ctx: &mut Context, cmd: CommandInfo, end: ListEnd) -> anyhow::Result<Message> {
+    let mut store = ctx.store.lock().await;
+    let list = store.get_or_create_list(cmd.args[0].clone())?;
+    for element in &cmd.args[1..] {
+        push_element(list, end, element.clone());
+    }
+    Ok(Message::Int(list.len() as isize))
+}
+
+fn process_lpop(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { pop(ctx, cmd, ListEnd::Left).await })
+}
+
+fn process_rpop(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { pop(ctx, cmd, ListEnd::Right).await })
+}
+
+async fn pop(ctx: &mut Context, cmd: CommandInfo, end: ListEnd) -> anyhow::Result<Message> {
+    if cmd.args.len() > 2 {
+        return Err(CommandError::Syntax.into());
+    }
+    let count = match cmd.args.get(1) {
+        Some(_) => {
+            let count = cmd.parse_arg::<i64>(1)?;
+            if count < 0 {
+                return Err(CommandError::Other("ERR value is out of range, must be positive".to_string()).into());
+            }
+            Some(count as usize)
+        }
+        None => None,
+    };
+
+    let key = &cmd.args[0];
+    let mut store = ctx.store.lock().await;
+    let Some(list) = store.get_list_mut(key)? else {
+        return Ok(if count.is_some() {
+            Message::NullArray
+        } else {
+            Message::Null
+        });
+    };
+
+    let popped = pop_elements(list, end, count.unwrap_or(1));
+    store.remove_if_empty(key);
+
+    let response = match count {
+        Some(_) => Message::Array(popped.into_iter().map(Message::Bulk).collect()),
+        None => popped.into_iter().next().map_or(Message::Null, Message::Bulk),
+    };
+    Ok(response)
+}
+
+fn push_element(list: &mut VecDeque<Bytes>, end: ListEnd, element: Bytes) {
+    match end {
+        ListEnd::Left => list.push_front(element),
+        ListEnd::Right => list.push_back(element),
+    }
+}
+
+/// Removes up to `count` elements from one end of `list`, in the order they were popped.
+pub fn pop_elements(list: &mut VecDeque<Bytes>, end: ListEnd, count: usize) -> Vec<Bytes> {
+    let count = count.min(list.len());
+    match end {
+        ListEnd::Left => list.drain(..count).collect(),
+        ListEnd::Right => list.drain(list.len() - count..).rev().collect(),
+    }
+}
+
+fn process_lrange(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let start = cmd.parse_arg::<i64>(1)?;
+        let stop = cmd.parse_arg::<i64>(2)?;
+        let store = ctx.store.lock().await;
+        let Some(list) = store.get_list(&cmd.args[0])? else {
+            return Ok(Message::Array(vec![]));
+        };
+        let elements = match normalize_range(start, stop, list.len()) {
+            Some((start, stop)) => list.range(start..=stop).cloned().map(Message::Bulk).collect(),
+            None => vec![],
+        };
+        Ok(Message::Array(elements))
+    })
+}
+
+fn process_lindex(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let index = cmd.parse_arg::<i64>(1)?;
+        let store = ctx.store.lock().await;
+        let element = store
+            .get_list(&cmd.args[0])?
+            .and_then(|list| resolve_index(index, list.len()).and_then(|index| list.get(index)));
+        Ok(element.cloned().map_or(Message::Null, Message::Bulk))
+    })
+}
+
+fn process_lset(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let index = cmd.parse_arg::<i64>(1)?;
+        let mut store = ctx.store.lock().await;
+        let Some(list) = store.get_list_mut(&cmd.args[0])? else {
+            return Err(CommandError::Other("ERR no such key".to_string()).into());
+        };
+        let Some(index) = resolve_index(index, list.len()) else {
+            return Err(CommandError::Other("ERR index out of range".to_string()).into());
+        };
+        list[index] = cmd.args[2].clone();
+        Ok(Message::Simple("OK".to_string()))
+    })
+}
+
+fn process_lrem(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let count = cmd.parse_arg::<i64>(1)?;
+        let element = &cmd.args[2];
+        let key = &cmd.args[0];
+        let mut store = ctx.store.lock().await;
+        let Some(list) = store.get_list_mut(key)? else {
+            return Ok(Message::Int(0));
+        };
+
+        let limit = if count == 0 {
+            usize::MAX
+        } else {
+            count.unsigned_abs() as usize
+        };
+        // Removing from the tail is removing all but the first matches, so count them up front.
+        let mut kept = match count < 0 {
+            true => list
+                .iter()
+                .filter(|item| *item == element)
+                .count()
+                .saturating_sub(limit),
+            false => 0,
+        };
+        let mut removed = 0;
+        list.retain(|item| {
+            if item != element || removed == limit {
+                return true;
+            }
+            if kept > 0 {
+                kept -= 1;
+                return true;
+            }
+            removed += 1;
+            false
+        });
+        store.remove_if_empty(key);
+        Ok(Message::Int(removed as isize))
+    })
+}
+
+fn process_ltrim(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let start = cmd.parse_arg::<i64>(1)?;
+        let stop = cmd.parse_arg::<i64>(2)?;
+        let key = &cmd.args[0];
+        let mut store = ctx.store.lock().await;
+        if let Some(list) = store.get_list_mut(key)? {
+            match normalize_range(start, stop, list.len()) {
+                Some((start, stop)) => {
+                    list.truncate(stop + 1);
+                    list.drain(..start);
+                }
+                None => list.clear(),
+            }
+            store.remove_if_empty(key);
+        }
+        Ok(Message::Simple("OK".to_string()))
+    })
+}
+
+fn process_linsert(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let after = match cmd.arg_string(1).to_lowercase().as_str() {
+            "before" => false,
+            "after" => true,
+            _ => return Err(CommandError::Syntax.into()),
+        };
+        let mut store = ctx.store.lock().await;
+        let Some(list) = store.get_list_mut(&cmd.args[0])? else {
+            return Ok(Message::Int(0));
+        };
+        let Some(position) = list.iter().position(|element| element == &cmd.args[2]) else {
+            return Ok(Message::Int(-1));
+        };
+        let position = if after { position + 1 } else { position };
+        list.insert(position, cmd.args[3].clone());
+        Ok(Message::Int(list.len() as isize))
+    })
+}
+
+fn process_llen(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let store = ctx.store.lock().await;
+        let len = store.get_list(&cmd.args[0])?.map_or(0, |list| list.len());
+        Ok(Message::Int(len as isize))
+    })
+}
+
+fn process_lpos(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut rank = 1i64;
+        let mut count = None;
+        let mut maxlen = 0usize;
+        let mut index = 2;
+        while index < cmd.args.len() {
+            if index + 1 >= cmd.args.len() {
+                return Err(CommandError::Syntax.into());
+            }
+            let value = cmd.parse_arg::<i64>(index + 1)?;
+            match cmd.arg_string(index).to_lowercase().as_str() {
+                "rank" if value == 0 => {
+                    return Err(CommandError::Other(
+                        "ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use \
+                         negative to start from the end of the list"
+                            .to_string(),
+                    )
+                    .into())
+                }
+                "rank" => rank = value,
+                "count" if value < 0 => {
+                    return Err(CommandError::Other("ERR COUNT can't be negative".to_string()).into())
+                }
+                "count" => count = Some(value as usize),
+                "maxlen" if value < 0 => {
+                    return Err(CommandError::Other("ERR MAXLEN can't be negative".to_string()).into())
+                }
+                "maxlen" => maxlen = value as usize,
+                _ => return Err(CommandError::Syntax.into()),
+            }
+            index += 2;
+        }
+
+        let store = ctx.store.lock().await;
+        let element = &cmd.args[1];
+        let mut matches = Vec::new();
+        if let Some(list) = store.get_list(&cmd.args[0])? {
+            let limit = match count {
+                Some(0) => usize::MAX,
+                Some(count) => count,
+                None => 1,
+            };
+            let scan = if maxlen == 0 {
+                list.len()
+            } else {
+                maxlen.min(list.len())
+            };
+            let mut skip = rank.unsigned_abs() - 1;
+            let positions: Box<dyn Iterator<Item = usize>> = if rank > 0 {
+                Box::new(0..scan)
+            } else {
+                Box::new((list.len() - scan..list.len()).rev())
+            };
+            for position in positions {
+                if list[position] != element {
+                    continue;
+                }
+                if skip > 0 {
+                    skip -= 1;
+                    continue;
+                }
+                matches.push(Message::Int(position as isize));
+                if matches.len() >= limit {
+                    break;
+                }
+            }
+        }
+
+        let response = match count {
+            Some(_) => Message::Array(matches),
+            None => matches.into_iter().next().unwrap_or(Message::Null),
+        };
+        Ok(response)
+    })
+}
+
+fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+/// Pops from the first non-empty list among `keys`. Without a count the reply is `[key, element]`, as for
+/// `BLPOP`; with one it is `[key, [elements...]]`, as for `LMPOP`.
+fn pop_first(
+    keys: Vec<Bytes>,
+    end: ListEnd,
+    count: Option<usize>,
+) -> impl FnMut(&mut Store) -> anyhow::Result<Option<Served>> + Send {
+    move |store| {
+        for key in &keys {
+            let Some(list) = store.get_list_mut(key)? else {
+                continue;
+            };
+            let popped = pop_elements(list, end, count.unwrap_or(1));
+            if popped.is_empty() {
+                continue;
+            }
+            store.remove_if_empty(key);
+
+            let mut args = vec![key.clone()];
+            let value = match count {
+                Some(count) => {
+                    args.push(count.to_string().into());
+                    Message::Array(popped.into_iter().map(Message::Bulk).collect())
+                }
+                None => Message::Bulk(popped.into_iter().next().unwrap_or_default()),
+            };
+            let reply = Message::Array(vec![Message::Bulk(key.clone()), value]);
+            let propagate = CommandInfo::new(end.pop_command().to_string(), args);
+            return Ok(Some(Served::new(reply, vec![propagate])));
+        }
+        Ok(None)
+    }
+}
+
+/// Moves one element from `source` to `destination`, replying with the element.
+fn move_element(
+    source: Bytes,
+    destination: Bytes,
+    from: ListEnd,
+    to: ListEnd,
+) -> impl FnMut(&mut Store) -> anyhow::Result<Option<Served>> + Send {
+    move |store| {
+        if store.get_list(&source)?.is_none_or(|list| list.is_empty()) {
+            return Ok(None);
+        }
+        // The destination type is checked before anything is popped.
+        store.get_list(&destination)?;
+
+        let Some(element) = store
+            .get_list_mut(&source)?
+            .and_then(|list| pop_elements(list, from, 1).pop())
+        else {
+            return Ok(None);
+        };
+        store.remove_if_empty(&source);
+        push_element(store.get_or_create_list(destination.clone())?, to, element.clone());
+
+        let propagate = CommandInfo::new(
+            "LMOVE".to_string(),
+            vec![
+                source.clone(),
+                destination.clone(),
+                from.as_str().into(),
+                to.as_str().into(),
+            ],
+        );
+        Ok(Some(Served {
+            reply: Message::Bulk(element),
+            propagate: vec![propagate],
+            ready_keys: vec![destination.clone()],
+        }))
+    }
+}
+
+/// Runs a non-blocking variant once, replying with `empty` when there was nothing to pop.
+async fn serve_now(
+    ctx: &mut Context,
+    mut serve: impl FnMut(&mut Store) -> anyhow::Result<Option<Served>>,
+    empty: Message,
+) -> anyhow::Result<Message> {
+    let served = serve(&mut *ctx.store.lock().await)?;
+    Ok(match served {
+        Some(served) => {
+            ctx.propagate = Some(served.propagate);
+            served.reply
+        }
+        None => {
+            ctx.propagate = Some(Vec::new());
+            empty
+        }
+    })
+}
+
+fn process_lmove(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let from = ListEnd::parse(&cmd, 2)?;
+        let to = ListEnd::parse(&cmd, 3)?;
+        let serve = move_element(cmd.args[0].clone(), cmd.args[1].clone(), from, to);
+        serve_now(ctx, serve, Message::Null).await
+    })
+}
+
+fn process_lmpop(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = MpopArgs::parse(&cmd, 0, ListEnd::parse)?;
+        let serve = pop_first(args.keys, args.direction, Some(args.count));
+        serve_now(ctx, serve, Message::NullArray).await
+    })
+}
+
+fn process_blpop(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { blocking_pop(ctx, cmd, ListEnd::Left).await })
+}
+
+fn process_brpop(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { blocking_pop(ctx, cmd, ListEnd::Right).await })
+}
+
+async fn blocking_pop(ctx: &mut Context, cmd: CommandInfo, end: ListEnd) -> anyhow::Result<Message> {
+    let timeout = parse_timeout(&cmd, cmd.args.len() - 1)?;
+    let keys = cmd.args[..cmd.args.len() - 1].to_vec();
+    let serve = pop_first(keys.clone(), end, None);
+    block_on(ctx, keys, timeout, Message::NullArray, Box::new(serve)).await
+}
+
+fn process_blmove(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let from = ListEnd::parse(&cmd, 2)?;
+        let to = ListEnd::parse(&cmd, 3)?;
+        let timeout = parse_timeout(&cmd, 4)?;
+        let source = cmd.args[0].clone();
+        let serve = move_element(source.clone(), cmd.args[1].clone(), from, to);
+        block_on(ctx, vec![source], timeout, Message::Null, Box::new(serve)).await
+    })
+}
+
+fn process_blmpop(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let timeout = parse_timeout(&cmd, 0)?;
+        let args = MpopArgs::parse(&cmd, 1, ListEnd::parse)?;
+        let serve = pop_first(args.keys.clone(), args.direction, Some(args.count));
+        block_on(ctx, args.keys, timeout, Message::NullArray, Box::new(serve)).await
+    })
+}
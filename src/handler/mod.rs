@@ -0,0 +1,506 @@
+use crate::{
+    blocking::{block_on, Served},
+    command::{CommandArgs, CommandError, CommandInfo, HelloArgs, XAddArgs, XRangArgs, XReadArgs},
+    connection::Connection,
+    message::{Message, Protocol},
+    protocol::rdb::Rdb,
+    registry::{Context, HandlerFuture, Registry},
+    replica::{replicate_channel, ReplicaCommand},
+    store::{EntryValue, Store, StoreItem},
+    stream::{StreamId, StreamInfo, StreamType},
+};
+use anyhow::Result;
+use bytes::Bytes;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::Mutex;
+
+pub mod bitmap;
+pub mod expire;
+pub mod geo;
+pub mod hash;
+pub mod hyperloglog;
+pub mod list;
+pub mod set;
+pub mod sorted_set;
+pub mod string;
+
+pub struct Handler {}
+
+impl Handler {
+    pub async fn handle_stream(
+        mut connection: Connection,
+        store: Arc<Mutex<Store>>,
+        stream_info: Arc<StreamInfo>,
+        registry: Arc<Registry>,
+    ) -> Result<()> {
+        let mut ctx = Context::new(store, stream_info, registry.clone());
+
+        while let Some(message) = connection.read_message().await {
+            let cmd_info = Message::parse_command(message).await?;
+            let reply = Self::dispatch_watching(&mut connection, &registry, &mut ctx, cmd_info).await?;
+            connection.write_reply(reply, ctx.client.protocol).await?;
+
+            if ctx.full_resync {
+                return process_full_resync(connection, &ctx.stream_info).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Dispatches a command while watching the socket, so a blocked client that hangs up is noticed.
+    async fn dispatch_watching(
+        connection: &mut Connection,
+        registry: &Registry,
+        ctx: &mut Context,
+        cmd_info: CommandInfo,
+    ) -> Result<Message> {
+        let disconnected = ctx.client.disconnected.clone();
+        let dispatch = registry.dispatch(ctx, cmd_info);
+        tokio::pin!(dispatch);
+        let mut closed = false;
+        loop {
+            tokio::select! {
+                biased;
+                reply = &mut dispatch => return reply,
+                _ = connection.closed(), if !closed => {
+                    closed = true;
+                    disconnected.notify_one();
+                }
+            }
+        }
+    }
+}
+
+pub fn register_builtins(registry: &mut Registry) {
+    registry.register_fn("ping", process_ping);
+    registry.register_fn("echo", process_echo);
+    registry.register_fn("info", process_info);
+    registry.register_fn("replconf", process_replconf);
+    registry.register_fn("psync", process_psync);
+    registry.register_fn("wait", process_wait);
+    registry.register_fn("config", process_config);
+    registry.register_fn("keys", process_keys);
+    registry.register_fn("type", process_type);
+    registry.register_fn("del", process_del);
+    registry.register_fn("unlink", process_del);
+    registry.register_fn("exists", process_exists);
+    registry.register_fn("xadd", process_xadd);
+    registry.register_fn("xrange", process_xrange);
+    registry.register_fn("xread", process_xread);
+    registry.register_fn("hello", process_hello);
+    registry.register_fn("command", process_command);
+    registry.register_fn("save", process_save);
+    registry.register_fn("object", process_object);
+    list::register(registry);
+    hash::register(registry);
+    set::register(registry);
+    sorted_set::register(registry);
+    string::register(registry);
+    bitmap::register(registry);
+    hyperloglog::register(registry);
+    geo::register(registry);
+    expire::register(registry);
+}
+
+fn process_ping(_ctx: &mut Context, _cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { Ok(Message::Simple("PONG".to_string())) })
+}
+
+fn process_echo(_ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { Ok(Message::Bulk(cmd.args[0].clone())) })
+}
+
+fn process_info(ctx: &mut Context, _cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let stream_info = &ctx.stream_info;
+        let response = format!(
+            "# Replication\n\
+            role:{}\n\
+            connected_clients:{}\n\
+            master_replid:{}\n\
+            master_repl_offset:{}\n\
+            ",
+            stream_info.role,
+            stream_info.count_replicas().await,
+            stream_info.id,
+            stream_info.offset
+        );
+        Ok(Message::Verbatim("txt".to_string(), response.into()))
+    })
+}
+
+fn process_replconf(_ctx: &mut Context, _cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { Ok(Message::Simple("OK".to_string())) })
+}
+
+fn process_psync(ctx: &mut Context, _cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        ctx.full_resync = true;
+        Ok(Message::Simple(format!("FULLRESYNC {} 0", ctx.stream_info.id)))
+    })
+}
+
+async fn process_full_resync(mut connection: Connection, stream_info: &Arc<StreamInfo>) -> Result<()> {
+    let stream_info = stream_info.clone();
+    {
+        let empty_rdb = Rdb::get_empty();
+        let _ = connection.write_bytes(&empty_rdb).await;
+    }
+    let (repl_handle, handle) = replicate_channel(connection);
+    {
+        stream_info.repl_handles.lock().await.push(repl_handle);
+    }
+    handle.await?;
+    Ok(())
+}
+
+fn process_save(ctx: &mut Context, _cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let data = ctx.store.lock().await.export_rdb();
+        Rdb::write_file(&ctx.stream_info, &data).await?;
+        Ok(Message::Simple("OK".to_string()))
+    })
+}
+
+fn process_wait(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let timeout = cmd.parse_arg::<u64>(1)?; // first args is number of replicas
+        let stream_info = &ctx.stream_info;
+        let mut count = 0;
+
+        if ctx.store.lock().await.is_empty() {
+            let num_replicas = stream_info.repl_handles.lock().await.len();
+            return Ok(Message::Int(num_replicas as isize));
+        }
+
+        for replica in stream_info.repl_handles.lock().await.iter_mut() {
+            let message = Message::Array(vec![
+                Message::Bulk("REPLCONF".into()),
+                Message::Bulk("GETACK".into()),
+                Message::Bulk("*".into()),
+            ]);
+            replica
+                .sender
+                .send(ReplicaCommand::new(message, Some(Duration::from_millis(timeout))))
+                .await?;
+        }
+
+        for replica in stream_info.repl_handles.lock().await.iter_mut() {
+            let response = replica.receiver.recv().await.unwrap();
+            if !response.expired {
+                count += 1;
+            }
+        }
+        Ok(Message::Int(count))
+    })
+}
+
+fn process_config(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let action = cmd.arg_string(0).to_lowercase();
+        let response = match action.as_str() {
+            "get" => {
+                if cmd.args.len() < 2 {
+                    return Err(CommandError::WrongArity(format!("config|{}", action)).into());
+                }
+                let key = cmd.arg_string(1);
+                let config_value = ctx.stream_info.config.lock().await.get_value(&key);
+                if let Some(value) = config_value {
+                    Message::Map(vec![(Message::Bulk(key.into()), Message::Bulk(value.into()))])
+                } else {
+                    Message::Simple("Value not found".to_string())
+                }
+            }
+            _ => Message::Simple("Unsupported config action".to_string()),
+        };
+        Ok(response)
+    })
+}
+
+fn process_keys(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        if cmd.args[0].as_ref() != b"*" {
+            return Ok(Message::Simple("Unsupported pattern".to_string()));
+        }
+        let keys = ctx
+            .store
+            .lock()
+            .await
+            .keys()
+            .into_iter()
+            .map(Message::Bulk)
+            .collect::<Vec<_>>();
+        Ok(Message::Array(keys))
+    })
+}
+
+fn process_type(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        let item = store.get_store_item(&cmd.args[0]);
+
+        let value_type = match item {
+            Some(x) => x.value_type(),
+            None => "none".to_string(),
+        };
+
+        Ok(Message::Simple(value_type))
+    })
+}
+
+/// Serves both `DEL` and `UNLINK`: values are dropped under the store lock either way.
+fn process_del(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        let mut deleted = 0;
+        for key in &cmd.args {
+            store.remove_expired_fields(key);
+            let exists = store.contains_key(key);
+            // Expired strings are dropped too, but not counted.
+            if store.remove(key).is_some() && exists {
+                deleted += 1;
+            }
+        }
+        if deleted == 0 {
+            ctx.propagate = Some(Vec::new());
+        }
+        Ok(Message::Int(deleted))
+    })
+}
+
+fn process_exists(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        // A key given several times is counted each time.
+        let count = cmd
+            .args
+            .iter()
+            .filter(|key| {
+                store.remove_expired_fields(key);
+                store.contains_key(key)
+            })
+            .count();
+        Ok(Message::Int(count as isize))
+    })
+}
+
+fn process_object(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let subcommand = cmd.arg_string(0);
+        match subcommand.to_lowercase().as_str() {
+            "encoding" if cmd.args.len() == 2 => {
+                let mut store = ctx.store.lock().await;
+                let encoding = store.get_store_item(&cmd.args[1]).map(StoreItem::encoding);
+                Ok(encoding.map_or(Message::Null, |encoding| Message::Bulk(encoding.into())))
+            }
+            "encoding" => Err(CommandError::WrongArity("object|encoding".to_string()).into()),
+            _ => Err(CommandError::Other(format!("ERR unknown subcommand '{subcommand}'. Try OBJECT HELP.")).into()),
+        }
+    })
+}
+
+fn process_xadd(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = XAddArgs::parse(&cmd)?;
+        let mut store = ctx.store.lock().await;
+        let stream_id = store
+            .generate_stream_id(&args.key, &args.id)
+            .filter(|id| StreamId::parse(id).is_some())
+            .ok_or_else(invalid_stream_id)?;
+        store.validate_stream(&args.key, &stream_id)?;
+
+        store.set_stream(args.key, stream_id.clone(), args.data)?;
+        Ok(Message::Bulk(stream_id.into()))
+    })
+}
+
+fn invalid_stream_id() -> CommandError {
+    CommandError::Other("ERR Invalid stream ID specified as stream command argument".to_string())
+}
+
+/// Parses a stream ID given by a client, where a missing sequence number means `missing_seq`.
+fn parse_stream_id(id: &str, missing_seq: u64) -> Result<StreamId, CommandError> {
+    let id = match id.contains('-') {
+        true => id.to_string(),
+        false => format!("{id}-{missing_seq}"),
+    };
+    StreamId::parse(&id).ok_or_else(invalid_stream_id)
+}
+
+fn get_xrange_start(id: &str) -> Result<Option<StreamId>, CommandError> {
+    if id == "-" {
+        return Ok(None);
+    }
+    parse_stream_id(id, 0).map(Some)
+}
+
+fn get_xrange_end(id: &str) -> Result<Option<StreamId>, CommandError> {
+    if id == "+" {
+        return Ok(None);
+    }
+    parse_stream_id(id, u64::MAX).map(Some)
+}
+
+fn process_xrange(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = XRangArgs::parse(&cmd)?;
+        let start = get_xrange_start(&args.start)?;
+        let end = get_xrange_end(&args.end)?;
+
+        let response = match ctx.store.lock().await.get_stream_range(&args.key, start, end) {
+            Some(stream) => stream.to_message(),
+            None => Message::Error("ERR Unable to parse start ID".to_string()),
+        };
+        Ok(response)
+    })
+}
+
+fn process_xread(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = XReadArgs::parse(&cmd)?;
+        let mut requests: Vec<(Bytes, StreamId)> = Vec::with_capacity(args.requests.len());
+        {
+            let mut store = ctx.store.lock().await;
+            for (key, id) in args.requests {
+                let stream_id = if id == "$" {
+                    store
+                        .get_lastest_stream(&key)
+                        .cloned()
+                        .unwrap_or_else(|| StreamId::from("0-0"))
+                } else {
+                    parse_stream_id(&id, 0)?
+                };
+                requests.push((key, stream_id));
+            }
+        }
+
+        let protocol = ctx.client.protocol;
+        let keys: Vec<Bytes> = requests.iter().map(|(key, _)| key.clone()).collect();
+        let read = move |store: &mut Store| -> Result<Option<Served>> {
+            let messages: Vec<(Message, Message)> = requests
+                .iter()
+                .filter_map(|(key, id)| {
+                    let stream = store.get_stream_after_id(key, id)?;
+                    Some((Message::Bulk(key.clone()), stream.to_message()))
+                })
+                .collect();
+            if messages.is_empty() {
+                return Ok(None);
+            }
+            let response = if protocol == Protocol::Resp3 {
+                Message::Map(messages)
+            } else {
+                let messages = messages
+                    .into_iter()
+                    .map(|(key, stream)| Message::Array(vec![key, stream]))
+                    .collect();
+                Message::Array(messages)
+            };
+            Ok(Some(Served::new(response, Vec::new())))
+        };
+
+        match args.block {
+            Some(duration) => {
+                let timeout = (!duration.is_zero()).then_some(duration);
+                block_on(ctx, keys, timeout, Message::Null, Box::new(read)).await
+            }
+            None => {
+                let served = read(&mut *ctx.store.lock().await)?;
+                Ok(served.map_or(Message::Null, |served| served.reply))
+            }
+        }
+    })
+}
+
+fn process_hello(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = HelloArgs::parse(&cmd)?;
+        if let Some(protover) = args.protover {
+            let protocol = match protover.parse::<i64>().ok() {
+                Some(2) => Protocol::Resp2,
+                Some(3) => Protocol::Resp3,
+                Some(_) => return Ok(Message::Error("NOPROTO unsupported protocol version".to_string())),
+                None => {
+                    return Ok(Message::Error(
+                        "ERR Protocol version is not an integer or out of range".to_string(),
+                    ))
+                }
+            };
+            ctx.client.protocol = protocol;
+        }
+        if args.setname.is_some() {
+            ctx.client.name = args.setname;
+        }
+
+        let role = match ctx.stream_info.role {
+            StreamType::Master => "master",
+            StreamType::Replica(_) => "replica",
+        };
+        Ok(Message::Map(vec![
+            (Message::Bulk("server".into()), Message::Bulk("redis".into())),
+            (Message::Bulk("version".into()), Message::Bulk("7.4.0".into())),
+            (
+                Message::Bulk("proto".into()),
+                Message::Int(ctx.client.protocol.version()),
+            ),
+            (Message::Bulk("id".into()), Message::Int(ctx.client.id as isize)),
+            (Message::Bulk("mode".into()), Message::Bulk("standalone".into())),
+            (Message::Bulk("role".into()), Message::Bulk(role.into())),
+            (Message::Bulk("modules".into()), Message::Array(vec![])),
+        ]))
+    })
+}
+
+fn process_command(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let registry = &ctx.registry;
+        let response = match CommandArgs::parse(&cmd)? {
+            CommandArgs::List => Message::Array(registry.specs().map(|spec| spec.to_message()).collect()),
+            CommandArgs::Count => Message::Int(registry.len() as isize),
+            CommandArgs::Info(names) if names.is_empty() => {
+                Message::Array(registry.specs().map(|spec| spec.to_message()).collect())
+            }
+            CommandArgs::Info(names) => Message::Array(
+                names
+                    .iter()
+                    .map(|name| {
+                        registry
+                            .get(name)
+                            .map_or(Message::Null, |handler| handler.spec().to_message())
+                    })
+                    .collect(),
+            ),
+            CommandArgs::Docs(names) => {
+                let docs = if names.is_empty() {
+                    registry
+                        .specs()
+                        .map(|spec| (Message::Bulk(spec.name.into()), spec.docs_message()))
+                        .collect()
+                } else {
+                    names
+                        .iter()
+                        .filter_map(|name| registry.get(name))
+                        .map(|handler| (Message::Bulk(handler.spec().name.into()), handler.spec().docs_message()))
+                        .collect()
+                };
+                Message::Map(docs)
+            }
+            CommandArgs::GetKeys(cmd_info) => match registry.lookup(&cmd_info) {
+                Ok(handler) => {
+                    let keys = cmd_info.keys(handler.spec());
+                    if keys.is_empty() {
+                        Message::Error("ERR The command has no key arguments".to_string())
+                    } else {
+                        Message::Array(keys.into_iter().map(Message::Bulk).collect())
+                    }
+                }
+                Err(CommandError::Unknown(..)) => Message::Error("ERR Invalid command specified".to_string()),
+                Err(CommandError::WrongArity(_)) => {
+                    Message::Error("ERR Invalid number of arguments specified for command".to_string())
+                }
+                Err(err) => Message::Error(err.to_string()),
+            },
+        };
+        Ok(response)
+    })
+}
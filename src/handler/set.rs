@@ -0,0 +1,316 @@
+use crate::{
+    command::{CommandError, CommandInfo, InterCardArgs, ScanArgs},
+    message::Message,
+    registry::{Context, HandlerFuture, Registry},
+    set::Set,
+    store::{Store, StoreItem},
+    utils::{glob_match, scan},
+};
+use bytes::Bytes;
+use rand::seq::SliceRandom;
+
+pub fn register(registry: &mut Registry) {
+    registry.register_fn("sadd", process_sadd);
+    registry.register_fn("srem", process_srem);
+    registry.register_fn("smembers", process_smembers);
+    registry.register_fn("sismember", process_sismember);
+    registry.register_fn("smismember", process_smismember);
+    registry.register_fn("scard", process_scard);
+    registry.register_fn("spop", process_spop);
+    registry.register_fn("srandmember", process_srandmember);
+    registry.register_fn("sinter", process_sinter);
+    registry.register_fn("sunion", process_sunion);
+    registry.register_fn("sdiff", process_sdiff);
+    registry.register_fn("sinterstore", process_sinterstore);
+    registry.register_fn("sunionstore", process_sunionstore);
+    registry.register_fn("sdiffstore", process_sdiffstore);
+    registry.register_fn("sintercard", process_sintercard);
+    registry.register_fn("smove", process_smove);
+    registry.register_fn("sscan", process_sscan);
+}
+
+fn members_message(members: Vec<Bytes>) -> Message {
+    Message::Set(members.into_iter().map(Message::Bulk).collect())
+}
+
+fn process_sadd(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let mut store = ctx.store.lock().await;
+        let set = store.get_or_create_set(cmd.args[0].clone())?;
+        let added = cmd.args[1..]
+            .iter()
+            .filter(|member| set.insert((*member).clone()))
+            .count();
+        Ok(Message::Int(added as isize))
+    })
+}
+
+fn process_srem(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let key = &cmd.args[0];
+        let mut store = ctx.store.lock().await;
+        let Some(set) = store.get_set_mut(key)? else {
+            return Ok(Message::Int(0));
+        };
+        let removed = cmd.args[1..].iter().filter(|member| set.remove(member)).count();
+        store.remove_if_empty(key);
+        Ok(Message::Int(removed as isize))
+    })
+}
+
+fn process_smembers(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let store = ctx.store.lock().await;
+        let members = store.get_set(&cmd.args[0])?.map(Set::members).unwrap_or_default();
+        Ok(members_message(members))
+    })
+}
+
+fn process_sismember(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let store = ctx.store.lock().await;
+        let is_member = store
+            .get_set(&cmd.args[0])?
+            .is_some_and(|set| set.contains(&cmd.args[1]));
+        Ok(Message::Int(is_member as isize))
+    })
+}
+
+fn process_smismember(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let store = ctx.store.lock().await;
+        let set = store.get_set(&cmd.args[0])?;
+        let replies = cmd.args[1..]
+            .iter()
+            .map(|member| Message::Int(set.is_some_and(|set| set.contains(member)) as isize))
+            .collect();
+        Ok(Message::Array(replies))
+    })
+}
+
+fn process_scard(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let store = ctx.store.lock().await;
+        let len = store.get_set(&cmd.args[0])?.map_or(0, Set::len);
+        Ok(Message::Int(len as isize))
+    })
+}
+
+fn process_spop(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let count = match cmd.args.get(1) {
+            Some(_) if cmd.args.len() > 2 => return Err(CommandError::Syntax.into()),
+            Some(_) => {
+                let count = cmd.parse_arg::<i64>(1)?;
+                if count < 0 {
+                    return Err(CommandError::Other("ERR value is out of range, must be positive".to_string()).into());
+                }
+                Some(count as usize)
+            }
+            None => None,
+        };
+
+        let key = &cmd.args[0];
+        let mut store = ctx.store.lock().await;
+        let popped = match store.get_set_mut(key)? {
+            Some(set) => set.pop(count.unwrap_or(1)),
+            None => Vec::new(),
+        };
+        store.remove_if_empty(key);
+
+        // Which members were popped is random, so replicas are told exactly which ones to remove.
+        ctx.propagate = Some(if popped.is_empty() {
+            Vec::new()
+        } else {
+            let mut args = vec![key.clone()];
+            args.extend(popped.iter().cloned());
+            vec![CommandInfo::new("SREM".to_string(), args)]
+        });
+
+        let response = match count {
+            Some(_) => members_message(popped),
+            None => popped.into_iter().next().map_or(Message::Null, Message::Bulk),
+        };
+        Ok(response)
+    })
+}
+
+fn process_srandmember(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let count = match cmd.args.get(1) {
+            Some(_) if cmd.args.len() > 2 => return Err(CommandError::Syntax.into()),
+            Some(_) => Some(cmd.parse_random_count(1)?),
+            None => None,
+        };
+
+        let store = ctx.store.lock().await;
+        let set = store.get_set(&cmd.args[0])?;
+        let Some(count) = count else {
+            let member = set.and_then(|set| set.random_members(1).pop());
+            return Ok(member.map_or(Message::Null, Message::Bulk));
+        };
+        let Some(set) = set else {
+            return Ok(Message::Array(vec![]));
+        };
+
+        let members = if count >= 0 {
+            set.random_members(count as usize)
+        } else {
+            // A negative count allows the same member to be returned several times.
+            let members = set.members();
+            let mut rng = rand::thread_rng();
+            (0..count.unsigned_abs())
+                .filter_map(|_| members.choose(&mut rng).cloned())
+                .collect()
+        };
+        Ok(Message::Array(members.into_iter().map(Message::Bulk).collect()))
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// Combines the sets stored at `keys`, treating missing keys as empty sets.
+fn combine(store: &Store, keys: &[Bytes], op: SetOp) -> anyhow::Result<Vec<Bytes>> {
+    let sets = keys
+        .iter()
+        .map(|key| store.get_set(key))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let members = match op {
+        SetOp::Inter => intersect(&sets, usize::MAX),
+        SetOp::Union => {
+            let union: Set = sets.iter().flatten().flat_map(|set| set.members()).collect();
+            union.members()
+        }
+        SetOp::Diff => match sets.split_first() {
+            Some((Some(first), others)) => first
+                .members()
+                .into_iter()
+                .filter(|member| !others.iter().flatten().any(|set| set.contains(member)))
+                .collect(),
+            _ => Vec::new(),
+        },
+    };
+    Ok(members)
+}
+
+/// Intersects `sets`, stopping once `limit` members have been found.
+fn intersect(sets: &[Option<&Set>], limit: usize) -> Vec<Bytes> {
+    let Some(mut sets) = sets.iter().copied().collect::<Option<Vec<&Set>>>() else {
+        return Vec::new();
+    };
+    // Walking the smallest set keeps the number of lookups down.
+    sets.sort_by_key(|set| set.len());
+    let Some((smallest, others)) = sets.split_first() else {
+        return Vec::new();
+    };
+    smallest
+        .members()
+        .into_iter()
+        .filter(|member| others.iter().all(|set| set.contains(member)))
+        .take(limit)
+        .collect()
+}
+
+fn process_sinter(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { combine_command(ctx, cmd, SetOp::Inter).await })
+}
+
+fn process_sunion(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { combine_command(ctx, cmd, SetOp::Union).await })
+}
+
+fn process_sdiff(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { combine_command(ctx, cmd, SetOp::Diff).await })
+}
+
+async fn combine_command(ctx: &mut Context, cmd: CommandInfo, op: SetOp) -> anyhow::Result<Message> {
+    let store = ctx.store.lock().await;
+    Ok(members_message(combine(&store, &cmd.args, op)?))
+}
+
+fn process_sinterstore(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { combine_store(ctx, cmd, SetOp::Inter).await })
+}
+
+fn process_sunionstore(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { combine_store(ctx, cmd, SetOp::Union).await })
+}
+
+fn process_sdiffstore(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { combine_store(ctx, cmd, SetOp::Diff).await })
+}
+
+/// Stores the combination of the source sets at the destination, replacing whatever it held.
+async fn combine_store(ctx: &mut Context, cmd: CommandInfo, op: SetOp) -> anyhow::Result<Message> {
+    let destination = cmd.args[0].clone();
+    let mut store = ctx.store.lock().await;
+    let set: Set = combine(&store, &cmd.args[1..], op)?.into_iter().collect();
+    let len = set.len();
+    if set.is_empty() {
+        store.remove(&destination);
+    } else {
+        store.set_item(destination, StoreItem::Set(set));
+    }
+    Ok(Message::Int(len as isize))
+}
+
+fn process_sintercard(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = InterCardArgs::parse(&cmd)?;
+        let store = ctx.store.lock().await;
+        let sets = args
+            .keys
+            .iter()
+            .map(|key| store.get_set(key))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Message::Int(intersect(&sets, args.limit).len() as isize))
+    })
+}
+
+fn process_smove(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let (source, destination, member) = (&cmd.args[0], &cmd.args[1], &cmd.args[2]);
+        let mut store = ctx.store.lock().await;
+        // Both keys must hold sets before anything changes.
+        store.get_set(destination)?;
+        let Some(set) = store.get_set_mut(source)? else {
+            return Ok(Message::Int(0));
+        };
+        if source == destination {
+            return Ok(Message::Int(set.contains(member) as isize));
+        }
+        if !set.remove(member) {
+            return Ok(Message::Int(0));
+        }
+        store.remove_if_empty(source);
+        store.get_or_create_set(destination.clone())?.insert(member.clone());
+        Ok(Message::Int(1))
+    })
+}
+
+fn process_sscan(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = ScanArgs::parse(&cmd, 1)?;
+        if args.novalues {
+            return Err(CommandError::Syntax.into());
+        }
+        let store = ctx.store.lock().await;
+        let members = store.get_set(&cmd.args[0])?.map(Set::members).unwrap_or_default();
+
+        let (cursor, batch) = scan(members.iter().map(|member| (member, ())), args.cursor, args.count);
+        let elements = batch
+            .into_iter()
+            .filter(|(member, _)| args.pattern.as_ref().is_none_or(|pattern| glob_match(pattern, member)))
+            .map(|(member, _)| Message::Bulk(member.clone()))
+            .collect();
+        Ok(Message::Array(vec![
+            Message::Bulk(cursor.to_string().into()),
+            Message::Array(elements),
+        ]))
+    })
+}
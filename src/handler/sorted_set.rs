@@ -0,0 +1,919 @@
+use crate::{
+    blocking::{block_on, parse_timeout, Served},
+    command::{CommandError, CommandInfo, InterCardArgs, MpopArgs, ScanArgs},
+    message::{format_double, Message, Protocol},
+    registry::{Context, HandlerFuture, Registry},
+    sorted_set::{LexBound, SortedSet},
+    store::{Store, StoreItem},
+    utils::{glob_match, normalize_range, scan},
+};
+use bytes::Bytes;
+use rand::seq::SliceRandom;
+use std::{collections::HashMap, ops::Bound};
+
+pub fn register(registry: &mut Registry) {
+    registry.register_fn("zadd", process_zadd);
+    registry.register_fn("zincrby", process_zincrby);
+    registry.register_fn("zrem", process_zrem);
+    registry.register_fn("zcard", process_zcard);
+    registry.register_fn("zscore", process_zscore);
+    registry.register_fn("zmscore", process_zmscore);
+    registry.register_fn("zrank", process_zrank);
+    registry.register_fn("zrevrank", process_zrevrank);
+    registry.register_fn("zcount", process_zcount);
+    registry.register_fn("zlexcount", process_zlexcount);
+    registry.register_fn("zrange", process_zrange);
+    registry.register_fn("zrangestore", process_zrangestore);
+    registry.register_fn("zrevrange", process_zrevrange);
+    registry.register_fn("zrangebyscore", process_zrangebyscore);
+    registry.register_fn("zrevrangebyscore", process_zrevrangebyscore);
+    registry.register_fn("zrangebylex", process_zrangebylex);
+    registry.register_fn("zrevrangebylex", process_zrevrangebylex);
+    registry.register_fn("zpopmin", process_zpopmin);
+    registry.register_fn("zpopmax", process_zpopmax);
+    registry.register_fn("zrandmember", process_zrandmember);
+    registry.register_fn("zscan", process_zscan);
+    registry.register_fn("zunion", process_zunion);
+    registry.register_fn("zinter", process_zinter);
+    registry.register_fn("zdiff", process_zdiff);
+    registry.register_fn("zunionstore", process_zunionstore);
+    registry.register_fn("zinterstore", process_zinterstore);
+    registry.register_fn("zdiffstore", process_zdiffstore);
+    registry.register_fn("zintercard", process_zintercard);
+    registry.register_fn("zmpop", process_zmpop);
+    registry.register_fn("bzpopmin", process_bzpopmin);
+    registry.register_fn("bzpopmax", process_bzpopmax);
+    registry.register_fn("bzmpop", process_bzmpop);
+}
+
+fn not_a_float() -> CommandError {
+    CommandError::Other("ERR value is not a valid float".to_string())
+}
+
+fn not_a_number() -> CommandError {
+    CommandError::Other("ERR resulting score is not a number (NaN)".to_string())
+}
+
+/// Parses a score, accepting `inf` and `-inf` but never NaN.
+fn parse_score(arg: &[u8]) -> Option<f64> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|arg| arg.parse::<f64>().ok())
+        .filter(|score| !score.is_nan())
+}
+
+/// Parses one end of a score range, where a leading `(` makes it exclusive.
+fn parse_score_bound(arg: &[u8]) -> Result<Bound<f64>, CommandError> {
+    let error = || CommandError::Other("ERR min or max is not a float".to_string());
+    match arg.strip_prefix(b"(") {
+        Some(score) => parse_score(score).map(Bound::Excluded).ok_or_else(error),
+        None => parse_score(arg).map(Bound::Included).ok_or_else(error),
+    }
+}
+
+/// Parses one end of a lexicographical range: `-`, `+`, or a string prefixed by `[` or `(`.
+fn parse_lex_bound(arg: &Bytes) -> Result<LexBound, CommandError> {
+    match arg.first() {
+        Some(b'-') if arg.len() == 1 => Ok(LexBound::Min),
+        Some(b'+') if arg.len() == 1 => Ok(LexBound::Max),
+        Some(b'[') => Ok(LexBound::Included(arg.slice(1..))),
+        Some(b'(') => Ok(LexBound::Excluded(arg.slice(1..))),
+        _ => Err(CommandError::Other(
+            "ERR min or max not valid string range item".to_string(),
+        )),
+    }
+}
+
+/// Replies with members and, when asked for, their scores: flat under RESP2 and as pairs under RESP3.
+fn scored_reply(protocol: Protocol, items: Vec<(Bytes, f64)>, with_scores: bool) -> Message {
+    let replies = if !with_scores {
+        items.into_iter().map(|(member, _)| Message::Bulk(member)).collect()
+    } else if protocol == Protocol::Resp3 {
+        items
+            .into_iter()
+            .map(|(member, score)| Message::Array(vec![Message::Bulk(member), Message::Double(score)]))
+            .collect()
+    } else {
+        items
+            .into_iter()
+            .flat_map(|(member, score)| [Message::Bulk(member), Message::Double(score)])
+            .collect()
+    };
+    Message::Array(replies)
+}
+
+#[derive(Debug, Default)]
+struct ZAddArgs {
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+    ch: bool,
+    incr: bool,
+    elements: Vec<(f64, Bytes)>,
+}
+
+impl ZAddArgs {
+    fn parse(cmd: &CommandInfo) -> Result<Self, CommandError> {
+        let mut args = Self::default();
+        let mut index = 1;
+        while index < cmd.args.len() {
+            let flag = match cmd.arg_string(index).to_lowercase().as_str() {
+                "nx" => &mut args.nx,
+                "xx" => &mut args.xx,
+                "gt" => &mut args.gt,
+                "lt" => &mut args.lt,
+                "ch" => &mut args.ch,
+                "incr" => &mut args.incr,
+                _ => break,
+            };
+            *flag = true;
+            index += 1;
+        }
+
+        let pairs = &cmd.args[index..];
+        if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+            return Err(CommandError::Syntax);
+        }
+        if args.nx && args.xx {
+            return Err(CommandError::Other(
+                "ERR XX and NX options at the same time are not compatible".to_string(),
+            ));
+        }
+        if (args.gt && args.lt) || (args.nx && (args.gt || args.lt)) {
+            return Err(CommandError::Other(
+                "ERR GT, LT, and/or NX options at the same time are not compatible".to_string(),
+            ));
+        }
+        if args.incr && pairs.len() > 2 {
+            return Err(CommandError::Other(
+                "ERR INCR option supports a single increment-element pair".to_string(),
+            ));
+        }
+        args.elements = pairs
+            .chunks(2)
+            .map(|pair| Ok((parse_score(&pair[0]).ok_or_else(not_a_float)?, pair[1].clone())))
+            .collect::<Result<_, CommandError>>()?;
+        Ok(args)
+    }
+}
+
+pub(super) fn process_zadd(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = ZAddArgs::parse(&cmd)?;
+        let key = &cmd.args[0];
+        let mut store = ctx.store.lock().await;
+        let zset = store.get_or_create_sorted_set(key.clone())?;
+
+        let (mut added, mut changed) = (0, 0);
+        let mut incremented = None;
+        for (score, member) in args.elements {
+            let current = zset.score(&member);
+            if (args.nx && current.is_some()) || (args.xx && current.is_none()) {
+                continue;
+            }
+            let score = match (args.incr, current) {
+                (true, Some(current)) => current + score,
+                _ => score,
+            };
+            if score.is_nan() {
+                store.remove_if_empty(key);
+                return Err(not_a_number().into());
+            }
+            if let Some(current) = current {
+                if (args.gt && score <= current) || (args.lt && score >= current) {
+                    continue;
+                }
+                if score != current {
+                    zset.insert(member, score);
+                    changed += 1;
+                }
+            } else {
+                zset.insert(member, score);
+                added += 1;
+            }
+            incremented = Some(score);
+        }
+        // A ZADD that added nothing, such as one with XX on a missing key, must not leave an empty key behind.
+        store.remove_if_empty(key);
+
+        if args.incr {
+            return Ok(incremented.map_or(Message::Null, Message::Double));
+        }
+        Ok(Message::Int(if args.ch { added + changed } else { added }))
+    })
+}
+
+fn process_zincrby(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let increment = parse_score(&cmd.args[1]).ok_or_else(not_a_float)?;
+        let mut store = ctx.store.lock().await;
+        let zset = store.get_or_create_sorted_set(cmd.args[0].clone())?;
+        let score = zset.score(&cmd.args[2]).unwrap_or(0.0) + increment;
+        if score.is_nan() {
+            store.remove_if_empty(&cmd.args[0]);
+            return Err(not_a_number().into());
+        }
+        zset.insert(cmd.args[2].clone(), score);
+        Ok(Message::Double(score))
+    })
+}
+
+fn process_zrem(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let key = &cmd.args[0];
+        let mut store = ctx.store.lock().await;
+        let Some(zset) = store.get_sorted_set_mut(key)? else {
+            return Ok(Message::Int(0));
+        };
+        let removed = cmd.args[1..].iter().filter(|member| zset.remove(member)).count();
+        store.remove_if_empty(key);
+        Ok(Message::Int(removed as isize))
+    })
+}
+
+fn process_zcard(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let store = ctx.store.lock().await;
+        let len = store.get_sorted_set(&cmd.args[0])?.map_or(0, SortedSet::len);
+        Ok(Message::Int(len as isize))
+    })
+}
+
+fn process_zscore(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let store = ctx.store.lock().await;
+        let score = store
+            .get_sorted_set(&cmd.args[0])?
+            .and_then(|zset| zset.score(&cmd.args[1]));
+        Ok(score.map_or(Message::Null, Message::Double))
+    })
+}
+
+fn process_zmscore(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let store = ctx.store.lock().await;
+        let zset = store.get_sorted_set(&cmd.args[0])?;
+        let scores = cmd.args[1..]
+            .iter()
+            .map(|member| {
+                zset.and_then(|zset| zset.score(member))
+                    .map_or(Message::Null, Message::Double)
+            })
+            .collect();
+        Ok(Message::Array(scores))
+    })
+}
+
+fn process_zrank(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { rank(ctx, cmd, false).await })
+}
+
+fn process_zrevrank(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { rank(ctx, cmd, true).await })
+}
+
+async fn rank(ctx: &mut Context, cmd: CommandInfo, rev: bool) -> anyhow::Result<Message> {
+    let with_score = match cmd.args.get(2) {
+        Some(arg) if cmd.args.len() == 3 && arg.eq_ignore_ascii_case(b"withscore") => true,
+        Some(_) => return Err(CommandError::Syntax.into()),
+        None => false,
+    };
+    let store = ctx.store.lock().await;
+    let Some(zset) = store.get_sorted_set(&cmd.args[0])? else {
+        return Ok(Message::Null);
+    };
+    let (Some(rank), Some(score)) = (zset.rank(&cmd.args[1]), zset.score(&cmd.args[1])) else {
+        return Ok(Message::Null);
+    };
+    let rank = if rev { zset.len() - 1 - rank } else { rank };
+    if with_score {
+        return Ok(Message::Array(vec![
+            Message::Int(rank as isize),
+            Message::Double(score),
+        ]));
+    }
+    Ok(Message::Int(rank as isize))
+}
+
+fn process_zcount(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let min = parse_score_bound(&cmd.args[1])?;
+        let max = parse_score_bound(&cmd.args[2])?;
+        let store = ctx.store.lock().await;
+        let count = store.get_sorted_set(&cmd.args[0])?.map_or(0, |zset| {
+            let (start, end) = zset.score_span(min, max);
+            end - start
+        });
+        Ok(Message::Int(count as isize))
+    })
+}
+
+fn process_zlexcount(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let min = parse_lex_bound(&cmd.args[1])?;
+        let max = parse_lex_bound(&cmd.args[2])?;
+        let store = ctx.store.lock().await;
+        let count = store.get_sorted_set(&cmd.args[0])?.map_or(0, |zset| {
+            let (start, end) = zset.lex_span(&min, &max);
+            end - start
+        });
+        Ok(Message::Int(count as isize))
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RangeKind {
+    Rank,
+    Score,
+    Lex,
+}
+
+#[derive(Debug)]
+enum RangeBy {
+    Rank(i64, i64),
+    Score(Bound<f64>, Bound<f64>),
+    Lex(LexBound, LexBound),
+}
+
+/// The selection made by `ZRANGE`, `ZRANGESTORE` and the older range commands they subsume.
+#[derive(Debug)]
+struct RangeArgs {
+    by: RangeBy,
+    rev: bool,
+    limit: Option<(i64, i64)>,
+    with_scores: bool,
+}
+
+impl RangeArgs {
+    /// Parses `<start> <stop> [options]` from `index`. The older commands pass the kind and direction their name
+    /// implies, which also rules out the BYSCORE, BYLEX and REV options. `ZRANGESTORE` sets `store` to rule out
+    /// WITHSCORES.
+    fn parse(
+        cmd: &CommandInfo,
+        index: usize,
+        implied: Option<(RangeKind, bool)>,
+        store: bool,
+    ) -> Result<Self, CommandError> {
+        let (mut kind, mut rev) = implied.unwrap_or((RangeKind::Rank, false));
+        let mut limit = None;
+        let mut with_scores = false;
+
+        let mut option = index + 2;
+        while option < cmd.args.len() {
+            match cmd.arg_string(option).to_lowercase().as_str() {
+                "byscore" if implied.is_none() => kind = RangeKind::Score,
+                "bylex" if implied.is_none() => kind = RangeKind::Lex,
+                "rev" if implied.is_none() => rev = true,
+                "withscores" if !store => with_scores = true,
+                "limit" if option + 2 < cmd.args.len() => {
+                    limit = Some((cmd.parse_arg::<i64>(option + 1)?, cmd.parse_arg::<i64>(option + 2)?));
+                    option += 2;
+                }
+                _ => return Err(CommandError::Syntax),
+            }
+            option += 1;
+        }
+
+        if limit.is_some() && kind == RangeKind::Rank {
+            return Err(CommandError::Other(
+                "ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX".to_string(),
+            ));
+        }
+        if with_scores && kind == RangeKind::Lex {
+            return Err(CommandError::Other(
+                "ERR syntax error, WITHSCORES not supported in combination with BYLEX".to_string(),
+            ));
+        }
+
+        // Reversed score and lex ranges are written from the high end down.
+        let (low, high) = match (kind, rev) {
+            (RangeKind::Rank, _) | (_, false) => (&cmd.args[index], &cmd.args[index + 1]),
+            (_, true) => (&cmd.args[index + 1], &cmd.args[index]),
+        };
+        let by = match kind {
+            RangeKind::Rank => RangeBy::Rank(cmd.parse_arg(index)?, cmd.parse_arg(index + 1)?),
+            RangeKind::Score => RangeBy::Score(parse_score_bound(low)?, parse_score_bound(high)?),
+            RangeKind::Lex => RangeBy::Lex(parse_lex_bound(low)?, parse_lex_bound(high)?),
+        };
+        Ok(Self {
+            by,
+            rev,
+            limit,
+            with_scores,
+        })
+    }
+
+    /// Members of `zset` in the selection, in reply order.
+    fn select(&self, zset: &SortedSet) -> Vec<(Bytes, f64)> {
+        let (start, end) = match &self.by {
+            RangeBy::Rank(start, stop) => {
+                let Some((start, stop)) = normalize_range(*start, *stop, zset.len()) else {
+                    return Vec::new();
+                };
+                return zset.range_by_rank(start, stop, self.rev);
+            }
+            RangeBy::Score(min, max) => zset.score_span(*min, *max),
+            RangeBy::Lex(min, max) => zset.lex_span(min, max),
+        };
+        let (offset, count) = match self.limit {
+            // A negative offset selects nothing and a negative count means every remaining member.
+            Some((offset, _)) if offset < 0 => return Vec::new(),
+            Some((offset, count)) => (offset as usize, if count < 0 { usize::MAX } else { count as usize }),
+            None => (0, usize::MAX),
+        };
+        let len = (end - start).saturating_sub(offset).min(count);
+        if len == 0 {
+            return Vec::new();
+        }
+        // Only the selected members are visited, starting from the rank of the first one in reply order.
+        let first = match self.rev {
+            true => zset.len() - end + offset,
+            false => start + offset,
+        };
+        zset.range_by_rank(first, first + len - 1, self.rev)
+    }
+}
+
+fn process_zrange(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { range(ctx, cmd, None).await })
+}
+
+fn process_zrevrange(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { range(ctx, cmd, Some((RangeKind::Rank, true))).await })
+}
+
+fn process_zrangebyscore(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { range(ctx, cmd, Some((RangeKind::Score, false))).await })
+}
+
+fn process_zrevrangebyscore(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { range(ctx, cmd, Some((RangeKind::Score, true))).await })
+}
+
+fn process_zrangebylex(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { range(ctx, cmd, Some((RangeKind::Lex, false))).await })
+}
+
+fn process_zrevrangebylex(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { range(ctx, cmd, Some((RangeKind::Lex, true))).await })
+}
+
+async fn range(ctx: &mut Context, cmd: CommandInfo, implied: Option<(RangeKind, bool)>) -> anyhow::Result<Message> {
+    let args = RangeArgs::parse(&cmd, 1, implied, false)?;
+    let store = ctx.store.lock().await;
+    let items = store
+        .get_sorted_set(&cmd.args[0])?
+        .map(|zset| args.select(zset))
+        .unwrap_or_default();
+    Ok(scored_reply(ctx.client.protocol, items, args.with_scores))
+}
+
+fn process_zrangestore(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = RangeArgs::parse(&cmd, 2, None, true)?;
+        let destination = cmd.args[0].clone();
+        let mut store = ctx.store.lock().await;
+        let zset: SortedSet = store
+            .get_sorted_set(&cmd.args[1])?
+            .map(|zset| args.select(zset))
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        let len = zset.len();
+        if zset.is_empty() {
+            store.remove(&destination);
+        } else {
+            store.set_item(destination, StoreItem::SortedSet(zset));
+        }
+        Ok(Message::Int(len as isize))
+    })
+}
+
+fn process_zpopmin(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { pop(ctx, cmd, false).await })
+}
+
+fn process_zpopmax(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { pop(ctx, cmd, true).await })
+}
+
+async fn pop(ctx: &mut Context, cmd: CommandInfo, highest: bool) -> anyhow::Result<Message> {
+    let count = match cmd.args.get(1) {
+        Some(_) if cmd.args.len() > 2 => return Err(CommandError::Syntax.into()),
+        Some(_) => {
+            let count = cmd.parse_arg::<i64>(1)?;
+            if count < 0 {
+                return Err(CommandError::Other("ERR value is out of range, must be positive".to_string()).into());
+            }
+            Some(count as usize)
+        }
+        None => None,
+    };
+
+    let key = &cmd.args[0];
+    let mut store = ctx.store.lock().await;
+    let popped = match store.get_sorted_set_mut(key)? {
+        Some(zset) => zset.pop(count.unwrap_or(1), highest),
+        None => Vec::new(),
+    };
+    store.remove_if_empty(key);
+
+    // Without a count the reply is a single flat pair, whatever the protocol.
+    match count {
+        Some(_) => Ok(scored_reply(ctx.client.protocol, popped, true)),
+        None => Ok(scored_reply(Protocol::Resp2, popped, true)),
+    }
+}
+
+fn process_zrandmember(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let count = match cmd.args.get(1) {
+            Some(_) => Some(cmd.parse_random_count(1)?),
+            None => None,
+        };
+        let with_scores = match cmd.args.get(2) {
+            Some(arg) if cmd.args.len() == 3 && arg.eq_ignore_ascii_case(b"withscores") => true,
+            Some(_) => return Err(CommandError::Syntax.into()),
+            None => false,
+        };
+
+        let store = ctx.store.lock().await;
+        let zset = store.get_sorted_set(&cmd.args[0])?;
+        let Some(count) = count else {
+            let member = zset.and_then(|zset| zset.random_members(1).pop());
+            return Ok(member.map_or(Message::Null, |(member, _)| Message::Bulk(member)));
+        };
+        let Some(zset) = zset else {
+            return Ok(Message::Array(vec![]));
+        };
+
+        let picked = if count >= 0 {
+            zset.random_members(count as usize)
+        } else {
+            // A negative count allows the same member to be returned several times.
+            let members: Vec<_> = zset.iter().collect();
+            let mut rng = rand::thread_rng();
+            (0..count.unsigned_abs())
+                .filter_map(|_| {
+                    members
+                        .choose(&mut rng)
+                        .map(|(member, score)| ((*member).clone(), *score))
+                })
+                .collect()
+        };
+        Ok(scored_reply(ctx.client.protocol, picked, with_scores))
+    })
+}
+
+fn process_zscan(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = ScanArgs::parse(&cmd, 1)?;
+        if args.novalues {
+            return Err(CommandError::Syntax.into());
+        }
+        let store = ctx.store.lock().await;
+        let Some(zset) = store.get_sorted_set(&cmd.args[0])? else {
+            return Ok(Message::Array(vec![Message::Bulk("0".into()), Message::Array(vec![])]));
+        };
+
+        let (cursor, batch) = scan(zset.iter(), args.cursor, args.count);
+        let mut elements = Vec::new();
+        for (member, score) in batch {
+            if args
+                .pattern
+                .as_ref()
+                .is_some_and(|pattern| !glob_match(pattern, member))
+            {
+                continue;
+            }
+            elements.push(Message::Bulk(member.clone()));
+            elements.push(Message::Bulk(format_double(score).into()));
+        }
+        Ok(Message::Array(vec![
+            Message::Bulk(cursor.to_string().into()),
+            Message::Array(elements),
+        ]))
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ZSetOp {
+    Union,
+    Inter,
+    Diff,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, current: f64, score: f64) -> f64 {
+        match self {
+            // Like Redis, a sum of opposite infinities counts as zero rather than NaN.
+            Self::Sum => zero_if_nan(current + score),
+            Self::Min => current.min(score),
+            Self::Max => current.max(score),
+        }
+    }
+}
+
+fn zero_if_nan(score: f64) -> f64 {
+    if score.is_nan() {
+        0.0
+    } else {
+        score
+    }
+}
+
+/// Arguments of `ZUNION`, `ZINTER`, `ZDIFF` and their STORE forms.
+#[derive(Debug)]
+struct CombineArgs {
+    keys: Vec<Bytes>,
+    weights: Vec<f64>,
+    aggregate: Aggregate,
+    with_scores: bool,
+}
+
+impl CombineArgs {
+    /// Parses `numkeys key [key ...] [WEIGHTS weight ...] [AGGREGATE SUM|MIN|MAX] [WITHSCORES]` from `index`. ZDIFF
+    /// takes neither weights nor an aggregate and the STORE forms take no WITHSCORES.
+    fn parse(cmd: &CommandInfo, index: usize, op: ZSetOp, store: bool) -> Result<Self, CommandError> {
+        let numkeys = cmd.parse_arg::<i64>(index)?;
+        if numkeys < 1 {
+            return Err(CommandError::Other(format!(
+                "ERR at least 1 input key is needed for '{}' command",
+                cmd.name.to_lowercase()
+            )));
+        }
+        let numkeys = numkeys as usize;
+        if numkeys > cmd.args.len() - index - 1 {
+            return Err(CommandError::Syntax);
+        }
+
+        let mut args = Self {
+            keys: cmd.args[index + 1..=index + numkeys].to_vec(),
+            weights: vec![1.0; numkeys],
+            aggregate: Aggregate::Sum,
+            with_scores: false,
+        };
+        let mut option = index + numkeys + 1;
+        while option < cmd.args.len() {
+            match cmd.arg_string(option).to_lowercase().as_str() {
+                "weights" if op != ZSetOp::Diff && option + numkeys < cmd.args.len() => {
+                    for (weight, arg) in args.weights.iter_mut().zip(&cmd.args[option + 1..=option + numkeys]) {
+                        *weight = parse_score(arg)
+                            .ok_or_else(|| CommandError::Other("ERR weight value is not a float".to_string()))?;
+                    }
+                    option += numkeys + 1;
+                }
+                "aggregate" if op != ZSetOp::Diff && option + 1 < cmd.args.len() => {
+                    args.aggregate = match cmd.arg_string(option + 1).to_lowercase().as_str() {
+                        "sum" => Aggregate::Sum,
+                        "min" => Aggregate::Min,
+                        "max" => Aggregate::Max,
+                        _ => return Err(CommandError::Syntax),
+                    };
+                    option += 2;
+                }
+                "withscores" if !store => {
+                    args.with_scores = true;
+                    option += 1;
+                }
+                _ => return Err(CommandError::Syntax),
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Loads the members of the sorted set or plain set at `key`, giving plain set members a score of 1. A missing key
+/// is an empty input.
+fn load_input(store: &Store, key: &[u8]) -> anyhow::Result<HashMap<Bytes, f64>> {
+    let members = match store.get_sorted_set(key) {
+        Ok(zset) => zset
+            .map(|zset| zset.iter().map(|(member, score)| (member.clone(), score)).collect())
+            .unwrap_or_default(),
+        Err(_) => store
+            .get_set(key)?
+            .map(|set| set.members().into_iter().map(|member| (member, 1.0)).collect())
+            .unwrap_or_default(),
+    };
+    Ok(members)
+}
+
+/// Combines the inputs named in `args`, returning the result in sorted set order.
+fn combine(store: &Store, args: &CombineArgs, op: ZSetOp) -> anyhow::Result<SortedSet> {
+    let inputs = args
+        .keys
+        .iter()
+        .map(|key| load_input(store, key))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut inputs = inputs.into_iter().zip(args.weights.iter().copied());
+    let Some((first, weight)) = inputs.next() else {
+        return Ok(SortedSet::new());
+    };
+
+    let mut result: HashMap<Bytes, f64> = match op {
+        ZSetOp::Diff => first,
+        _ => first
+            .into_iter()
+            .map(|(member, score)| (member, zero_if_nan(score * weight)))
+            .collect(),
+    };
+    for (input, weight) in inputs {
+        match op {
+            ZSetOp::Union => {
+                for (member, score) in input {
+                    let score = zero_if_nan(score * weight);
+                    result
+                        .entry(member)
+                        .and_modify(|current| *current = args.aggregate.apply(*current, score))
+                        .or_insert(score);
+                }
+            }
+            ZSetOp::Inter => {
+                result.retain(|member, current| match input.get(member) {
+                    Some(score) => {
+                        *current = args.aggregate.apply(*current, zero_if_nan(score * weight));
+                        true
+                    }
+                    None => false,
+                });
+            }
+            ZSetOp::Diff => result.retain(|member, _| !input.contains_key(member)),
+        }
+    }
+    Ok(result.into_iter().collect())
+}
+
+fn process_zunion(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { combine_command(ctx, cmd, ZSetOp::Union).await })
+}
+
+fn process_zinter(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { combine_command(ctx, cmd, ZSetOp::Inter).await })
+}
+
+fn process_zdiff(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { combine_command(ctx, cmd, ZSetOp::Diff).await })
+}
+
+async fn combine_command(ctx: &mut Context, cmd: CommandInfo, op: ZSetOp) -> anyhow::Result<Message> {
+    let args = CombineArgs::parse(&cmd, 0, op, false)?;
+    let store = ctx.store.lock().await;
+    let zset = combine(&store, &args, op)?;
+    let items = zset.iter().map(|(member, score)| (member.clone(), score)).collect();
+    Ok(scored_reply(ctx.client.protocol, items, args.with_scores))
+}
+
+fn process_zunionstore(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { combine_store(ctx, cmd, ZSetOp::Union).await })
+}
+
+fn process_zinterstore(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { combine_store(ctx, cmd, ZSetOp::Inter).await })
+}
+
+fn process_zdiffstore(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { combine_store(ctx, cmd, ZSetOp::Diff).await })
+}
+
+/// Stores the combination of the inputs at the destination, replacing whatever it held.
+async fn combine_store(ctx: &mut Context, cmd: CommandInfo, op: ZSetOp) -> anyhow::Result<Message> {
+    let args = CombineArgs::parse(&cmd, 1, op, true)?;
+    let destination = cmd.args[0].clone();
+    let mut store = ctx.store.lock().await;
+    let zset = combine(&store, &args, op)?;
+    let len = zset.len();
+    if zset.is_empty() {
+        store.remove(&destination);
+    } else {
+        store.set_item(destination, StoreItem::SortedSet(zset));
+    }
+    Ok(Message::Int(len as isize))
+}
+
+fn process_zintercard(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = InterCardArgs::parse(&cmd)?;
+        let store = ctx.store.lock().await;
+        let mut inputs = args
+            .keys
+            .iter()
+            .map(|key| load_input(&store, key))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        // Walking the smallest input keeps the number of lookups down.
+        inputs.sort_by_key(HashMap::len);
+        let Some((smallest, others)) = inputs.split_first() else {
+            return Ok(Message::Int(0));
+        };
+        let count = smallest
+            .keys()
+            .filter(|member| others.iter().all(|input| input.contains_key(*member)))
+            .take(args.limit)
+            .count();
+        Ok(Message::Int(count as isize))
+    })
+}
+
+/// Parses the `MIN` or `MAX` of `ZMPOP` and `BZMPOP`, returning whether members are popped from the high end.
+fn parse_min_max(cmd: &CommandInfo, index: usize) -> Result<bool, CommandError> {
+    match cmd.arg_string(index).to_lowercase().as_str() {
+        "min" => Ok(false),
+        "max" => Ok(true),
+        _ => Err(CommandError::Syntax),
+    }
+}
+
+/// Pops from the first non-empty sorted set among `keys`, propagating the equivalent `ZPOPMIN` or `ZPOPMAX`.
+/// Without a count the reply is `[key, member, score]`, as for `BZPOPMIN`; with one it is `[key, [[member, score],
+/// ...]]`, as for `ZMPOP`.
+fn pop_first(
+    keys: Vec<Bytes>,
+    highest: bool,
+    count: Option<usize>,
+) -> impl FnMut(&mut Store) -> anyhow::Result<Option<Served>> + Send {
+    move |store| {
+        for key in &keys {
+            let Some(zset) = store.get_sorted_set_mut(key)? else {
+                continue;
+            };
+            let popped = zset.pop(count.unwrap_or(1), highest);
+            if popped.is_empty() {
+                continue;
+            }
+            store.remove_if_empty(key);
+
+            let mut args = vec![key.clone()];
+            let reply = match count {
+                Some(count) => {
+                    args.push(count.to_string().into());
+                    let pairs = popped
+                        .into_iter()
+                        .map(|(member, score)| Message::Array(vec![Message::Bulk(member), Message::Double(score)]))
+                        .collect();
+                    Message::Array(vec![Message::Bulk(key.clone()), Message::Array(pairs)])
+                }
+                None => {
+                    let (member, score) = popped.into_iter().next().unwrap_or_default();
+                    Message::Array(vec![
+                        Message::Bulk(key.clone()),
+                        Message::Bulk(member),
+                        Message::Double(score),
+                    ])
+                }
+            };
+            let name = if highest { "ZPOPMAX" } else { "ZPOPMIN" };
+            let propagate = CommandInfo::new(name.to_string(), args);
+            return Ok(Some(Served::new(reply, vec![propagate])));
+        }
+        Ok(None)
+    }
+}
+
+fn process_zmpop(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = MpopArgs::parse(&cmd, 0, parse_min_max)?;
+        let mut serve = pop_first(args.keys, args.direction, Some(args.count));
+        let served = serve(&mut *ctx.store.lock().await)?;
+        Ok(match served {
+            Some(served) => {
+                ctx.propagate = Some(served.propagate);
+                served.reply
+            }
+            None => {
+                ctx.propagate = Some(Vec::new());
+                Message::NullArray
+            }
+        })
+    })
+}
+
+fn process_bzpopmin(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { blocking_pop(ctx, cmd, false).await })
+}
+
+fn process_bzpopmax(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { blocking_pop(ctx, cmd, true).await })
+}
+
+async fn blocking_pop(ctx: &mut Context, cmd: CommandInfo, highest: bool) -> anyhow::Result<Message> {
+    let timeout = parse_timeout(&cmd, cmd.args.len() - 1)?;
+    let keys = cmd.args[..cmd.args.len() - 1].to_vec();
+    let serve = pop_first(keys.clone(), highest, None);
+    block_on(ctx, keys, timeout, Message::NullArray, Box::new(serve)).await
+}
+
+fn process_bzmpop(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let timeout = parse_timeout(&cmd, 0)?;
+        let args = MpopArgs::parse(&cmd, 1, parse_min_max)?;
+        let serve = pop_first(args.keys.clone(), args.direction, Some(args.count));
+        block_on(ctx, args.keys, timeout, Message::NullArray, Box::new(serve)).await
+    })
+}
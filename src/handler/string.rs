@@ -0,0 +1,553 @@
+use crate::{
+    command::{CommandError, CommandInfo, Expiry, SetArgs, SetCondition},
+    message::Message,
+    registry::{Context, HandlerFuture, Registry},
+    store::{Entry, StringValue, MAX_STRING_LEN},
+    utils::format_float,
+};
+use bytes::Bytes;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn register(registry: &mut Registry) {
+    registry.register_fn("get", process_get);
+    registry.register_fn("set", process_set);
+    registry.register_fn("setnx", process_setnx);
+    registry.register_fn("setex", process_setex);
+    registry.register_fn("psetex", process_psetex);
+    registry.register_fn("getset", process_getset);
+    registry.register_fn("getdel", process_getdel);
+    registry.register_fn("getex", process_getex);
+    registry.register_fn("mget", process_mget);
+    registry.register_fn("mset", process_mset);
+    registry.register_fn("msetnx", process_msetnx);
+    registry.register_fn("incr", process_incr);
+    registry.register_fn("decr", process_decr);
+    registry.register_fn("incrby", process_incrby);
+    registry.register_fn("decrby", process_decrby);
+    registry.register_fn("incrbyfloat", process_incrbyfloat);
+    registry.register_fn("append", process_append);
+    registry.register_fn("strlen", process_strlen);
+    registry.register_fn("getrange", process_getrange);
+    registry.register_fn("setrange", process_setrange);
+    registry.register_fn("lcs", process_lcs);
+}
+
+fn bulk_or_null(value: Option<Bytes>) -> Message {
+    value.map_or(Message::Null, Message::Bulk)
+}
+
+/// The `SET` that recreates a string on a replica. Expiry times are sent as absolute timestamps, so that replicas
+/// expire the key at the same instant however late they apply the command.
+fn set_command(key: &Bytes, value: Bytes, expiry_at: Option<SystemTime>) -> CommandInfo {
+    let mut args = vec![key.clone(), value];
+    if let Some(expiry_at) = expiry_at {
+        let millis = expiry_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        args.extend(["PXAT".into(), millis.to_string().into()]);
+    }
+    CommandInfo::new("SET".to_string(), args)
+}
+
+fn process_get(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let store = ctx.store.lock().await;
+        let value = store.get_string(&cmd.args[0])?.map(|entry| entry.value.to_bytes());
+        Ok(bulk_or_null(value))
+    })
+}
+
+fn process_set(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = SetArgs::parse(&cmd)?;
+        let (written, old) = set_string(ctx, &cmd.args[0], &cmd.args[1], args.condition, args.expiry, args.get).await?;
+        Ok(match (written, args.get) {
+            (_, true) => bulk_or_null(old),
+            (true, false) => Message::Simple("OK".to_string()),
+            (false, false) => Message::Null,
+        })
+    })
+}
+
+/// Writes `value` at `key` when `condition` allows it. Returns whether the key was written, and its previous value
+/// when `get` is set, which also makes keys of other types an error.
+async fn set_string(
+    ctx: &mut Context,
+    key: &Bytes,
+    value: &Bytes,
+    condition: SetCondition,
+    expiry: Option<Expiry>,
+    get: bool,
+) -> anyhow::Result<(bool, Option<Bytes>)> {
+    let mut store = ctx.store.lock().await;
+    let old = match get {
+        true => store.get_string(key)?.map(|entry| entry.value.to_bytes()),
+        false => None,
+    };
+    let allowed = match condition {
+        SetCondition::Always => true,
+        SetCondition::IfMissing => !store.contains_key(key),
+        SetCondition::IfExists => store.contains_key(key),
+    };
+    if !allowed {
+        ctx.propagate = Some(Vec::new());
+        return Ok((false, old));
+    }
+
+    let current = store.expiry(key);
+    let expiry_at = expiry.and_then(|expiry| expiry.resolve(current));
+    store.set_kv(key.clone(), Entry::new(value.clone()))?;
+    store.set_expiry(key, expiry_at);
+    ctx.propagate = Some(vec![set_command(key, value.clone(), expiry_at)]);
+    Ok((true, old))
+}
+
+fn process_setnx(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let (written, _) = set_string(ctx, &cmd.args[0], &cmd.args[1], SetCondition::IfMissing, None, false).await?;
+        Ok(Message::Int(written as isize))
+    })
+}
+
+fn process_setex(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let expiry = Expiry::parse(&cmd, "ex", 1)?;
+        set_string(
+            ctx,
+            &cmd.args[0],
+            &cmd.args[2],
+            SetCondition::Always,
+            Some(expiry),
+            false,
+        )
+        .await?;
+        Ok(Message::Simple("OK".to_string()))
+    })
+}
+
+fn process_psetex(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let expiry = Expiry::parse(&cmd, "px", 1)?;
+        set_string(
+            ctx,
+            &cmd.args[0],
+            &cmd.args[2],
+            SetCondition::Always,
+            Some(expiry),
+            false,
+        )
+        .await?;
+        Ok(Message::Simple("OK".to_string()))
+    })
+}
+
+fn process_getset(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let (_, old) = set_string(ctx, &cmd.args[0], &cmd.args[1], SetCondition::Always, None, true).await?;
+        Ok(bulk_or_null(old))
+    })
+}
+
+fn process_getdel(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let key = &cmd.args[0];
+        let mut store = ctx.store.lock().await;
+        let value = store.get_string(key)?.map(|entry| entry.value.to_bytes());
+        if value.is_some() {
+            store.remove(key);
+        }
+        Ok(bulk_or_null(value))
+    })
+}
+
+fn process_getex(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let option = cmd.arg_string(1).to_lowercase();
+        let expiry = match (cmd.args.len(), option.as_str()) {
+            (1, _) => None,
+            (2, "persist") => Some(Expiry::Persist),
+            (3, "ex" | "px" | "exat" | "pxat") => Some(Expiry::parse(&cmd, &option, 2)?),
+            _ => return Err(CommandError::Syntax.into()),
+        };
+
+        let key = &cmd.args[0];
+        let mut store = ctx.store.lock().await;
+        let Some(entry) = store.get_string(key)? else {
+            return Ok(Message::Null);
+        };
+        let value = entry.value.to_bytes();
+        match expiry {
+            Some(expiry) => {
+                let expiry_at = expiry.resolve(store.expiry(key));
+                store.set_expiry(key, expiry_at);
+                ctx.propagate = Some(vec![set_command(key, value.clone(), expiry_at)]);
+            }
+            // Without options GETEX is a plain read.
+            None => ctx.propagate = Some(Vec::new()),
+        }
+        Ok(Message::Bulk(value))
+    })
+}
+
+fn process_mget(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let store = ctx.store.lock().await;
+        // Keys holding other types read as missing instead of failing the whole command.
+        let values = cmd
+            .args
+            .iter()
+            .map(|key| bulk_or_null(store.get_kv(key).map(|entry| entry.value.to_bytes())))
+            .collect();
+        Ok(Message::Array(values))
+    })
+}
+
+/// Checks that the arguments of `MSET` and `MSETNX` are key, value pairs.
+fn check_pairs(cmd: &CommandInfo) -> Result<(), CommandError> {
+    if !cmd.args.len().is_multiple_of(2) {
+        return Err(CommandError::WrongArity(cmd.name.to_lowercase()));
+    }
+    Ok(())
+}
+
+fn process_mset(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        check_pairs(&cmd)?;
+        let mut store = ctx.store.lock().await;
+        for pair in cmd.args.chunks(2) {
+            store.set_kv(pair[0].clone(), Entry::new(pair[1].clone()))?;
+        }
+        Ok(Message::Simple("OK".to_string()))
+    })
+}
+
+fn process_msetnx(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        check_pairs(&cmd)?;
+        let mut store = ctx.store.lock().await;
+        // Either every key is set or none is, so a single existing key fails the command.
+        if cmd.args.iter().step_by(2).any(|key| store.contains_key(key)) {
+            ctx.propagate = Some(Vec::new());
+            return Ok(Message::Int(0));
+        }
+        for pair in cmd.args.chunks(2) {
+            store.set_kv(pair[0].clone(), Entry::new(pair[1].clone()))?;
+        }
+        Ok(Message::Int(1))
+    })
+}
+
+fn process_incr(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { increment(ctx, &cmd.args[0], 1).await })
+}
+
+fn process_decr(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move { increment(ctx, &cmd.args[0], -1).await })
+}
+
+fn process_incrby(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let by = cmd.parse_arg::<i64>(1)?;
+        increment(ctx, &cmd.args[0], by).await
+    })
+}
+
+fn process_decrby(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let by = cmd.parse_arg::<i64>(1)?;
+        // The smallest integer has no positive counterpart to add instead.
+        let by = by
+            .checked_neg()
+            .ok_or_else(|| CommandError::Other("ERR decrement would overflow".to_string()))?;
+        increment(ctx, &cmd.args[0], by).await
+    })
+}
+
+/// Adds `by` to the integer at `key`, starting from zero when the key is missing. The expiry is left untouched.
+async fn increment(ctx: &mut Context, key: &Bytes, by: i64) -> anyhow::Result<Message> {
+    let mut store = ctx.store.lock().await;
+    let entry = store.get_string_mut(key)?;
+    let current = match &entry {
+        Some(entry) => entry.value.as_int().ok_or(CommandError::NotInteger)?,
+        None => 0,
+    };
+    let value = current
+        .checked_add(by)
+        .ok_or_else(|| CommandError::Other("ERR increment or decrement would overflow".to_string()))?;
+    match entry {
+        Some(entry) => entry.value = StringValue::Int(value),
+        None => store.set_kv(key.clone(), Entry::new(value.to_string().into()))?,
+    }
+    Ok(Message::Int(value as isize))
+}
+
+fn process_incrbyfloat(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let key = &cmd.args[0];
+        let increment = cmd
+            .parse_arg::<f64>(1)
+            .map_err(|_| CommandError::Other("ERR value is not a valid float".to_string()))?;
+        let mut store = ctx.store.lock().await;
+        let entry = store.get_string_mut(key)?;
+        let current = match &entry {
+            Some(entry) => std::str::from_utf8(&entry.value.as_bytes())
+                .ok()
+                .and_then(|value| value.parse::<f64>().ok())
+                .ok_or_else(|| CommandError::Other("ERR value is not a valid float".to_string()))?,
+            None => 0.0,
+        };
+        let sum = current + increment;
+        if !sum.is_finite() {
+            return Err(CommandError::Other("ERR increment would produce NaN or Infinity".to_string()).into());
+        }
+        let value: Bytes = format_float(sum).into();
+        match entry {
+            Some(entry) => entry.value = value.clone().into(),
+            None => store.set_kv(key.clone(), Entry::new(value.clone()))?,
+        }
+
+        // Replicas get the resulting value so float rounding cannot make them drift.
+        ctx.propagate = Some(vec![CommandInfo::new(
+            "SET".to_string(),
+            vec![key.clone(), value.clone(), "KEEPTTL".into()],
+        )]);
+        Ok(Message::Bulk(value))
+    })
+}
+
+fn process_append(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let (key, suffix) = (&cmd.args[0], &cmd.args[1]);
+        let mut store = ctx.store.lock().await;
+        let len = match store.get_string_mut(key)? {
+            Some(entry) => {
+                check_string_len(entry.value.len() + suffix.len())?;
+                entry.value.append(suffix)
+            }
+            None => {
+                store.set_kv(key.clone(), Entry::new(suffix.clone()))?;
+                suffix.len()
+            }
+        };
+        Ok(Message::Int(len as isize))
+    })
+}
+
+fn check_string_len(len: usize) -> Result<(), CommandError> {
+    if len > MAX_STRING_LEN {
+        return Err(CommandError::Other(
+            "ERR string exceeds maximum allowed size (proto-max-bulk-len)".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn process_strlen(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let store = ctx.store.lock().await;
+        let len = store.get_string(&cmd.args[0])?.map_or(0, |entry| entry.value.len());
+        Ok(Message::Int(len as isize))
+    })
+}
+
+fn process_getrange(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let start = cmd.parse_arg::<i64>(1)?;
+        let end = cmd.parse_arg::<i64>(2)?;
+        let store = ctx.store.lock().await;
+        let value = store
+            .get_string(&cmd.args[0])?
+            .map(|entry| entry.value.range(start, end))
+            .unwrap_or_default();
+        Ok(Message::Bulk(value))
+    })
+}
+
+fn process_setrange(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let (key, value) = (&cmd.args[0], &cmd.args[2]);
+        let offset = cmd.parse_arg::<i64>(1)?;
+        let offset =
+            usize::try_from(offset).map_err(|_| CommandError::Other("ERR offset is out of range".to_string()))?;
+        let mut store = ctx.store.lock().await;
+        let entry = store.get_string_mut(key)?;
+        // An empty value changes nothing, not even a missing key.
+        if value.is_empty() {
+            ctx.propagate = Some(Vec::new());
+            return Ok(Message::Int(entry.map_or(0, |entry| entry.value.len()) as isize));
+        }
+        check_string_len(offset.saturating_add(value.len()))?;
+
+        let len = match entry {
+            Some(entry) => entry.value.set_range(offset, value),
+            None => {
+                let mut string = StringValue::Raw(Vec::new());
+                let len = string.set_range(offset, value);
+                store.set_kv(key.clone(), Entry { value: string })?;
+                len
+            }
+        };
+        Ok(Message::Int(len as isize))
+    })
+}
+
+/// Options of `LCS`.
+#[derive(Debug, Default)]
+struct LcsArgs {
+    len: bool,
+    idx: bool,
+    min_match_len: usize,
+    with_match_len: bool,
+}
+
+impl LcsArgs {
+    fn parse(cmd: &CommandInfo) -> Result<Self, CommandError> {
+        let mut args = Self::default();
+        let mut index = 2;
+        while index < cmd.args.len() {
+            match cmd.arg_string(index).to_lowercase().as_str() {
+                "len" => args.len = true,
+                "idx" => args.idx = true,
+                "withmatchlen" => args.with_match_len = true,
+                "minmatchlen" if index + 1 < cmd.args.len() => {
+                    index += 1;
+                    // Negative lengths are as good as no minimum at all.
+                    args.min_match_len = cmd.parse_arg::<i64>(index)?.max(0) as usize;
+                }
+                _ => return Err(CommandError::Syntax),
+            }
+            index += 1;
+        }
+        if args.len && args.idx {
+            return Err(CommandError::Other(
+                "ERR If you want both the length and indexes, please just use IDX.".to_string(),
+            ));
+        }
+        Ok(args)
+    }
+}
+
+/// A run of bytes common to both strings, as inclusive ranges into each.
+#[derive(Debug)]
+struct LcsMatch {
+    a: (usize, usize),
+    b: (usize, usize),
+}
+
+impl LcsMatch {
+    fn len(&self) -> usize {
+        self.a.1 - self.a.0 + 1
+    }
+}
+
+/// Longest common subsequence of `a` and `b`, together with the contiguous runs it is made of. Like Redis, runs are
+/// listed from the end of the strings backwards.
+fn lcs(a: &[u8], b: &[u8]) -> (Vec<u8>, Vec<LcsMatch>) {
+    // `table[i][j]` is the length of the LCS of the first `i` bytes of `a` and the first `j` bytes of `b`.
+    let width = b.len() + 1;
+    let mut table = vec![0u32; (a.len() + 1) * width];
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            table[i * width + j] = if a[i - 1] == b[j - 1] {
+                table[(i - 1) * width + j - 1] + 1
+            } else {
+                table[(i - 1) * width + j].max(table[i * width + j - 1])
+            };
+        }
+    }
+
+    let mut result = vec![0; table[a.len() * width + b.len()] as usize];
+    let mut idx = result.len();
+    let mut matches = Vec::new();
+    let mut current: Option<LcsMatch> = None;
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 && j > 0 {
+        let mut emit = false;
+        if a[i - 1] == b[j - 1] {
+            result[idx - 1] = a[i - 1];
+            match &mut current {
+                // The bytes are contiguous with the run being tracked, so it grows backwards.
+                Some(run) if run.a.0 == i && run.b.0 == j => {
+                    run.a.0 -= 1;
+                    run.b.0 -= 1;
+                }
+                Some(_) => emit = true,
+                None => {
+                    current = Some(LcsMatch {
+                        a: (i - 1, i - 1),
+                        b: (j - 1, j - 1),
+                    })
+                }
+            }
+            if current.as_ref().is_some_and(|run| run.a.0 == 0 || run.b.0 == 0) {
+                emit = true;
+            }
+            idx -= 1;
+            i -= 1;
+            j -= 1;
+        } else {
+            if table[(i - 1) * width + j] > table[i * width + j - 1] {
+                i -= 1;
+            } else {
+                j -= 1;
+            }
+            emit = current.is_some();
+        }
+        if emit {
+            if let Some(run) = current.take() {
+                matches.push(run);
+            }
+        }
+    }
+    (result, matches)
+}
+
+fn process_lcs(ctx: &mut Context, cmd: CommandInfo) -> HandlerFuture<'_> {
+    Box::pin(async move {
+        let args = LcsArgs::parse(&cmd)?;
+        let store = ctx.store.lock().await;
+        let mut values = Vec::with_capacity(2);
+        for key in &cmd.args[..2] {
+            let value = store
+                .get_string(key)
+                .map_err(|_| CommandError::Other("ERR The specified keys must contain string values".to_string()))?;
+            values.push(value.map(|entry| entry.value.to_bytes()).unwrap_or_default());
+        }
+        let (a, b) = (&values[0], &values[1]);
+        if (a.len() + 1)
+            .saturating_mul(b.len() + 1)
+            .saturating_mul(size_of::<u32>())
+            > MAX_STRING_LEN
+        {
+            return Err(CommandError::Other(
+                "ERR Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len".to_string(),
+            )
+            .into());
+        }
+
+        let (common, matches) = lcs(a, b);
+        if args.len {
+            return Ok(Message::Int(common.len() as isize));
+        }
+        if !args.idx {
+            return Ok(Message::Bulk(common.into()));
+        }
+
+        let range = |(start, end): (usize, usize)| {
+            Message::Array(vec![Message::Int(start as isize), Message::Int(end as isize)])
+        };
+        let matches = matches
+            .into_iter()
+            .filter(|run| run.len() >= args.min_match_len)
+            .map(|run| {
+                let mut reply = vec![range(run.a), range(run.b)];
+                if args.with_match_len {
+                    reply.push(Message::Int(run.len() as isize));
+                }
+                Message::Array(reply)
+            })
+            .collect();
+        Ok(Message::Map(vec![
+            (Message::Bulk("matches".into()), Message::Array(matches)),
+            (Message::Bulk("len".into()), Message::Int(common.len() as isize)),
+        ]))
+    })
+}
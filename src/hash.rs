@@ -0,0 +1,130 @@
+use bytes::Bytes;
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::SystemTime,
+};
+
+/// The value of a hash key: a map of fields to values, where each field may carry its own expiry time.
+///
+/// Expired fields are invisible to every read even before they are reclaimed with [`Hash::remove_expired`].
+#[derive(Debug, Clone, Default)]
+pub struct Hash {
+    fields: HashMap<Bytes, Bytes>,
+    expires: HashMap<Bytes, SystemTime>,
+    /// The same expiry times ordered by time, so the fields that are due are found without looking at the others.
+    deadlines: BTreeSet<(SystemTime, Bytes)>,
+}
+
+impl Hash {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        let now = SystemTime::now();
+        let expired = self.deadlines.iter().take_while(|(expiry, _)| *expiry <= now).count();
+        self.fields.len() - expired
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&Bytes> {
+        self.fields.get(field).filter(|_| !self.is_expired(field))
+    }
+
+    pub fn contains(&self, field: &[u8]) -> bool {
+        self.get(field).is_some()
+    }
+
+    /// Sets `field` to `value`, clearing any expiry it had. Returns whether the field is new.
+    pub fn insert(&mut self, field: Bytes, value: Bytes) -> bool {
+        let is_new = !self.contains(&field);
+        self.clear_expiry(&field);
+        self.fields.insert(field, value);
+        is_new
+    }
+
+    /// Updates the value of `field` without touching its expiry, as increments do.
+    pub fn update(&mut self, field: Bytes, value: Bytes) {
+        if !self.contains(&field) {
+            self.clear_expiry(&field);
+        }
+        self.fields.insert(field, value);
+    }
+
+    /// Removes `field`, returning whether it existed.
+    pub fn remove(&mut self, field: &[u8]) -> bool {
+        let existed = self.contains(field);
+        self.fields.remove(field);
+        self.clear_expiry(field);
+        existed
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, &Bytes)> {
+        let now = SystemTime::now();
+        self.fields
+            .iter()
+            .filter(move |(field, _)| self.expires.get(*field).is_none_or(|expiry| *expiry > now))
+    }
+
+    pub fn expiry(&self, field: &[u8]) -> Option<SystemTime> {
+        self.expires.get(field).copied().filter(|_| self.contains(field))
+    }
+
+    pub fn set_expiry(&mut self, field: Bytes, expiry: SystemTime) {
+        self.clear_expiry(&field);
+        self.deadlines.insert((expiry, field.clone()));
+        self.expires.insert(field, expiry);
+    }
+
+    /// Clears the expiry of `field`, returning whether it had one.
+    pub fn persist(&mut self, field: &[u8]) -> bool {
+        self.contains(field) && self.clear_expiry(field)
+    }
+
+    pub fn has_expiring_fields(&self) -> bool {
+        !self.expires.is_empty()
+    }
+
+    /// Drops every field whose expiry time has passed and returns their names.
+    pub fn remove_expired(&mut self) -> Vec<Bytes> {
+        let now = SystemTime::now();
+        let mut expired = Vec::new();
+        while self.deadlines.first().is_some_and(|(expiry, _)| *expiry <= now) {
+            let Some((_, field)) = self.deadlines.pop_first() else {
+                break;
+            };
+            self.fields.remove(&field);
+            self.expires.remove(&field);
+            expired.push(field);
+        }
+        expired
+    }
+
+    /// Removes the expiry of `field` from both indexes, returning whether it had one.
+    fn clear_expiry(&mut self, field: &[u8]) -> bool {
+        let Some((field, expiry)) = self.expires.remove_entry(field) else {
+            return false;
+        };
+        self.deadlines.remove(&(expiry, field));
+        true
+    }
+
+    fn is_expired(&self, field: &[u8]) -> bool {
+        self.expires
+            .get(field)
+            .is_some_and(|expiry| *expiry <= SystemTime::now())
+    }
+}
+
+impl FromIterator<(Bytes, Bytes)> for Hash {
+    fn from_iter<I: IntoIterator<Item = (Bytes, Bytes)>>(iter: I) -> Self {
+        Self {
+            fields: iter.into_iter().collect(),
+            expires: HashMap::new(),
+            deadlines: BTreeSet::new(),
+        }
+    }
+}
@@ -0,0 +1,478 @@
+//! HyperLogLog cardinality estimation, stored in string values with exactly the layout Redis uses, so values move
+//! between this server and Redis through RDB files unchanged.
+//!
+//! A value is a 16-byte header followed by 2^14 registers in one of two encodings:
+//!
+//! - Dense: 6 bits per register, packed least significant bit first.
+//! - Sparse: a sequence of run-length opcodes, used while most registers are still zero. `ZERO` (`00xxxxxx`) and
+//!   `XZERO` (`01xxxxxx yyyyyyyy`) cover runs of up to 64 and 16384 zero registers, and `VAL` (`1vvvvvxx`) covers a
+//!   run of up to 4 registers holding the same value of up to 32.
+//!
+//! The header holds the `HYLL` magic, the encoding, and a cached cardinality whose most significant bit marks it stale.
+
+use rand::Rng;
+
+const MAGIC: &[u8] = b"HYLL";
+const HEADER_LEN: usize = 16;
+const ENCODING: usize = 4;
+const CARD: std::ops::Range<usize> = 8..16;
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+
+/// Bits of the hash that select a register.
+const P: u32 = 14;
+/// Bits of the hash whose run of trailing zeros is counted.
+const Q: u32 = 64 - P;
+const REGISTERS: usize = 1 << P;
+const REGISTER_BITS: usize = 6;
+const REGISTER_MAX: u8 = (1 << REGISTER_BITS) - 1;
+const DENSE_LEN: usize = HEADER_LEN + (REGISTERS * REGISTER_BITS).div_ceil(8);
+
+/// Sparse values longer than this are turned dense, Redis' default `hll-sparse-max-bytes`.
+const SPARSE_MAX_BYTES: usize = 3000;
+const SPARSE_VAL_MAX_VALUE: u8 = 32;
+const SPARSE_VAL_MAX_LEN: usize = 4;
+const SPARSE_ZERO_MAX_LEN: usize = 64;
+const SPARSE_XZERO_MAX_LEN: usize = 16384;
+
+/// The value claims to be a HyperLogLog, but its registers do not add up.
+#[derive(Debug)]
+pub struct Corrupted;
+
+/// An empty HyperLogLog: sparse, with every register zero and a cached cardinality of zero.
+pub fn new() -> Vec<u8> {
+    let mut hll = MAGIC.to_vec();
+    hll.resize(HEADER_LEN, 0);
+    hll[ENCODING] = SPARSE;
+    let mut remaining = REGISTERS;
+    while remaining > 0 {
+        let len = remaining.min(SPARSE_XZERO_MAX_LEN);
+        hll.extend(zero_run(len));
+        remaining -= len;
+    }
+    hll
+}
+
+/// Whether `bytes` has the header of a HyperLogLog, and the size of one if it is dense.
+pub fn is_valid(bytes: &[u8]) -> bool {
+    bytes.len() >= HEADER_LEN
+        && bytes.starts_with(MAGIC)
+        && match bytes[ENCODING] {
+            DENSE => bytes.len() == DENSE_LEN,
+            SPARSE => true,
+            _ => false,
+        }
+}
+
+pub fn is_sparse(hll: &[u8]) -> bool {
+    hll[ENCODING] == SPARSE
+}
+
+fn invalidate_cache(hll: &mut [u8]) {
+    hll[CARD.end - 1] |= 0x80;
+}
+
+/// Adds `element`, returning whether any register changed.
+pub fn add(hll: &mut Vec<u8>, element: &[u8]) -> Result<bool, Corrupted> {
+    let (index, count) = pattern(element);
+    raise(hll, index, count)
+}
+
+/// Raises the register at `index` to `count` unless it already holds as much, returning whether it changed.
+fn raise(hll: &mut Vec<u8>, index: usize, count: u8) -> Result<bool, Corrupted> {
+    let updated = match hll[ENCODING] {
+        DENSE => dense_raise(&mut hll[HEADER_LEN..], index, count),
+        _ => sparse_raise(hll, index, count)?,
+    };
+    if updated {
+        invalidate_cache(hll);
+    }
+    Ok(updated)
+}
+
+/// The estimated number of distinct elements added, taken from the cache when it is fresh and cached otherwise.
+pub fn count(hll: &mut [u8]) -> Result<u64, Corrupted> {
+    if hll[CARD.end - 1] & 0x80 == 0 {
+        let card: [u8; 8] = hll[CARD].try_into().expect("the cache is 8 bytes");
+        return Ok(u64::from_le_bytes(card));
+    }
+    let card = estimate(&registers(hll)?);
+    hll[CARD].copy_from_slice(&card.to_le_bytes());
+    Ok(card)
+}
+
+/// The registers of `hll`, one byte each.
+pub fn registers(hll: &[u8]) -> Result<Vec<u8>, Corrupted> {
+    if hll[ENCODING] == DENSE {
+        let dense = &hll[HEADER_LEN..];
+        return Ok((0..REGISTERS).map(|index| dense_get(dense, index)).collect());
+    }
+
+    let mut registers = Vec::with_capacity(REGISTERS);
+    let mut pos = HEADER_LEN;
+    while pos < hll.len() {
+        let (opcode, size) = Opcode::read(hll, pos).ok_or(Corrupted)?;
+        if registers.len() + opcode.len() > REGISTERS {
+            return Err(Corrupted);
+        }
+        let value = match opcode {
+            Opcode::Val(value, _) => value,
+            _ => 0,
+        };
+        registers.resize(registers.len() + opcode.len(), value);
+        pos += size;
+    }
+    if registers.len() != REGISTERS {
+        return Err(Corrupted);
+    }
+    Ok(registers)
+}
+
+/// The registers of the union of `hlls`: the largest value of each register across all of them.
+pub fn union<'a>(hlls: impl IntoIterator<Item = &'a [u8]>) -> Result<Vec<u8>, Corrupted> {
+    let mut union = vec![0; REGISTERS];
+    for hll in hlls {
+        for (max, register) in union.iter_mut().zip(registers(hll)?) {
+            *max = (*max).max(register);
+        }
+    }
+    Ok(union)
+}
+
+/// Raises the registers of `hll` to those of `registers`, turning it dense first when `dense` is set.
+pub fn merge(hll: &mut Vec<u8>, registers: &[u8], dense: bool) -> Result<(), Corrupted> {
+    if dense {
+        to_dense(hll)?;
+    }
+    for (index, &count) in registers.iter().enumerate() {
+        if count > 0 {
+            raise(hll, index, count)?;
+        }
+    }
+    invalidate_cache(hll);
+    Ok(())
+}
+
+/// Converts a sparse `hll` to the dense encoding, returning whether it was sparse.
+pub fn to_dense(hll: &mut Vec<u8>) -> Result<bool, Corrupted> {
+    if hll[ENCODING] == DENSE {
+        return Ok(false);
+    }
+    let registers = registers(hll)?;
+    let mut dense = vec![0; DENSE_LEN];
+    dense[..HEADER_LEN].copy_from_slice(&hll[..HEADER_LEN]);
+    dense[ENCODING] = DENSE;
+    for (index, count) in registers.into_iter().enumerate() {
+        dense_set(&mut dense[HEADER_LEN..], index, count);
+    }
+    *hll = dense;
+    Ok(true)
+}
+
+/// The opcodes of a sparse `hll` in the notation of `PFDEBUG DECODE`: `z:len` and `Z:len` for runs of zeros, and
+/// `v:value,len` for runs of a value.
+pub fn describe_sparse(hll: &[u8]) -> String {
+    let mut opcodes = Vec::new();
+    let mut pos = HEADER_LEN;
+    while let Some((opcode, size)) = Opcode::read(hll, pos) {
+        opcodes.push(match opcode {
+            Opcode::Zero(len) => format!("z:{len}"),
+            Opcode::XZero(len) => format!("Z:{len}"),
+            Opcode::Val(value, len) => format!("v:{value},{len}"),
+        });
+        pos += size;
+    }
+    opcodes.join(" ")
+}
+
+/// The estimated cardinality of a set of registers, with the estimator by Otmar Ertl that Redis uses.
+pub fn estimate(registers: &[u8]) -> u64 {
+    // Registers of a well-formed value never exceed Q + 1, but dense values loaded from elsewhere may hold any 6-bit
+    // value. Like Redis, those are counted and then left out of the estimate.
+    let mut histogram = [0u32; REGISTER_MAX as usize + 1];
+    for &register in registers {
+        histogram[register as usize] += 1;
+    }
+    let m = REGISTERS as f64;
+    let mut z = m * tau((m - histogram[Q as usize + 1] as f64) / m);
+    for j in (1..=Q as usize).rev() {
+        z += histogram[j] as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+    let alpha_inf = 0.5 / std::f64::consts::LN_2;
+    (alpha_inf * m * m / z).round() as u64
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if previous == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if previous == z {
+            return z / 3.0;
+        }
+    }
+}
+
+/// The register `element` falls in, and the value it offers that register: one more than the number of trailing
+/// zeros in the rest of its hash.
+fn pattern(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash64a(element, 0xadc83b19);
+    let index = (hash & (REGISTERS as u64 - 1)) as usize;
+    let rest = (hash >> P) | (1 << Q);
+    (index, rest.trailing_zeros() as u8 + 1)
+}
+
+/// MurmurHash64A, reading the input as little-endian words like Redis does on every platform.
+fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().expect("chunks are 8 bytes"));
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, &byte) in tail.iter().enumerate() {
+            h ^= (byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+fn dense_get(registers: &[u8], index: usize) -> u8 {
+    let bit = index * REGISTER_BITS;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let low = registers[byte] as u16 >> shift;
+    let high = registers.get(byte + 1).map_or(0, |&next| (next as u16) << (8 - shift));
+    ((low | high) & REGISTER_MAX as u16) as u8
+}
+
+fn dense_set(registers: &mut [u8], index: usize, value: u8) {
+    let bit = index * REGISTER_BITS;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let (max, value) = (REGISTER_MAX as u16, value as u16);
+    registers[byte] &= !(max << shift) as u8;
+    registers[byte] |= (value << shift) as u8;
+    if let Some(next) = registers.get_mut(byte + 1) {
+        *next &= !(max >> (8 - shift)) as u8;
+        *next |= (value >> (8 - shift)) as u8;
+    }
+}
+
+fn dense_raise(registers: &mut [u8], index: usize, count: u8) -> bool {
+    if dense_get(registers, index) >= count {
+        return false;
+    }
+    dense_set(registers, index, count);
+    true
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Opcode {
+    Zero(usize),
+    XZero(usize),
+    Val(u8, usize),
+}
+
+impl Opcode {
+    /// Decodes the opcode at `pos`, returning it with its size in bytes.
+    fn read(hll: &[u8], pos: usize) -> Option<(Self, usize)> {
+        let byte = *hll.get(pos)?;
+        Some(match byte & 0xc0 {
+            0x00 => (Self::Zero((byte & 0x3f) as usize + 1), 1),
+            0x40 => {
+                let low = *hll.get(pos + 1)?;
+                (Self::XZero((((byte & 0x3f) as usize) << 8 | low as usize) + 1), 2)
+            }
+            _ => (Self::Val(((byte >> 2) & 0x1f) + 1, (byte & 0x03) as usize + 1), 1),
+        })
+    }
+
+    fn len(self) -> usize {
+        match self {
+            Self::Zero(len) | Self::XZero(len) | Self::Val(_, len) => len,
+        }
+    }
+}
+
+fn val_opcode(value: u8, len: usize) -> u8 {
+    0x80 | (value - 1) << 2 | (len - 1) as u8
+}
+
+/// The opcode for a run of `len` zero registers.
+fn zero_run(len: usize) -> Vec<u8> {
+    if len > SPARSE_ZERO_MAX_LEN {
+        let len = len - 1;
+        vec![0x40 | (len >> 8) as u8, (len & 0xff) as u8]
+    } else {
+        vec![(len - 1) as u8]
+    }
+}
+
+/// Raises a register of a sparse `hll` in place, splitting the opcode that covers it the way Redis does so both
+/// produce the same bytes. The value turns dense when the register outgrows `VAL` or the value outgrows
+/// `SPARSE_MAX_BYTES`.
+fn sparse_raise(hll: &mut Vec<u8>, index: usize, count: u8) -> Result<bool, Corrupted> {
+    if count > SPARSE_VAL_MAX_VALUE {
+        return promote(hll, index, count);
+    }
+
+    // Finds the opcode covering the register, and the first register it covers.
+    let mut pos = HEADER_LEN;
+    let mut first = 0;
+    let mut previous = None;
+    let (opcode, size) = loop {
+        let (opcode, size) = Opcode::read(hll, pos).ok_or(Corrupted)?;
+        if index < first + opcode.len() {
+            break (opcode, size);
+        }
+        previous = Some(pos);
+        pos += size;
+        first += opcode.len();
+    };
+    let last = first + opcode.len() - 1;
+
+    match opcode {
+        Opcode::Val(value, _) if value >= count => return Ok(false),
+        // A single register is rewritten in place.
+        Opcode::Val(_, 1) | Opcode::Zero(1) => hll[pos] = val_opcode(count, 1),
+        _ => {
+            // Splits the run into the registers before the one being set, that register, and the ones after.
+            let mut sequence = Vec::with_capacity(5);
+            let run = |len: usize| match opcode {
+                Opcode::Val(value, _) => vec![val_opcode(value, len)],
+                _ => zero_run(len),
+            };
+            if index != first {
+                sequence.extend(run(index - first));
+            }
+            sequence.push(val_opcode(count, 1));
+            if index != last {
+                sequence.extend(run(last - index));
+            }
+            if sequence.len() > size && hll.len() + sequence.len() - size > SPARSE_MAX_BYTES {
+                return promote(hll, index, count);
+            }
+            hll.splice(pos..pos + size, sequence);
+        }
+    }
+
+    // Merges adjacent runs of the same value, looking at up to 5 opcodes from the one before the change.
+    let mut pos = previous.unwrap_or(HEADER_LEN);
+    let mut scan = 5;
+    while pos < hll.len() && scan > 0 {
+        scan -= 1;
+        let (opcode, size) = Opcode::read(hll, pos).ok_or(Corrupted)?;
+        let Opcode::Val(value, len) = opcode else {
+            pos += size;
+            continue;
+        };
+        if let Some((Opcode::Val(next_value, next_len), _)) = Opcode::read(hll, pos + 1) {
+            if value == next_value && len + next_len <= SPARSE_VAL_MAX_LEN {
+                hll[pos + 1] = val_opcode(value, len + next_len);
+                hll.remove(pos);
+                // The merged run may merge again with the one after it.
+                continue;
+            }
+        }
+        pos += 1;
+    }
+    Ok(true)
+}
+
+fn promote(hll: &mut Vec<u8>, index: usize, count: u8) -> Result<bool, Corrupted> {
+    to_dense(hll)?;
+    Ok(dense_raise(&mut hll[HEADER_LEN..], index, count))
+}
+
+/// Checks the dense register layout, and that the estimate stays within bounds and agrees between the encodings as
+/// elements are added. Returns a description of the first failure.
+pub fn self_test() -> Result<(), String> {
+    let mut rng = rand::thread_rng();
+
+    let mut expected = vec![0u8; REGISTERS];
+    let mut dense = vec![0u8; DENSE_LEN - HEADER_LEN];
+    for _ in 0..1000 {
+        for (index, register) in expected.iter_mut().enumerate() {
+            *register = rng.gen::<u8>() & REGISTER_MAX;
+            dense_set(&mut dense, index, *register);
+        }
+        for (index, &register) in expected.iter().enumerate() {
+            let value = dense_get(&dense, index);
+            if value != register {
+                return Err(format!(
+                    "TESTFAILED Register {index} should be {register} but is {value}"
+                ));
+            }
+        }
+    }
+
+    let mut sparse = new();
+    let mut dense = new();
+    to_dense(&mut dense).map_err(|_| "TESTFAILED dense conversion failed".to_string())?;
+    let relative_error = 1.04 / (REGISTERS as f64).sqrt();
+    let seed: u64 = rng.gen();
+    let mut checkpoint = 1;
+    for j in 1..=10_000_000u64 {
+        let element = (j ^ seed).to_le_bytes();
+        let corrupted = |_| "TESTFAILED corrupted HLL".to_string();
+        add(&mut dense, &element).map_err(corrupted)?;
+        add(&mut sparse, &element).map_err(corrupted)?;
+        if j != checkpoint {
+            continue;
+        }
+        if j < (SPARSE_MAX_BYTES / 2) as u64 && !is_sparse(&sparse) {
+            return Err("TESTFAILED sparse encoding not used".to_string());
+        }
+        let card = estimate(&registers(&dense).map_err(corrupted)?);
+        if card != estimate(&registers(&sparse).map_err(corrupted)?) {
+            return Err("TESTFAILED dense/sparse disagree".to_string());
+        }
+        let error = checkpoint.abs_diff(card);
+        let max_error = if j == 10 {
+            1
+        } else {
+            (relative_error * 6.0 * checkpoint as f64).ceil() as u64
+        };
+        if error > max_error {
+            return Err(format!("TESTFAILED Too big error. card:{card} abserr:{error}"));
+        }
+        checkpoint *= 10;
+    }
+    Ok(())
+}
@@ -1,10 +1,19 @@
 pub mod args;
+pub mod bitmap;
+pub mod blocking;
 pub mod command;
 pub mod connection;
+pub mod expire;
+pub mod geo;
 pub mod handler;
+pub mod hash;
+pub mod hyperloglog;
 pub mod message;
 pub mod protocol;
+pub mod registry;
 pub mod replica;
+pub mod set;
+pub mod sorted_set;
 pub mod store;
 pub mod stream;
 pub mod utils;
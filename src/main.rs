@@ -3,8 +3,10 @@ use clap::Parser;
 use redis_starter_rust::{
     args::CliArgs,
     connection::Connection,
+    expire,
     handler::Handler,
     protocol::rdb::Rdb,
+    registry::Registry,
     replica::{handler::ReplicaHandler, handshake::perform_handshake_to_master, should_replicate},
     store::Store,
     stream::StreamInfo,
@@ -17,6 +19,7 @@ async fn main() -> Result<()> {
     let args = CliArgs::parse();
     let stream_info = Arc::new(StreamInfo::new(&args));
     let store = Arc::new(Mutex::new(Store::new()));
+    let registry = Arc::new(Registry::default());
     if let Some(dir) = args.dir {
         stream_info.config.lock().await.dir = Some(dir);
     }
@@ -31,15 +34,18 @@ async fn main() -> Result<()> {
         }
     }
 
+    tokio::spawn(expire::run(store.clone(), stream_info.clone()));
+
     if should_replicate(&stream_info).await {
         let info = stream_info.clone();
         let store = store.clone();
+        let registry = registry.clone();
         tokio::spawn(async move {
             let mut replica_connection = perform_handshake_to_master(&info)
                 .await
                 .expect("Failed the handshake with the master");
             replica_connection.get_rdb().await;
-            _ = ReplicaHandler::handle_replica(replica_connection, store).await;
+            _ = ReplicaHandler::handle_replica(replica_connection, store, info, registry).await;
         });
     }
 
@@ -52,6 +58,7 @@ async fn main() -> Result<()> {
     loop {
         let stream_info = stream_info.clone();
         let store = store.clone();
+        let registry = registry.clone();
 
         let (stream, _) = listener
             .accept()
@@ -61,7 +68,7 @@ async fn main() -> Result<()> {
         println!("Accepted new connection");
 
         tokio::spawn(async move {
-            let _ = Handler::handle_stream(connection, store, stream_info).await;
+            let _ = Handler::handle_stream(connection, store, stream_info, registry).await;
         });
     }
 }
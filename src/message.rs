@@ -1,67 +1,136 @@
 use anyhow::{anyhow, Result};
-use bytes::BytesMut;
+use bytes::{Buf, Bytes, BytesMut};
 
 use crate::{
     command::CommandInfo,
-    protocol::parser::{parse_int, read_until_crlf},
+    protocol::parser::{parse_int, parse_signed_int, read_until_crlf, read_until_lf, split_inline_args},
 };
 
+const INLINE_MAX_SIZE: usize = 64 * 1024;
+/// The longest bulk string accepted, Redis' default `proto-max-bulk-len`.
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+/// The most arguments a request may have, like Redis' limit on multibulk lengths.
+const MAX_MULTIBULK_LEN: usize = 1024 * 1024;
+/// How deep aggregates may nest in a decoded message, so that hostile input cannot exhaust the stack.
+const MAX_NESTING: usize = 64;
+
+/// Wire protocol negotiated by a client through `HELLO`. Every connection starts on RESP2.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+impl Protocol {
+    pub fn version(&self) -> isize {
+        match self {
+            Self::Resp2 => 2,
+            Self::Resp3 => 3,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Message {
     Simple(String),
-    Bulk(String),
+    Bulk(Bytes),
     Array(Vec<Message>),
     Int(isize),
     Error(String),
     Null,
+    NullArray,
+    Map(Vec<(Message, Message)>),
+    Set(Vec<Message>),
+    Double(f64),
+    Boolean(bool),
+    BigNumber(String),
+    Verbatim(String, Bytes),
+    Push(Vec<Message>),
+    Attribute(Vec<(Message, Message)>),
 }
 
 impl Message {
-    pub fn decode(buffer: BytesMut) -> Result<(Message, usize)> {
-        match buffer[0] as char {
-            '+' => parse_simple_string(buffer),
-            '*' => parse_array(buffer),
-            '$' => parse_bulk_string(buffer),
-            _ => Err(anyhow!("Not a known value type {:?}", buffer)),
-        }
+    /// Decodes a single frame from the front of `buffer`, returning the message and the number of bytes it
+    /// occupied. `Ok(None)` means the buffer does not yet hold a complete frame.
+    pub fn decode(buffer: &[u8]) -> Result<Option<(Message, usize)>> {
+        decode_nested(buffer, 0)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        self.encode_for(Protocol::Resp2)
     }
 
-    pub fn encode(&self) -> String {
+    /// Encodes the message for a client speaking `protocol`. RESP3-only types are downgraded to their RESP2
+    /// equivalents the same way Redis does: maps become flat arrays, doubles become bulk strings and so on.
+    pub fn encode_for(&self, protocol: Protocol) -> Vec<u8> {
+        let resp3 = protocol == Protocol::Resp3;
         match self {
-            Message::Simple(value) => {
-                format!("+{}\r\n", value)
+            Message::Simple(value) => format!("+{}\r\n", value).into_bytes(),
+            Message::Bulk(value) => encode_bulk(value),
+            Message::Array(values) => encode_aggregate('*', values, protocol),
+            Message::Int(value) => format!(":{}\r\n", value).into_bytes(),
+            Message::Error(s) => format!("-{}\r\n", s).into_bytes(),
+            Message::Null if resp3 => b"_\r\n".to_vec(),
+            Message::Null => b"$-1\r\n".to_vec(),
+            Message::NullArray if resp3 => b"_\r\n".to_vec(),
+            Message::NullArray => b"*-1\r\n".to_vec(),
+            Message::Map(pairs) => {
+                let mut result = if resp3 {
+                    format!("%{}\r\n", pairs.len()).into_bytes()
+                } else {
+                    format!("*{}\r\n", pairs.len() * 2).into_bytes()
+                };
+                for (key, value) in pairs {
+                    result.extend(key.encode_for(protocol));
+                    result.extend(value.encode_for(protocol));
+                }
+                result
             }
-            Message::Bulk(value) => {
-                format!("${}\r\n{}\r\n", value.chars().count(), value)
+            Message::Set(values) if resp3 => encode_aggregate('~', values, protocol),
+            Message::Set(values) => encode_aggregate('*', values, protocol),
+            Message::Double(value) if resp3 => format!(",{}\r\n", format_double(*value)).into_bytes(),
+            Message::Double(value) => encode_bulk(format_double(*value).as_bytes()),
+            Message::Boolean(value) if resp3 => format!("#{}\r\n", if *value { 't' } else { 'f' }).into_bytes(),
+            Message::Boolean(value) => format!(":{}\r\n", *value as u8).into_bytes(),
+            Message::BigNumber(value) if resp3 => format!("({}\r\n", value).into_bytes(),
+            Message::BigNumber(value) => encode_bulk(value.as_bytes()),
+            Message::Verbatim(format, value) if resp3 => {
+                let mut result = format!("={}\r\n{}:", value.len() + 4, format).into_bytes();
+                result.extend_from_slice(value);
+                result.extend_from_slice(b"\r\n");
+                result
             }
-            Message::Array(values) => {
-                let mut result = format!("*{}\r\n", values.len());
-                for value in values {
-                    result.push_str(&value.encode());
+            Message::Verbatim(_, value) => encode_bulk(value),
+            Message::Push(values) if resp3 => encode_aggregate('>', values, protocol),
+            Message::Push(values) => encode_aggregate('*', values, protocol),
+            Message::Attribute(pairs) if resp3 => {
+                let mut result = format!("|{}\r\n", pairs.len()).into_bytes();
+                for (key, value) in pairs {
+                    result.extend(key.encode_for(protocol));
+                    result.extend(value.encode_for(protocol));
                 }
                 result
             }
-            Message::Int(value) => {
-                format!(":{}\r\n", value)
-            }
-            Message::Error(s) => format!("-{}\r\n", s),
-            Message::Null => "$-1\r\n".to_string(),
+            // RESP2 has no way to carry out-of-band attributes, so they are dropped.
+            Message::Attribute(_) => Vec::new(),
         }
     }
 
-    pub fn encode_array_str(values: Vec<&str>) -> String {
-        let mut result = format!("*{}\r\n", values.len());
-        for value in values {
-            result.push_str(&Message::Bulk(value.to_string()).encode());
-        }
-        result
+    pub fn encode_array_str(values: Vec<&str>) -> Vec<u8> {
+        let values = values
+            .into_iter()
+            .map(|value| Message::Bulk(Bytes::copy_from_slice(value.as_bytes())))
+            .collect();
+        Message::Array(values).encode()
     }
 
     pub async fn parse_command(message: Message) -> Result<CommandInfo> {
         match message {
             Message::Array(a) => {
                 if let Some(name) = a.first().and_then(|v| unpack_bulk_str(v.clone())) {
-                    let args: Vec<String> = a.into_iter().skip(1).filter_map(unpack_bulk_str).collect();
+                    let name = String::from_utf8_lossy(&name).into_owned();
+                    let args: Vec<Bytes> = a.into_iter().skip(1).filter_map(unpack_bulk_str).collect();
                     Ok(CommandInfo::new(name, args))
                 } else {
                     Err(anyhow!("Invalid command format"))
@@ -72,46 +141,327 @@ impl Message {
     }
 }
 
-pub fn parse_simple_string(buffer: BytesMut) -> Result<(Message, usize)> {
-    if let Some((line, len)) = read_until_crlf(&buffer[1..]) {
-        let string = String::from_utf8(line.to_vec()).unwrap();
-        return Ok((Message::Simple(string), len + 1));
+/// Decodes client requests incrementally, the way Redis keeps `multibulklen` and `bulklen` between reads. Requests
+/// are flat arrays of bulk strings, or inline commands typed into `telnet`/`nc`. Every argument is taken out of the
+/// buffer as soon as it is complete, so a large pipelined request is parsed once however many reads it takes.
+#[derive(Debug, Default)]
+pub struct RequestDecoder {
+    /// Arguments of the multibulk being received, and how many are still expected.
+    args: Vec<Message>,
+    remaining: usize,
+}
+
+impl RequestDecoder {
+    /// Takes the next complete request from the front of `buffer`. `Ok(None)` means more data is needed. Empty
+    /// requests, from blank inline lines or empty arrays, come out as empty arrays.
+    pub fn decode(&mut self, buffer: &mut BytesMut) -> Result<Option<Message>> {
+        if self.remaining == 0 {
+            if buffer.is_empty() {
+                return Ok(None);
+            }
+            if buffer[0] != b'*' {
+                return decode_inline(buffer);
+            }
+            let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+                if buffer.len() > INLINE_MAX_SIZE {
+                    return Err(anyhow!("too big mbulk count string"));
+                }
+                return Ok(None);
+            };
+            // Like in Redis, a negative count makes an empty request.
+            let count = match parse_signed_int(line) {
+                Ok(count) if count <= 0 => 0,
+                Ok(count) if count as usize <= MAX_MULTIBULK_LEN => count as usize,
+                _ => return Err(anyhow!("invalid multibulk length")),
+            };
+            buffer.advance(len + 1);
+            self.remaining = count;
+            self.args = Vec::with_capacity(count.min(1024));
+        }
+
+        while self.remaining > 0 {
+            if buffer.is_empty() {
+                return Ok(None);
+            }
+            if buffer[0] != b'$' {
+                return Err(anyhow!("expected '$', got '{}'", buffer[0] as char));
+            }
+            let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+                if buffer.len() > INLINE_MAX_SIZE {
+                    return Err(anyhow!("too big bulk count string"));
+                }
+                return Ok(None);
+            };
+            let bulk_len = parse_bulk_len(line)?;
+            let header_len = len + 1;
+            if buffer.len() < header_len + bulk_len + 2 {
+                return Ok(None);
+            }
+            if &buffer[header_len + bulk_len..header_len + bulk_len + 2] != b"\r\n" {
+                return Err(anyhow!("expected CRLF after bulk string"));
+            }
+            buffer.advance(header_len);
+            let arg = buffer.split_to(bulk_len).freeze();
+            buffer.advance(2);
+            self.args.push(Message::Bulk(arg));
+            self.remaining -= 1;
+        }
+        Ok(Some(Message::Array(std::mem::take(&mut self.args))))
+    }
+}
+
+/// Takes an inline command from the front of `buffer`, turned into an array of bulk strings so that both forms of
+/// request reach `parse_command` alike.
+fn decode_inline(buffer: &mut BytesMut) -> Result<Option<Message>> {
+    let Some((line, len)) = read_until_lf(buffer) else {
+        if buffer.len() > INLINE_MAX_SIZE {
+            return Err(anyhow!("too big inline request"));
+        }
+        return Ok(None);
+    };
+    let args = split_inline_args(line)?
+        .into_iter()
+        .map(|arg| Message::Bulk(arg.into()))
+        .collect();
+    buffer.advance(len);
+    Ok(Some(Message::Array(args)))
+}
+
+/// Decodes a frame found `depth` aggregates deep.
+fn decode_nested(buffer: &[u8], depth: usize) -> Result<Option<(Message, usize)>> {
+    if buffer.is_empty() {
+        return Ok(None);
+    }
+    if depth > MAX_NESTING {
+        return Err(anyhow!("too many nested aggregates"));
+    }
+    match buffer[0] as char {
+        '+' => parse_simple_string(buffer),
+        '-' => parse_error(buffer),
+        ':' => parse_integer(buffer),
+        '*' => parse_array(buffer, depth),
+        '$' => parse_bulk_string(buffer),
+        '_' => parse_null(buffer),
+        ',' => parse_double(buffer),
+        '#' => parse_boolean(buffer),
+        '(' => parse_big_number(buffer),
+        '=' => parse_verbatim_string(buffer),
+        '%' | '|' => parse_map(buffer, depth),
+        '~' | '>' => parse_set_or_push(buffer, depth),
+        _ => Err(anyhow!("Not a known value type {:?}", buffer[0] as char)),
+    }
+}
+
+pub fn parse_simple_string(buffer: &[u8]) -> Result<Option<(Message, usize)>> {
+    let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+        return Ok(None);
+    };
+    let string = String::from_utf8(line.to_vec())?;
+    Ok(Some((Message::Simple(string), len + 1)))
+}
+
+pub fn parse_error(buffer: &[u8]) -> Result<Option<(Message, usize)>> {
+    let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+        return Ok(None);
+    };
+    let string = String::from_utf8(line.to_vec())?;
+    Ok(Some((Message::Error(string), len + 1)))
+}
+
+pub fn parse_integer(buffer: &[u8]) -> Result<Option<(Message, usize)>> {
+    let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+        return Ok(None);
+    };
+    let value = parse_signed_int(line).map_err(|_| anyhow!("invalid integer"))?;
+    Ok(Some((Message::Int(value), len + 1)))
+}
+
+pub fn parse_array(buffer: &[u8], depth: usize) -> Result<Option<(Message, usize)>> {
+    let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+        return Ok(None);
+    };
+    if line == b"-1" {
+        return Ok(Some((Message::NullArray, len + 1)));
+    }
+    let array_length = parse_int(line).map_err(|_| anyhow!("invalid multibulk length"))?;
+    let Some((items, bytes_consumed)) = parse_items(buffer, len + 1, array_length, depth)? else {
+        return Ok(None);
+    };
+    Ok(Some((Message::Array(items), bytes_consumed)))
+}
+
+pub fn parse_bulk_string(buffer: &[u8]) -> Result<Option<(Message, usize)>> {
+    let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+        return Ok(None);
+    };
+    if line == b"-1" {
+        return Ok(Some((Message::Null, len + 1)));
+    }
+    let bulk_str_len = parse_bulk_len(line)?;
+    let bytes_consumed = len + 1;
+    let end_of_bulk_str = bytes_consumed
+        .checked_add(bulk_str_len)
+        .ok_or_else(|| anyhow!("invalid bulk length"))?;
+    let total_parsed = end_of_bulk_str.saturating_add(2);
+    if total_parsed > buffer.len() {
+        return Ok(None);
+    }
+    if &buffer[end_of_bulk_str..total_parsed] != b"\r\n" {
+        return Err(anyhow!("expected CRLF after bulk string"));
+    }
+    Ok(Some((
+        Message::Bulk(Bytes::copy_from_slice(&buffer[bytes_consumed..end_of_bulk_str])),
+        total_parsed,
+    )))
+}
+
+/// Parses the length of a bulk string, which may not exceed [`MAX_BULK_LEN`].
+fn parse_bulk_len(line: &[u8]) -> Result<usize> {
+    parse_int(line)
+        .ok()
+        .filter(|len| *len <= MAX_BULK_LEN)
+        .ok_or_else(|| anyhow!("invalid bulk length"))
+}
+
+pub fn parse_null(buffer: &[u8]) -> Result<Option<(Message, usize)>> {
+    let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+        return Ok(None);
+    };
+    if !line.is_empty() {
+        return Err(anyhow!("invalid null"));
+    }
+    Ok(Some((Message::Null, len + 1)))
+}
+
+pub fn parse_double(buffer: &[u8]) -> Result<Option<(Message, usize)>> {
+    let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+        return Ok(None);
+    };
+    let value = std::str::from_utf8(line)?
+        .parse::<f64>()
+        .map_err(|_| anyhow!("invalid double"))?;
+    Ok(Some((Message::Double(value), len + 1)))
+}
+
+pub fn parse_boolean(buffer: &[u8]) -> Result<Option<(Message, usize)>> {
+    let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+        return Ok(None);
+    };
+    let value = match line {
+        b"t" => true,
+        b"f" => false,
+        _ => return Err(anyhow!("invalid boolean")),
+    };
+    Ok(Some((Message::Boolean(value), len + 1)))
+}
+
+pub fn parse_big_number(buffer: &[u8]) -> Result<Option<(Message, usize)>> {
+    let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+        return Ok(None);
+    };
+    let digits = line.strip_prefix(b"-").unwrap_or(line);
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return Err(anyhow!("invalid big number"));
+    }
+    Ok(Some((Message::BigNumber(String::from_utf8(line.to_vec())?), len + 1)))
+}
+
+pub fn parse_verbatim_string(buffer: &[u8]) -> Result<Option<(Message, usize)>> {
+    let Some((Message::Bulk(value), total_parsed)) = parse_bulk_string(buffer)? else {
+        return Ok(None);
+    };
+    if value.len() < 4 || value[3] != b':' {
+        return Err(anyhow!("invalid verbatim string"));
     }
-    Err(anyhow!("Invalid string {:?}", buffer))
+    let format = String::from_utf8(value[..3].to_vec())?;
+    Ok(Some((Message::Verbatim(format, value.slice(4..)), total_parsed)))
 }
 
-pub fn parse_array(buffer: BytesMut) -> Result<(Message, usize)> {
-    let (array_length, mut bytes_consumed) = if let Some((line, len)) = read_until_crlf(&buffer[1..]) {
-        let array_length = parse_int(line)?;
-        (array_length, len + 1)
+pub fn parse_map(buffer: &[u8], depth: usize) -> Result<Option<(Message, usize)>> {
+    let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+        return Ok(None);
+    };
+    let map_length = parse_int(line)
+        .ok()
+        .and_then(|len| len.checked_mul(2))
+        .ok_or_else(|| anyhow!("invalid map length"))?;
+    let Some((items, bytes_consumed)) = parse_items(buffer, len + 1, map_length, depth)? else {
+        return Ok(None);
+    };
+    let mut items = items.into_iter();
+    let mut pairs = Vec::with_capacity(map_length / 2);
+    while let (Some(key), Some(value)) = (items.next(), items.next()) {
+        pairs.push((key, value));
+    }
+    let message = if buffer[0] == b'|' {
+        Message::Attribute(pairs)
     } else {
-        return Err(anyhow!("Invalid array format {:?}", buffer));
+        Message::Map(pairs)
+    };
+    Ok(Some((message, bytes_consumed)))
+}
+
+pub fn parse_set_or_push(buffer: &[u8], depth: usize) -> Result<Option<(Message, usize)>> {
+    let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+        return Ok(None);
+    };
+    let length = parse_int(line).map_err(|_| anyhow!("invalid aggregate length"))?;
+    let Some((items, bytes_consumed)) = parse_items(buffer, len + 1, length, depth)? else {
+        return Ok(None);
     };
-    let mut items = vec![];
-    for _ in 0..array_length {
-        let (array_item, len) = Message::decode(BytesMut::from(&buffer[bytes_consumed..]))?;
-        items.push(array_item);
+    let message = if buffer[0] == b'>' {
+        Message::Push(items)
+    } else {
+        Message::Set(items)
+    };
+    Ok(Some((message, bytes_consumed)))
+}
+
+/// Decodes the `count` items of an aggregate found `depth` aggregates deep.
+fn parse_items(
+    buffer: &[u8],
+    mut bytes_consumed: usize,
+    count: usize,
+    depth: usize,
+) -> Result<Option<(Vec<Message>, usize)>> {
+    let mut items = Vec::with_capacity(count.min(1024));
+    for _ in 0..count {
+        let Some((item, len)) = decode_nested(&buffer[bytes_consumed..], depth + 1)? else {
+            return Ok(None);
+        };
+        items.push(item);
         bytes_consumed += len;
     }
-    Ok((Message::Array(items), bytes_consumed))
+    Ok(Some((items, bytes_consumed)))
+}
+
+fn encode_bulk(value: &[u8]) -> Vec<u8> {
+    let mut result = format!("${}\r\n", value.len()).into_bytes();
+    result.extend_from_slice(value);
+    result.extend_from_slice(b"\r\n");
+    result
+}
+
+fn encode_aggregate(prefix: char, values: &[Message], protocol: Protocol) -> Vec<u8> {
+    let mut result = format!("{}{}\r\n", prefix, values.len()).into_bytes();
+    for value in values {
+        result.extend(value.encode_for(protocol));
+    }
+    result
 }
 
-pub fn parse_bulk_string(buffer: BytesMut) -> Result<(Message, usize)> {
-    let (bulk_str_len, bytes_consumed) = if let Some((line, len)) = read_until_crlf(&buffer[1..]) {
-        let bulk_str_len = parse_int(line)?;
-        (bulk_str_len, len + 1)
+/// Formats a double the way it appears in replies, with `inf` and `-inf` for the infinities.
+pub fn format_double(value: f64) -> String {
+    if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "inf" } else { "-inf" }.to_string()
     } else {
-        return Err(anyhow!("Invalid array format {:?}", buffer));
-    };
-    let end_of_bulk_str = bytes_consumed + bulk_str_len;
-    let total_parsed = end_of_bulk_str + 2;
-    Ok((
-        Message::Bulk(String::from_utf8(buffer[bytes_consumed..end_of_bulk_str].to_vec())?),
-        total_parsed,
-    ))
+        value.to_string()
+    }
 }
 
-pub fn unpack_bulk_str(value: Message) -> Option<String> {
+pub fn unpack_bulk_str(value: Message) -> Option<Bytes> {
     match value {
         Message::Bulk(s) => Some(s),
         _ => None,
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 pub fn read_until_crlf(buffer: &[u8]) -> Option<(&[u8], usize)> {
     for i in 1..buffer.len() {
@@ -12,3 +12,89 @@ pub fn read_until_crlf(buffer: &[u8]) -> Option<(&[u8], usize)> {
 pub fn parse_int(buffer: &[u8]) -> Result<usize> {
     Ok(String::from_utf8(buffer.to_vec())?.parse()?)
 }
+
+pub fn parse_signed_int(buffer: &[u8]) -> Result<isize> {
+    Ok(String::from_utf8(buffer.to_vec())?.parse()?)
+}
+
+pub fn read_until_lf(buffer: &[u8]) -> Option<(&[u8], usize)> {
+    let end = buffer.iter().position(|&byte| byte == b'\n')?;
+    let line = buffer[..end].strip_suffix(b"\r").unwrap_or(&buffer[..end]);
+    Some((line, end + 1))
+}
+
+/// Splits an inline command line into arguments the way `redis-cli` and Redis itself do: arguments are separated
+/// by whitespace, double quotes support `\n`-style and `\xHH` escapes, and single quotes only escape `\'`.
+pub fn split_inline_args(line: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut args = Vec::new();
+    let mut index = 0;
+    loop {
+        while index < line.len() && line[index].is_ascii_whitespace() {
+            index += 1;
+        }
+        if index == line.len() {
+            return Ok(args);
+        }
+
+        let mut arg = Vec::new();
+        let mut in_double_quotes = false;
+        let mut in_single_quotes = false;
+        loop {
+            let Some(&byte) = line.get(index) else {
+                if in_double_quotes || in_single_quotes {
+                    return Err(anyhow!("unbalanced quotes in request"));
+                }
+                break;
+            };
+            if in_double_quotes {
+                if byte == b'\\' && index + 3 < line.len() && line[index + 1] == b'x' {
+                    if let Ok(value) = u8::from_str_radix(&String::from_utf8_lossy(&line[index + 2..index + 4]), 16) {
+                        arg.push(value);
+                        index += 4;
+                        continue;
+                    }
+                }
+                if byte == b'\\' && index + 1 < line.len() {
+                    index += 1;
+                    arg.push(match line[index] {
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'b' => 0x08,
+                        b'a' => 0x07,
+                        other => other,
+                    });
+                } else if byte == b'"' {
+                    // The closing quote must be followed by a space or the end of the line.
+                    if line.get(index + 1).is_some_and(|next| !next.is_ascii_whitespace()) {
+                        return Err(anyhow!("unbalanced quotes in request"));
+                    }
+                    in_double_quotes = false;
+                } else {
+                    arg.push(byte);
+                }
+            } else if in_single_quotes {
+                if byte == b'\\' && line.get(index + 1) == Some(&b'\'') {
+                    index += 1;
+                    arg.push(b'\'');
+                } else if byte == b'\'' {
+                    if line.get(index + 1).is_some_and(|next| !next.is_ascii_whitespace()) {
+                        return Err(anyhow!("unbalanced quotes in request"));
+                    }
+                    in_single_quotes = false;
+                } else {
+                    arg.push(byte);
+                }
+            } else {
+                match byte {
+                    b' ' | b'\n' | b'\r' | b'\t' | 0x0B | 0x0C => break,
+                    b'"' => in_double_quotes = true,
+                    b'\'' => in_single_quotes = true,
+                    _ => arg.push(byte),
+                }
+            }
+            index += 1;
+        }
+        args.push(arg);
+    }
+}
@@ -1,16 +1,56 @@
 use crate::{
-    store::{Entry, Store},
+    hash::Hash,
+    set::Set,
+    sorted_set::SortedSet,
+    store::{Entry, Store, StoreItem},
     stream::StreamInfo,
 };
 use anyhow::{anyhow, Result};
+use bytes::Bytes;
 use std::{
     env,
-    path::Path,
+    path::{Path, PathBuf},
     sync::Arc,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use tokio::{fs::File, io::AsyncReadExt};
 
+const DEFAULT_FILENAME: &str = "dump.rdb";
+
+const OPCODE_AUX: u8 = 0xFA;
+const OPCODE_RESIZEDB: u8 = 0xFB;
+const OPCODE_EXPIRETIME_MS: u8 = 0xFC;
+const OPCODE_EXPIRETIME: u8 = 0xFD;
+const OPCODE_SELECTDB: u8 = 0xFE;
+const OPCODE_EOF: u8 = 0xFF;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+/// A sorted set whose scores are written as strings.
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+/// A sorted set whose scores are written as binary doubles.
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_HASH_ZIPMAP: u8 = 9;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_SET_LISTPACK: u8 = 20;
+/// A hash with field expiry times, as written by Redis 7.4: the earliest expiry time follows the key, and each field
+/// is preceded by its expiry time relative to it.
+const TYPE_HASH_METADATA: u8 = 24;
+/// A listpack encoded hash with field expiry times: the earliest expiry time, then field, value and absolute expiry
+/// time triplets.
+const TYPE_HASH_LISTPACK_EX: u8 = 25;
+
+const ENCODING_INT8: u8 = 0;
+const ENCODING_INT16: u8 = 1;
+const ENCODING_INT32: u8 = 2;
+const ENCODING_LZF: u8 = 3;
+
 pub struct Rdb {}
 
 impl Rdb {
@@ -38,23 +78,166 @@ impl Rdb {
         Some(buffer)
     }
 
+    /// Location of the RDB file from the `dir` and `dbfilename` settings, defaulting like Redis does.
+    pub async fn file_path(stream_info: &Arc<StreamInfo>) -> PathBuf {
+        let config = stream_info.config.lock().await;
+        let directory = config
+            .dir
+            .clone()
+            .unwrap_or_else(|| env::current_dir().unwrap().into_os_string().into_string().unwrap());
+        let filename = config
+            .dbfilename
+            .clone()
+            .unwrap_or_else(|| DEFAULT_FILENAME.to_string());
+        Path::new(&directory).join(filename)
+    }
+
+    pub async fn write_file(stream_info: &Arc<StreamInfo>, data: &[u8]) -> Result<()> {
+        let path = Self::file_path(stream_info).await;
+        let temp_path = path.with_extension("rdb.tmp");
+        tokio::fs::write(&temp_path, data).await?;
+        tokio::fs::rename(&temp_path, &path).await?;
+        Ok(())
+    }
+
     pub fn parse_rdb(store: &mut Store, data: &[u8]) -> Result<()> {
         let mut marker = 0;
         if !has_magic_number(data, &mut marker) {
             return Ok(());
         }
-        if !find_database_selector(data, 0x00, &mut marker) {
-            return Ok(());
-        }
-        if !read_resizedb_field(data, &mut marker) {
-            return Ok(());
-        }
-        while data[marker] != 0xFF {
-            let (key, entry) = read_entry(data, &mut marker)?;
-            store.set_kv(key, entry)?;
+        marker += 4; // Version digits
+        let mut database = 0;
+        let mut expiry: Option<SystemTime> = None;
+        loop {
+            let opcode = *data
+                .get(marker)
+                .ok_or_else(|| anyhow!("Unexpected end of the RDB file"))?;
+            marker += 1;
+            match opcode {
+                OPCODE_EOF => break,
+                OPCODE_AUX => {
+                    read_string(data, &mut marker)?;
+                    read_string(data, &mut marker)?;
+                }
+                OPCODE_SELECTDB => database = read_length(data, &mut marker)?,
+                OPCODE_RESIZEDB => {
+                    read_length(data, &mut marker)?;
+                    read_length(data, &mut marker)?;
+                }
+                OPCODE_EXPIRETIME => {
+                    let seconds = u32::from_le_bytes(read_array(data, &mut marker)?);
+                    expiry = Some(UNIX_EPOCH + Duration::from_secs(seconds as u64));
+                }
+                OPCODE_EXPIRETIME_MS => {
+                    let millis = u64::from_le_bytes(read_array(data, &mut marker)?);
+                    expiry = Some(UNIX_EPOCH + Duration::from_millis(millis));
+                }
+                value_type => {
+                    let key = read_string(data, &mut marker)?;
+                    let item = read_object(data, &mut marker, value_type)?;
+                    let expiry = expiry.take();
+                    // Only the first database is served.
+                    if database == 0 {
+                        store.set_item(key.clone(), item);
+                        store.set_expiry(&key, expiry);
+                    }
+                }
+            }
         }
         Ok(())
     }
+
+    /// Serializes the keyspace into an RDB file. Streams are not persisted yet and are left out.
+    pub fn dump(store: &Store) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"REDIS0011");
+        out.push(OPCODE_AUX);
+        write_string(&mut out, b"redis-ver");
+        write_string(&mut out, b"7.4.0");
+
+        let items: Vec<_> = store
+            .data
+            .iter()
+            .filter(|(_, item)| !matches!(item, StoreItem::Stream(_)))
+            .collect();
+        let expires = items.iter().filter(|(key, _)| store.expires.contains_key(*key)).count();
+        out.push(OPCODE_SELECTDB);
+        write_length(&mut out, 0);
+        out.push(OPCODE_RESIZEDB);
+        write_length(&mut out, items.len() as u64);
+        write_length(&mut out, expires as u64);
+
+        for (key, item) in items {
+            if let Some(expiry_at) = store.expires.get(key) {
+                let millis = expiry_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+                out.push(OPCODE_EXPIRETIME_MS);
+                out.extend_from_slice(&millis.to_le_bytes());
+            }
+            match item {
+                StoreItem::KeyValueEntry(entry) => {
+                    out.push(TYPE_STRING);
+                    write_string(&mut out, key);
+                    write_string(&mut out, &entry.value.as_bytes());
+                }
+                StoreItem::List(list) => {
+                    out.push(TYPE_LIST);
+                    write_string(&mut out, key);
+                    write_length(&mut out, list.len() as u64);
+                    for element in list {
+                        write_string(&mut out, element);
+                    }
+                }
+                StoreItem::Set(set) => {
+                    out.push(TYPE_SET);
+                    write_string(&mut out, key);
+                    write_length(&mut out, set.len() as u64);
+                    for member in set.members() {
+                        write_string(&mut out, &member);
+                    }
+                }
+                StoreItem::SortedSet(zset) => {
+                    out.push(TYPE_ZSET_2);
+                    write_string(&mut out, key);
+                    write_length(&mut out, zset.len() as u64);
+                    for (member, score) in zset.iter() {
+                        write_string(&mut out, member);
+                        out.extend_from_slice(&score.to_le_bytes());
+                    }
+                }
+                StoreItem::Hash(hash) => {
+                    let expiry_millis = |field: &[u8]| {
+                        hash.expiry(field)
+                            .map(|expiry| expiry.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64)
+                    };
+                    let min_expiry = hash.iter().filter_map(|(field, _)| expiry_millis(field)).min();
+                    out.push(if min_expiry.is_some() {
+                        TYPE_HASH_METADATA
+                    } else {
+                        TYPE_HASH
+                    });
+                    write_string(&mut out, key);
+                    if let Some(min_expiry) = min_expiry {
+                        out.extend_from_slice(&min_expiry.to_le_bytes());
+                    }
+                    write_length(&mut out, hash.len() as u64);
+                    for (field, value) in hash.iter() {
+                        if let Some(min_expiry) = min_expiry {
+                            // Zero marks a field without expiry.
+                            let expiry = expiry_millis(field).map_or(0, |expiry| expiry - min_expiry + 1);
+                            write_length(&mut out, expiry);
+                        }
+                        write_string(&mut out, field);
+                        write_string(&mut out, value);
+                    }
+                }
+                StoreItem::Stream(_) => {}
+            }
+        }
+        out.push(OPCODE_EOF);
+        // A zero checksum tells readers that checksumming is disabled.
+        out.extend_from_slice(&[0; 8]);
+        out
+    }
 }
 
 #[derive(Debug)]
@@ -99,130 +282,399 @@ fn has_magic_number(data: &[u8], marker: &mut usize) -> bool {
     magic_number == &data[0..magic_number.len()]
 }
 
-fn find_database_selector(data: &[u8], database: u8, marker: &mut usize) -> bool {
-    let start = *marker + 1;
-    for i in start..data.len() {
-        if data[i - 1] == 0xFE && data[i] == database {
-            *marker = i + 1;
-            return true;
+fn read_array<const N: usize>(data: &[u8], marker: &mut usize) -> Result<[u8; N]> {
+    let bytes = data
+        .get(*marker..*marker + N)
+        .ok_or_else(|| anyhow!("Unexpected end of the RDB file"))?;
+    *marker += N;
+    Ok(bytes.try_into()?)
+}
+
+fn read_bytes<'a>(data: &'a [u8], marker: &mut usize, len: usize) -> Result<&'a [u8]> {
+    let bytes = data
+        .get(*marker..*marker + len)
+        .ok_or_else(|| anyhow!("Unexpected end of the RDB file"))?;
+    *marker += len;
+    Ok(bytes)
+}
+
+enum Length {
+    Len(usize),
+    /// A string stored in a special format, identified by the low 6 bits of the first byte.
+    Encoded(u8),
+}
+
+fn read_length_encoding(data: &[u8], marker: &mut usize) -> Result<Length> {
+    let [first] = read_array(data, marker)?;
+    let length = match first >> 6 {
+        0b00 => Length::Len((first & 0x3F) as usize),
+        0b01 => {
+            let [second] = read_array(data, marker)?;
+            Length::Len((((first & 0x3F) as usize) << 8) | second as usize)
         }
+        0b10 if first == 0x80 => Length::Len(u32::from_be_bytes(read_array(data, marker)?) as usize),
+        0b10 if first == 0x81 => Length::Len(u64::from_be_bytes(read_array(data, marker)?) as usize),
+        0b11 => Length::Encoded(first & 0x3F),
+        _ => return Err(anyhow!("Invalid length encoding in the RDB file")),
+    };
+    Ok(length)
+}
+
+fn read_length(data: &[u8], marker: &mut usize) -> Result<usize> {
+    match read_length_encoding(data, marker)? {
+        Length::Len(len) => Ok(len),
+        Length::Encoded(_) => Err(anyhow!("Expected a length in the RDB file")),
     }
-    false
 }
 
-#[allow(dead_code)]
-fn read_length_encoded_int(data: &[u8], marker: &mut usize) -> Option<u64> {
-    let original = data[*marker];
-    let tag = original & 0x03;
-    *marker += 1;
-    match tag {
-        0b11 => {
-            let length = read_length_encoded_int(data, marker)?;
-            let mut result = Vec::with_capacity(length as usize);
-            for _ in 0..length {
-                result.push(data[*marker]);
-                *marker += 1;
-            }
-            Some(String::from_utf8(result).unwrap().parse().unwrap())
+fn read_string(data: &[u8], marker: &mut usize) -> Result<Bytes> {
+    let string = match read_length_encoding(data, marker)? {
+        Length::Len(len) => Bytes::copy_from_slice(read_bytes(data, marker, len)?),
+        Length::Encoded(ENCODING_INT8) => {
+            let value = i8::from_le_bytes(read_array(data, marker)?);
+            value.to_string().into()
         }
-        0b10 => {
-            *marker += 4; // Discard the remaining 6 bits. The next 4 bytes from the stream represent the length
-            Some(u64::from_le_bytes(data[*marker - 4..*marker].try_into().unwrap()))
+        Length::Encoded(ENCODING_INT16) => {
+            let value = i16::from_le_bytes(read_array(data, marker)?);
+            value.to_string().into()
         }
-        0b01 => {
-            let octet1 = original & 0xFC;
-            let octet2 = data[*marker];
-            *marker += 1;
-            Some(u16::from_le_bytes([octet1, octet2]) as u64)
-        }
-        0b00 => {
-            let length = data[*marker];
-            *marker += 1;
-            if length == 0 {
-                Some(0)
-            } else {
-                // Here we need to implement the rest of the next 6 bits representing the length
-                let mut result = 0;
-                for _ in 0..length {
-                    result <<= 6;
-                    result |= data[*marker] as u64;
-                    *marker += 1;
+        Length::Encoded(ENCODING_INT32) => {
+            let value = i32::from_le_bytes(read_array(data, marker)?);
+            value.to_string().into()
+        }
+        Length::Encoded(ENCODING_LZF) => {
+            let compressed_len = read_length(data, marker)?;
+            let len = read_length(data, marker)?;
+            lzf_decompress(read_bytes(data, marker, compressed_len)?, len)?.into()
+        }
+        Length::Encoded(encoding) => return Err(anyhow!("Unsupported string encoding {}", encoding)),
+    };
+    Ok(string)
+}
+
+fn read_object(data: &[u8], marker: &mut usize, value_type: u8) -> Result<StoreItem> {
+    let item = match value_type {
+        TYPE_STRING => StoreItem::KeyValueEntry(Entry::new(read_string(data, marker)?)),
+        TYPE_LIST => {
+            let len = read_length(data, marker)?;
+            let list = (0..len).map(|_| read_string(data, marker)).collect::<Result<_>>()?;
+            StoreItem::List(list)
+        }
+        TYPE_SET => {
+            let len = read_length(data, marker)?;
+            let set = (0..len).map(|_| read_string(data, marker)).collect::<Result<_>>()?;
+            StoreItem::Set(set)
+        }
+        TYPE_SET_INTSET => StoreItem::Set(read_intset(&read_string(data, marker)?)?),
+        TYPE_SET_LISTPACK => StoreItem::Set(read_listpack(&read_string(data, marker)?)?.into_iter().collect()),
+        TYPE_ZSET => {
+            let len = read_length(data, marker)?;
+            let zset = (0..len)
+                .map(|_| Ok((read_string(data, marker)?, read_double(data, marker)?)))
+                .collect::<Result<_>>()?;
+            StoreItem::SortedSet(zset)
+        }
+        TYPE_ZSET_2 => {
+            let len = read_length(data, marker)?;
+            let zset = (0..len)
+                .map(|_| {
+                    Ok((
+                        read_string(data, marker)?,
+                        f64::from_le_bytes(read_array(data, marker)?),
+                    ))
+                })
+                .collect::<Result<_>>()?;
+            StoreItem::SortedSet(zset)
+        }
+        TYPE_ZSET_ZIPLIST => StoreItem::SortedSet(scored_pairs(read_ziplist(&read_string(data, marker)?)?)?),
+        TYPE_ZSET_LISTPACK => StoreItem::SortedSet(scored_pairs(read_listpack(&read_string(data, marker)?)?)?),
+        TYPE_HASH => {
+            let len = read_length(data, marker)?;
+            let hash = (0..len)
+                .map(|_| Ok((read_string(data, marker)?, read_string(data, marker)?)))
+                .collect::<Result<_>>()?;
+            StoreItem::Hash(hash)
+        }
+        TYPE_HASH_METADATA => {
+            let min_expiry = u64::from_le_bytes(read_array(data, marker)?);
+            let len = read_length(data, marker)?;
+            let mut hash = Hash::new();
+            for _ in 0..len {
+                let expiry = read_length(data, marker)? as u64;
+                let field = read_string(data, marker)?;
+                hash.insert(field.clone(), read_string(data, marker)?);
+                if expiry != 0 {
+                    let expiry = min_expiry
+                        .checked_add(expiry - 1)
+                        .ok_or_else(|| anyhow!("Invalid hash field expiry"))?;
+                    hash.set_expiry(field, UNIX_EPOCH + Duration::from_millis(expiry));
                 }
-                Some(result)
             }
+            hash.remove_expired();
+            StoreItem::Hash(hash)
         }
-        _ => panic!("Unreachable statement"),
-    }
-}
-
-fn read_resizedb_field(data: &[u8], marker: &mut usize) -> bool {
-    if data[*marker] != 0xFB {
-        return false;
-    }
-    *marker += 1;
-    *marker += 1;
-    *marker += 1;
-    true
-}
-
-fn read_key_value_pair(data: &[u8], marker: &mut usize) -> Result<(String, String)> {
-    let key = read_length_string(data, marker).ok_or_else(|| anyhow!("Unable to read key from the entry"))?;
-    let value = read_length_string(data, marker).ok_or_else(|| anyhow!("Unable to read value from the entry"))?;
-    Ok((key, value))
-}
-
-fn read_entry(data: &[u8], marker: &mut usize) -> Result<(String, Entry)> {
-    let mut offset = *marker;
-    match data[offset] {
-        0xFC => {
-            offset += 1;
-            let expiry_time = u64::from_le_bytes([
-                data[offset],
-                data[offset + 1],
-                data[offset + 2],
-                data[offset + 3],
-                data[offset + 4],
-                data[offset + 5],
-                data[offset + 6],
-                data[offset + 7],
-            ]);
-            offset += 8;
-            let value_type = data[offset];
-            offset += 1;
-            if value_type != 0x00 {
-                return Err(anyhow!("Unsupported value"));
+        TYPE_HASH_LISTPACK_EX => {
+            // The earliest expiry time only lets Redis skip the listpack; every triplet has its own.
+            read_array::<8>(data, marker)?;
+            let entries = read_listpack(&read_string(data, marker)?)?;
+            if !entries.len().is_multiple_of(3) {
+                return Err(anyhow!(
+                    "Invalid number of entries in an RDB hash with field expiry times"
+                ));
             }
-            let (key, value) = read_key_value_pair(data, &mut offset)?;
-
-            let expiry = UNIX_EPOCH + Duration::from_millis(expiry_time);
-            let current = SystemTime::now();
-            let duration = expiry
-                .duration_since(current)
-                .unwrap_or_else(|_| Duration::from_secs(0));
-            *marker = offset;
-            Ok((key, Entry::new(value, Some(duration))))
-        }
-        _ => {
-            if data[offset] != 0x00 {
-                return Err(anyhow!("Unsupported value"));
+            let mut entries = entries.into_iter();
+            let mut hash = Hash::new();
+            while let (Some(field), Some(value), Some(expiry)) = (entries.next(), entries.next(), entries.next()) {
+                let expiry = std::str::from_utf8(&expiry)
+                    .ok()
+                    .and_then(|expiry| expiry.parse::<u64>().ok())
+                    .ok_or_else(|| anyhow!("Invalid hash field expiry"))?;
+                hash.insert(field.clone(), value);
+                if expiry != 0 {
+                    hash.set_expiry(field, UNIX_EPOCH + Duration::from_millis(expiry));
+                }
             }
-            offset += 1;
-            let (key, value) = read_key_value_pair(data, &mut offset)?;
-            *marker = offset;
-            Ok((key, Entry::new(value, None)))
+            hash.remove_expired();
+            StoreItem::Hash(hash)
         }
+        TYPE_HASH_ZIPMAP => StoreItem::Hash(read_zipmap(&read_string(data, marker)?)?),
+        TYPE_HASH_ZIPLIST => StoreItem::Hash(pairs(read_ziplist(&read_string(data, marker)?)?)?),
+        TYPE_HASH_LISTPACK => StoreItem::Hash(pairs(read_listpack(&read_string(data, marker)?)?)?),
+        _ => return Err(anyhow!("Unsupported value type {}", value_type)),
+    };
+    Ok(item)
+}
+
+fn pairs(entries: Vec<Bytes>) -> Result<Hash> {
+    if !entries.len().is_multiple_of(2) {
+        return Err(anyhow!("Odd number of entries in an RDB hash"));
+    }
+    let mut entries = entries.into_iter();
+    let mut hash = Hash::new();
+    while let (Some(field), Some(value)) = (entries.next(), entries.next()) {
+        hash.insert(field, value);
     }
+    Ok(hash)
 }
 
-fn read_length_string(data: &[u8], marker: &mut usize) -> Option<String> {
-    let length = data[*marker] as usize;
-    *marker += 1;
-    let start = *marker;
-    let end = start + length;
-    if end > data.len() {
-        return None;
+/// Pairs up the alternating members and scores of a ziplist or listpack encoded sorted set.
+fn scored_pairs(entries: Vec<Bytes>) -> Result<SortedSet> {
+    if !entries.len().is_multiple_of(2) {
+        return Err(anyhow!("Odd number of entries in an RDB sorted set"));
     }
-    let slice = &data[start..end];
-    *marker = end;
-    String::from_utf8(slice.to_vec()).ok()
+    let mut entries = entries.into_iter();
+    let mut zset = SortedSet::new();
+    while let (Some(member), Some(score)) = (entries.next(), entries.next()) {
+        zset.insert(member, parse_score(&score)?);
+    }
+    Ok(zset)
+}
+
+/// Reads a score written as a string prefixed by its one-byte length, where three reserved lengths stand for NaN
+/// and the infinities.
+fn read_double(data: &[u8], marker: &mut usize) -> Result<f64> {
+    let [len] = read_array(data, marker)?;
+    match len {
+        253 => Ok(f64::NAN),
+        254 => Ok(f64::INFINITY),
+        255 => Ok(f64::NEG_INFINITY),
+        len => parse_score(read_bytes(data, marker, len as usize)?),
+    }
+}
+
+fn parse_score(score: &[u8]) -> Result<f64> {
+    std::str::from_utf8(score)
+        .ok()
+        .and_then(|score| score.parse().ok())
+        .ok_or_else(|| anyhow!("Invalid score in an RDB sorted set"))
+}
+
+/// Decodes an intset: the width of its integers, their count and the sorted little-endian values.
+fn read_intset(blob: &[u8]) -> Result<Set> {
+    let mut marker = 0;
+    let width = u32::from_le_bytes(read_array(blob, &mut marker)?) as usize;
+    let len = u32::from_le_bytes(read_array(blob, &mut marker)?) as usize;
+    let mut set = Set::new();
+    for _ in 0..len {
+        let value = match width {
+            2 => i16::from_le_bytes(read_array(blob, &mut marker)?) as i64,
+            4 => i32::from_le_bytes(read_array(blob, &mut marker)?) as i64,
+            8 => i64::from_le_bytes(read_array(blob, &mut marker)?),
+            _ => return Err(anyhow!("Invalid intset encoding")),
+        };
+        set.insert(value.to_string().into());
+    }
+    Ok(set)
+}
+
+/// Decodes the zipmap encoding used for small hashes before Redis 2.6.
+fn read_zipmap(blob: &[u8]) -> Result<Hash> {
+    fn read_len(blob: &[u8], marker: &mut usize) -> Result<Option<usize>> {
+        let [first] = read_array(blob, marker)?;
+        match first {
+            0xFF => Ok(None),
+            0xFE => Ok(Some(u32::from_le_bytes(read_array(blob, marker)?) as usize)),
+            len => Ok(Some(len as usize)),
+        }
+    }
+
+    let mut marker = 1; // zmlen
+    let mut hash = Hash::new();
+    while let Some(field_len) = read_len(blob, &mut marker)? {
+        let field = Bytes::copy_from_slice(read_bytes(blob, &mut marker, field_len)?);
+        let value_len = read_len(blob, &mut marker)?.ok_or_else(|| anyhow!("Truncated zipmap"))?;
+        let [free] = read_array(blob, &mut marker)?;
+        let value = Bytes::copy_from_slice(read_bytes(blob, &mut marker, value_len)?);
+        marker += free as usize;
+        hash.insert(field, value);
+    }
+    Ok(hash)
+}
+
+/// Decodes the entries of a ziplist, the compact encoding used before listpacks.
+fn read_ziplist(blob: &[u8]) -> Result<Vec<Bytes>> {
+    let mut marker = 10; // zlbytes, zltail and zllen
+    let mut entries = Vec::new();
+    loop {
+        let [prevlen] = read_array(blob, &mut marker)?;
+        if prevlen == 0xFF {
+            break;
+        }
+        if prevlen == 0xFE {
+            marker += 4;
+        }
+        let [encoding] = read_array(blob, &mut marker)?;
+        let entry = match encoding >> 6 {
+            0b00 => Bytes::copy_from_slice(read_bytes(blob, &mut marker, (encoding & 0x3F) as usize)?),
+            0b01 => {
+                let [next] = read_array(blob, &mut marker)?;
+                let len = (((encoding & 0x3F) as usize) << 8) | next as usize;
+                Bytes::copy_from_slice(read_bytes(blob, &mut marker, len)?)
+            }
+            0b10 => {
+                let len = u32::from_be_bytes(read_array(blob, &mut marker)?) as usize;
+                Bytes::copy_from_slice(read_bytes(blob, &mut marker, len)?)
+            }
+            _ => {
+                let value = match encoding {
+                    0xC0 => i16::from_le_bytes(read_array(blob, &mut marker)?) as i64,
+                    0xD0 => i32::from_le_bytes(read_array(blob, &mut marker)?) as i64,
+                    0xE0 => i64::from_le_bytes(read_array(blob, &mut marker)?),
+                    0xF0 => {
+                        let [a, b, c] = read_array(blob, &mut marker)?;
+                        (i32::from_le_bytes([0, a, b, c]) >> 8) as i64
+                    }
+                    0xFE => i8::from_le_bytes(read_array(blob, &mut marker)?) as i64,
+                    0xF1..=0xFD => (encoding & 0x0F) as i64 - 1,
+                    _ => return Err(anyhow!("Invalid ziplist entry encoding")),
+                };
+                value.to_string().into()
+            }
+        };
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Decodes the entries of a listpack, the compact encoding Redis uses for small collections.
+fn read_listpack(blob: &[u8]) -> Result<Vec<Bytes>> {
+    let mut marker = 6; // Total bytes and number of elements
+    let mut entries = Vec::new();
+    loop {
+        let start = marker;
+        let [encoding] = read_array(blob, &mut marker)?;
+        if encoding == 0xFF {
+            break;
+        }
+        let entry: Bytes = if encoding & 0x80 == 0 {
+            (encoding as i64).to_string().into()
+        } else if encoding & 0xC0 == 0x80 {
+            Bytes::copy_from_slice(read_bytes(blob, &mut marker, (encoding & 0x3F) as usize)?)
+        } else if encoding & 0xE0 == 0xC0 {
+            let [next] = read_array(blob, &mut marker)?;
+            let value = (((encoding & 0x1F) as i64) << 8) | next as i64;
+            // Sign-extend the 13-bit integer.
+            ((value << 51) >> 51).to_string().into()
+        } else if encoding & 0xF0 == 0xE0 {
+            let [next] = read_array(blob, &mut marker)?;
+            let len = (((encoding & 0x0F) as usize) << 8) | next as usize;
+            Bytes::copy_from_slice(read_bytes(blob, &mut marker, len)?)
+        } else {
+            match encoding {
+                0xF0 => {
+                    let len = u32::from_le_bytes(read_array(blob, &mut marker)?) as usize;
+                    Bytes::copy_from_slice(read_bytes(blob, &mut marker, len)?)
+                }
+                0xF1 => i16::from_le_bytes(read_array(blob, &mut marker)?).to_string().into(),
+                0xF2 => {
+                    let [a, b, c] = read_array(blob, &mut marker)?;
+                    (i32::from_le_bytes([0, a, b, c]) >> 8).to_string().into()
+                }
+                0xF3 => i32::from_le_bytes(read_array(blob, &mut marker)?).to_string().into(),
+                0xF4 => i64::from_le_bytes(read_array(blob, &mut marker)?).to_string().into(),
+                _ => return Err(anyhow!("Invalid listpack entry encoding")),
+            }
+        };
+        // Skip the back-length that lets listpacks be walked from the tail.
+        marker += match marker - start {
+            0..=127 => 1,
+            128..16383 => 2,
+            16383..2097151 => 3,
+            2097151..268435455 => 4,
+            _ => 5,
+        };
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn lzf_decompress(input: &[u8], len: usize) -> Result<Vec<u8>> {
+    let truncated = || anyhow!("Truncated LZF data in the RDB file");
+    let mut output = Vec::with_capacity(len);
+    let mut marker = 0;
+    while marker < input.len() {
+        let control = input[marker] as usize;
+        marker += 1;
+        if control < 32 {
+            let literal = input.get(marker..marker + control + 1).ok_or_else(truncated)?;
+            output.extend_from_slice(literal);
+            marker += control + 1;
+        } else {
+            let mut run = control >> 5;
+            if run == 7 {
+                run += *input.get(marker).ok_or_else(truncated)? as usize;
+                marker += 1;
+            }
+            let offset = ((control & 0x1F) << 8) + *input.get(marker).ok_or_else(truncated)? as usize + 1;
+            marker += 1;
+            let start = output.len().checked_sub(offset).ok_or_else(truncated)?;
+            // Back-references may overlap the bytes they produce, so copy one at a time.
+            for index in start..start + run + 2 {
+                output.push(output[index]);
+            }
+        }
+    }
+    if output.len() != len {
+        return Err(anyhow!("Invalid LZF data in the RDB file"));
+    }
+    Ok(output)
+}
+
+fn write_length(out: &mut Vec<u8>, len: u64) {
+    if len < 1 << 6 {
+        out.push(len as u8);
+    } else if len < 1 << 14 {
+        out.extend_from_slice(&[0x40 | (len >> 8) as u8, len as u8]);
+    } else if len <= u32::MAX as u64 {
+        out.push(0x80);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    } else {
+        out.push(0x81);
+        out.extend_from_slice(&len.to_be_bytes());
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, string: &[u8]) {
+    write_length(out, string.len() as u64);
+    out.extend_from_slice(string);
 }
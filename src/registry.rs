@@ -0,0 +1,243 @@
+use crate::{
+    blocking::serve_blocked,
+    command::{
+        table::{lookup_command, CommandFlag, CommandSpec},
+        CommandError, CommandInfo,
+    },
+    message::{Message, Protocol},
+    replica::ReplicaCommand,
+    store::Store,
+    stream::StreamInfo,
+};
+use anyhow::Result;
+use bytes::Bytes;
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::{Mutex, Notify, OwnedMutexGuard};
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+pub type HandlerFuture<'a> = Pin<Box<dyn Future<Output = Result<Message>> + Send + 'a>>;
+
+/// Signature of the plain functions backing the built-in commands.
+pub type HandlerFn = for<'a> fn(&'a mut Context, CommandInfo) -> HandlerFuture<'a>;
+
+/// A command that can be executed by the server. Implement this to add commands from outside the crate and
+/// register them with [`Registry::register`].
+pub trait CommandHandler: Send + Sync {
+    fn spec(&self) -> &CommandSpec;
+
+    /// Runs the command and returns the reply for the client. Returning an error replies with it as a RESP error.
+    fn execute<'a>(&'a self, ctx: &'a mut Context, cmd: CommandInfo) -> HandlerFuture<'a>;
+}
+
+/// State of the client a command runs on behalf of.
+#[derive(Debug)]
+pub struct ClientState {
+    pub id: u64,
+    pub protocol: Protocol,
+    pub name: Option<Bytes>,
+    /// Notified when the peer goes away while a command is still running, so blocked commands can give up.
+    pub disconnected: Arc<Notify>,
+}
+
+impl Default for ClientState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientState {
+    pub fn new() -> Self {
+        Self {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            protocol: Protocol::default(),
+            name: None,
+            disconnected: Arc::new(Notify::new()),
+        }
+    }
+}
+
+/// Everything a command can reach while it executes.
+pub struct Context {
+    pub store: Arc<Mutex<Store>>,
+    pub stream_info: Arc<StreamInfo>,
+    pub registry: Arc<Registry>,
+    pub client: ClientState,
+    /// Set by `PSYNC` to hand the connection over to the replication stream once the reply is sent.
+    pub full_resync: bool,
+    /// Commands to send to replicas instead of the executed one. `None` propagates write commands verbatim.
+    pub propagate: Option<Vec<CommandInfo>>,
+    /// Held while a write command runs, serves the clients it unblocks and is replicated, so no other write gets in
+    /// between. Blocking commands let go of it before they wait.
+    pub write_guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Context {
+    pub fn new(store: Arc<Mutex<Store>>, stream_info: Arc<StreamInfo>, registry: Arc<Registry>) -> Self {
+        Self {
+            store,
+            stream_info,
+            registry,
+            client: ClientState::new(),
+            full_resync: false,
+            propagate: None,
+            write_guard: None,
+        }
+    }
+}
+
+struct FnHandler {
+    spec: &'static CommandSpec,
+    func: HandlerFn,
+}
+
+impl CommandHandler for FnHandler {
+    fn spec(&self) -> &CommandSpec {
+        self.spec
+    }
+
+    fn execute<'a>(&'a self, ctx: &'a mut Context, cmd: CommandInfo) -> HandlerFuture<'a> {
+        (self.func)(ctx, cmd)
+    }
+}
+
+/// Maps command names to their handlers. [`Registry::default`] contains every built-in command.
+pub struct Registry {
+    handlers: BTreeMap<String, Arc<dyn CommandHandler>>,
+    /// Serializes write commands; see [`Context::write_guard`].
+    writes: Arc<Mutex<()>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        crate::handler::register_builtins(&mut registry);
+        registry
+    }
+}
+
+impl Registry {
+    pub fn empty() -> Self {
+        Self {
+            handlers: BTreeMap::new(),
+            writes: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Registers a handler under the name in its spec, replacing any command with the same name.
+    pub fn register(&mut self, handler: impl CommandHandler + 'static) {
+        let name = handler.spec().name.to_lowercase();
+        self.handlers.insert(name, Arc::new(handler));
+    }
+
+    /// Registers a built-in command whose metadata lives in the static command table.
+    pub fn register_fn(&mut self, name: &str, func: HandlerFn) {
+        let spec = lookup_command(name).unwrap_or_else(|| panic!("command '{}' is missing from the table", name));
+        self.register(FnHandler { spec, func });
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn CommandHandler>> {
+        self.handlers.get(&name.to_lowercase()).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.handlers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    pub fn specs(&self) -> impl Iterator<Item = &CommandSpec> {
+        self.handlers.values().map(|handler| handler.spec())
+    }
+
+    /// Looks the command up and checks its arity before any argument is touched.
+    pub fn lookup(&self, cmd: &CommandInfo) -> Result<Arc<dyn CommandHandler>, CommandError> {
+        let handler = self
+            .get(&cmd.name)
+            .ok_or_else(|| CommandError::Unknown(cmd.name.clone(), cmd.args.clone()))?;
+        if !handler.spec().check_arity(cmd.args.len() + 1) {
+            return Err(CommandError::WrongArity(handler.spec().name.to_string()));
+        }
+        Ok(handler)
+    }
+
+    /// Executes a command and returns its reply. Successful write commands are forwarded to the replicas and
+    /// serve the clients blocked on the keys they touched.
+    pub async fn dispatch(&self, ctx: &mut Context, cmd: CommandInfo) -> Result<Message> {
+        let handler = match self.lookup(&cmd) {
+            Ok(handler) => handler,
+            Err(err) => return Ok(Message::Error(err.to_string())),
+        };
+        let is_write = handler.spec().has_flag(CommandFlag::Write);
+        ctx.propagate = None;
+        let original = is_write.then(|| cmd.clone());
+        let keys = cmd.keys(handler.spec());
+        if is_write {
+            ctx.write_guard = Some(self.writes.clone().lock_owned().await);
+        }
+        {
+            // Like in Redis, keys past their expiry are deleted as soon as a command touches them.
+            let mut store = ctx.store.lock().await;
+            for key in &keys {
+                store.expire_if_needed(key);
+            }
+        }
+
+        let reply = match handler.execute(ctx, cmd).await {
+            Ok(reply) => reply,
+            Err(err) => Message::Error(err.to_string()),
+        };
+        let failed = matches!(reply, Message::Error(_));
+
+        let propagate = {
+            let mut store = ctx.store.lock().await;
+            // Deletions the expiry machinery made while the command ran happened before it.
+            let mut propagate = store.take_deletions();
+            match ctx.propagate.take() {
+                Some(commands) => propagate.extend(commands),
+                None if failed => {}
+                None => propagate.extend(original),
+            }
+            if is_write && !keys.is_empty() && !failed {
+                // The write may have made keys ready for blocked clients; what they do is replicated after it.
+                propagate.extend(serve_blocked(&mut store, keys));
+            }
+            propagate
+        };
+        propagate_to_replicas(&ctx.stream_info, propagate).await;
+        ctx.write_guard = None;
+        Ok(reply)
+    }
+}
+
+/// Sends `commands` to every replica. Replicas whose connection has closed are dropped rather than failing the
+/// caller, which would otherwise stop replicating to the others too.
+pub async fn propagate_to_replicas(stream_info: &StreamInfo, commands: Vec<CommandInfo>) {
+    if commands.is_empty() {
+        return;
+    }
+    let mut replications = stream_info.repl_handles.lock().await;
+    let mut closed = Vec::new();
+    for (index, replication) in replications.iter().enumerate() {
+        for command in &commands {
+            let replica_command = ReplicaCommand::new(command.to_message(), None);
+            if replication.sender.send(replica_command).await.is_err() {
+                closed.push(index);
+                break;
+            }
+        }
+    }
+    for index in closed.into_iter().rev() {
+        replications.remove(index);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::message::Message;
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use std::collections::VecDeque;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
@@ -31,10 +31,10 @@ impl ReplicaConnection {
     }
 
     pub async fn write_message(&mut self, message: Message) -> Result<()> {
-        self.write_bytes(message.encode().as_bytes()).await
+        self.write_bytes(&message.encode()).await
     }
 
-    pub async fn get_rdb(&mut self) -> Option<String> {
+    pub async fn get_rdb(&mut self) -> Option<Bytes> {
         if self.cache.is_empty() && !self.read_stream().await {
             return None;
         }
@@ -74,10 +74,10 @@ impl ReplicaConnection {
                 let message = match data[0] {
                     b'$' => {
                         index += 93; // empty rdb file length
-                        ReplicaMessage::RdbFile("foobar".to_string())
+                        ReplicaMessage::RdbFile(Bytes::from_static(b"foobar"))
                     }
-                    b'+' | b'*' => {
-                        if let Ok((message, offset)) = Message::decode(data.into()) {
+                    b'+' | b'-' | b':' | b'*' => {
+                        if let Ok(Some((message, offset))) = Message::decode(data) {
                             index += offset;
                             ReplicaMessage::Response(message)
                         } else {
@@ -1,18 +1,26 @@
 use crate::{
-    command::Command,
     message::Message,
+    registry::{Context, Registry},
     replica::connection::ReplicaConnection,
-    store::{Entry, Store},
+    store::Store,
+    stream::StreamInfo,
 };
 use anyhow::Result;
+use bytes::Bytes;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 pub struct ReplicaHandler {}
 
 impl ReplicaHandler {
-    pub async fn handle_replica(mut replica_connection: ReplicaConnection, store: Arc<Mutex<Store>>) -> Result<()> {
+    pub async fn handle_replica(
+        mut replica_connection: ReplicaConnection,
+        store: Arc<Mutex<Store>>,
+        stream_info: Arc<StreamInfo>,
+        registry: Arc<Registry>,
+    ) -> Result<()> {
         let mut bytes_received = 0;
+        let mut ctx = Context::new(store, stream_info, registry.clone());
 
         loop {
             if let Some(message) = replica_connection.get_response().await {
@@ -23,17 +31,13 @@ impl ReplicaHandler {
                     }
                 };
 
-                match cmd_info.to_command() {
-                    Some(command) => match command {
-                        Command::Set(key, entry) => process_set(&store, key, entry).await?,
-                        Command::Replconf(args) => {
-                            process_replconf(&mut replica_connection, args, bytes_received).await?
-                        }
-                        _ => {}
-                    },
-                    None => process_invalid_command(&mut replica_connection).await?,
+                if cmd_info.name.eq_ignore_ascii_case("replconf") {
+                    process_replconf(&mut replica_connection, cmd_info.args, bytes_received).await?;
+                } else {
+                    // Commands streamed by the master are applied silently: the master does not read replies.
+                    registry.dispatch(&mut ctx, cmd_info).await?;
                 }
-                let message_len = message.encode().as_bytes().len();
+                let message_len = message.encode().len();
                 bytes_received += message_len;
             } else {
                 println!("Unable to get a message from the stream");
@@ -44,29 +48,19 @@ impl ReplicaHandler {
     }
 }
 
-async fn process_set(store: &Arc<Mutex<Store>>, key: String, entry: Entry) -> Result<()> {
-    store.lock().await.set_kv(key, entry)
-}
-
 async fn process_replconf(
     replica_connection: &mut ReplicaConnection,
-    args: Vec<String>,
+    args: Vec<Bytes>,
     bytes_received: usize,
 ) -> Result<()> {
-    let command = args.first().expect("Replconf args is required").to_lowercase();
-    if command == "getack" {
+    let command = args.first().expect("Replconf args is required");
+    if command.eq_ignore_ascii_case(b"getack") {
         let message = Message::Array(vec![
-            Message::Bulk("REPLCONF".to_string()),
-            Message::Bulk("ACK".to_string()),
-            Message::Bulk(bytes_received.to_string()),
+            Message::Bulk("REPLCONF".into()),
+            Message::Bulk("ACK".into()),
+            Message::Bulk(bytes_received.to_string().into()),
         ]);
         replica_connection.write_message(message).await?;
     }
     Ok(())
 }
-
-async fn process_invalid_command(replica_connection: &mut ReplicaConnection) -> Result<()> {
-    replica_connection
-        .write_message(Message::Simple("Invalid command".to_string()))
-        .await
-}
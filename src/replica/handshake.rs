@@ -17,28 +17,28 @@ pub async fn perform_handshake_to_master(stream_info: &Arc<StreamInfo>) -> Resul
     {
         // Send PING
         let ping = Message::encode_array_str(vec!["PING"]);
-        _ = replica_connection.write_bytes(ping.as_bytes()).await;
+        _ = replica_connection.write_bytes(&ping).await;
         replica_connection.get_response().await;
     }
 
     {
         // Send REPLCONF listening-port
         let replcon = Message::encode_array_str(vec!["REPLCONF", "listening-port", "6380"]);
-        _ = replica_connection.write_bytes(replcon.as_bytes()).await;
+        _ = replica_connection.write_bytes(&replcon).await;
         replica_connection.get_response().await;
     }
 
     {
         // Send REPLCONF capa eof and capa psync2
         let replconf = Message::encode_array_str(vec!["REPLCONF", "capa", "eof", "capa", "psync2"]);
-        _ = replica_connection.write_bytes(replconf.as_bytes()).await;
+        _ = replica_connection.write_bytes(&replconf).await;
         replica_connection.get_response().await;
     }
 
     {
         // Send PSYNC
         let psync = Message::encode_array_str(vec!["PSYNC", "?", "-1"]);
-        _ = replica_connection.write_bytes(psync.as_bytes()).await;
+        _ = replica_connection.write_bytes(&psync).await;
         replica_connection.get_response().await;
     }
 
@@ -1,8 +1,9 @@
 use crate::message::Message;
+use bytes::Bytes;
 
 #[derive(Debug)]
 pub enum ReplicaMessage {
-    RdbFile(String),
+    RdbFile(Bytes),
     Response(Message),
 }
 
@@ -0,0 +1,141 @@
+use bytes::Bytes;
+use rand::seq::{IteratorRandom, SliceRandom};
+use std::collections::HashSet;
+
+/// Largest number of members kept in the compact integer encoding, like Redis' `set-max-intset-entries`.
+pub const MAX_INTSET_ENTRIES: usize = 512;
+
+/// The value of a set key.
+///
+/// Sets made only of integers are kept as a sorted vector of `i64`, like Redis' intset. The first member that is
+/// not an integer, or one too many members, upgrades the set to a hash table for good.
+#[derive(Debug, Clone)]
+pub enum Set {
+    IntSet(Vec<i64>),
+    HashTable(HashSet<Bytes>),
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Self::IntSet(Vec::new())
+    }
+}
+
+impl Set {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name of the encoding, as reported by `OBJECT ENCODING`.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Self::IntSet(_) => "intset",
+            Self::HashTable(_) => "hashtable",
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::IntSet(values) => values.len(),
+            Self::HashTable(members) => members.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match self {
+            Self::IntSet(values) => parse_int(member).is_some_and(|value| values.binary_search(&value).is_ok()),
+            Self::HashTable(members) => members.contains(member),
+        }
+    }
+
+    /// Adds `member`, returning whether it is new.
+    pub fn insert(&mut self, member: Bytes) -> bool {
+        if let Self::IntSet(values) = self {
+            if let Some(value) = parse_int(&member) {
+                let Err(position) = values.binary_search(&value) else {
+                    return false;
+                };
+                if values.len() < MAX_INTSET_ENTRIES {
+                    values.insert(position, value);
+                    return true;
+                }
+            }
+            self.upgrade();
+        }
+        match self {
+            Self::HashTable(members) => members.insert(member),
+            Self::IntSet(_) => unreachable!("the set was upgraded above"),
+        }
+    }
+
+    /// Removes `member`, returning whether it existed.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self {
+            Self::IntSet(values) => match parse_int(member).map(|value| values.binary_search(&value)) {
+                Some(Ok(position)) => {
+                    values.remove(position);
+                    true
+                }
+                _ => false,
+            },
+            Self::HashTable(members) => members.remove(member),
+        }
+    }
+
+    pub fn members(&self) -> Vec<Bytes> {
+        match self {
+            Self::IntSet(values) => values.iter().map(|value| Bytes::from(value.to_string())).collect(),
+            Self::HashTable(members) => members.iter().cloned().collect(),
+        }
+    }
+
+    /// Picks up to `count` distinct members at random.
+    pub fn random_members(&self, count: usize) -> Vec<Bytes> {
+        // Sampling reserves room for `count` members up front.
+        let count = count.min(self.len());
+        let mut rng = rand::thread_rng();
+        match self {
+            Self::IntSet(values) => values
+                .choose_multiple(&mut rng, count)
+                .map(|value| Bytes::from(value.to_string()))
+                .collect(),
+            Self::HashTable(members) => members.iter().cloned().choose_multiple(&mut rng, count),
+        }
+    }
+
+    /// Removes up to `count` members picked at random and returns them.
+    pub fn pop(&mut self, count: usize) -> Vec<Bytes> {
+        let popped = self.random_members(count);
+        for member in &popped {
+            self.remove(member);
+        }
+        popped
+    }
+
+    fn upgrade(&mut self) {
+        if let Self::IntSet(_) = self {
+            *self = Self::HashTable(self.members().into_iter().collect());
+        }
+    }
+}
+
+impl FromIterator<Bytes> for Set {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(iter: I) -> Self {
+        let mut set = Self::new();
+        for member in iter {
+            set.insert(member);
+        }
+        set
+    }
+}
+
+/// Parses `member` as an integer only if that is its exact canonical form, so that converting it back yields the
+/// same bytes.
+fn parse_int(member: &[u8]) -> Option<i64> {
+    let value: i64 = std::str::from_utf8(member).ok()?.parse().ok()?;
+    (value.to_string().as_bytes() == member).then_some(value)
+}
@@ -0,0 +1,422 @@
+use bytes::Bytes;
+use rand::seq::IteratorRandom;
+use std::{cmp::Ordering, collections::HashMap, ops::Bound};
+
+/// A member with its score, ordered like Redis orders sorted sets: by score, then bytewise by member.
+#[derive(Debug, Clone)]
+struct Scored {
+    score: f64,
+    member: Bytes,
+}
+
+impl PartialEq for Scored {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| self.member.cmp(&other.member))
+    }
+}
+
+/// The value of a sorted set key.
+///
+/// Members are kept both in a tree ordered by score, which serves ranges and ranks in logarithmic time like the
+/// skiplist of Redis, and in a map from member to score, which serves point lookups. Scores are never NaN.
+#[derive(Debug, Clone, Default)]
+pub struct SortedSet {
+    scores: HashMap<Bytes, f64>,
+    ordered: RankedTree,
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Sets the score of `member`, returning whether it is new.
+    pub fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        // Adding zero turns -0 into 0, so both sort as the same score like they do in Redis.
+        let score = score + 0.0;
+        let previous = self.scores.insert(member.clone(), score);
+        if let Some(previous) = previous {
+            self.ordered.remove(&Scored {
+                score: previous,
+                member: member.clone(),
+            });
+        }
+        self.ordered.insert(Scored { score, member });
+        previous.is_none()
+    }
+
+    /// Removes `member`, returning whether it existed.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        let Some((member, score)) = self.scores.remove_entry(member) else {
+            return false;
+        };
+        self.ordered.remove(&Scored { score, member });
+        true
+    }
+
+    /// Members with their scores, from the lowest score to the highest.
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, f64)> {
+        self.ordered
+            .iter_from(0, false)
+            .map(|scored| (&scored.member, scored.score))
+    }
+
+    /// Zero-based position of `member` in ascending order.
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        let target = Scored {
+            score,
+            member: Bytes::copy_from_slice(member),
+        };
+        Some(self.ordered.rank(&target))
+    }
+
+    /// Members from rank `start` to rank `stop` inclusive, counting from the highest score when `rev` is set.
+    pub fn range_by_rank(&self, start: usize, stop: usize, rev: bool) -> Vec<(Bytes, f64)> {
+        self.ordered
+            .iter_from(start, rev)
+            .take((stop + 1).saturating_sub(start))
+            .map(|scored| (scored.member.clone(), scored.score))
+            .collect()
+    }
+
+    /// Members whose score lies between `min` and `max`, in ascending order.
+    pub fn range_by_score(&self, min: Bound<f64>, max: Bound<f64>) -> Vec<(Bytes, f64)> {
+        let (start, end) = self.score_span(min, max);
+        self.slice(start, end)
+    }
+
+    /// Members between `min` and `max` in bytewise order, in ascending order. Like in Redis, the result is only
+    /// meaningful when every member has the same score.
+    pub fn range_by_lex(&self, min: &LexBound, max: &LexBound) -> Vec<(Bytes, f64)> {
+        let (start, end) = self.lex_span(min, max);
+        self.slice(start, end)
+    }
+
+    /// Ranks of the members whose score lies between `min` and `max`: that of the first one and the one past the
+    /// last. Both are found in logarithmic time, so the span also counts the members without visiting them.
+    pub fn score_span(&self, min: Bound<f64>, max: Bound<f64>) -> (usize, usize) {
+        let start = self.ordered.count_while(|scored| !above(scored.score, min));
+        let end = self.ordered.count_while(|scored| below(scored.score, max));
+        (start, end.max(start))
+    }
+
+    /// Ranks of the members between `min` and `max` in bytewise order, like [`SortedSet::score_span`].
+    pub fn lex_span(&self, min: &LexBound, max: &LexBound) -> (usize, usize) {
+        let start = self
+            .ordered
+            .count_while(|scored| !min.admits_from_below(&scored.member));
+        let end = self.ordered.count_while(|scored| max.admits_from_above(&scored.member));
+        (start, end.max(start))
+    }
+
+    /// Members from rank `start` up to, but not including, rank `end`, in ascending order.
+    fn slice(&self, start: usize, end: usize) -> Vec<(Bytes, f64)> {
+        self.ordered
+            .iter_from(start, false)
+            .take(end - start)
+            .map(|scored| (scored.member.clone(), scored.score))
+            .collect()
+    }
+
+    /// Removes up to `count` members from the low end, or the high end when `highest` is set, and returns them.
+    pub fn pop(&mut self, count: usize, highest: bool) -> Vec<(Bytes, f64)> {
+        let mut popped = Vec::new();
+        while popped.len() < count {
+            let next = self.ordered.pop(highest);
+            let Some(scored) = next else {
+                break;
+            };
+            self.scores.remove(&scored.member);
+            popped.push((scored.member, scored.score));
+        }
+        popped
+    }
+
+    /// Picks up to `count` distinct members at random.
+    pub fn random_members(&self, count: usize) -> Vec<(Bytes, f64)> {
+        // Sampling reserves room for `count` members up front.
+        let count = count.min(self.len());
+        self.scores
+            .iter()
+            .map(|(member, score)| (member.clone(), *score))
+            .choose_multiple(&mut rand::thread_rng(), count)
+    }
+}
+
+impl FromIterator<(Bytes, f64)> for SortedSet {
+    fn from_iter<I: IntoIterator<Item = (Bytes, f64)>>(iter: I) -> Self {
+        let mut zset = Self::new();
+        for (member, score) in iter {
+            zset.insert(member, score);
+        }
+        zset
+    }
+}
+
+/// An order statistic tree: a treap whose nodes count the members below them, so positions are found in logarithmic
+/// time like with the spans of a skiplist.
+#[derive(Debug, Clone, Default)]
+struct RankedTree {
+    root: Link,
+}
+
+type Link = Option<Box<Node>>;
+
+#[derive(Debug, Clone)]
+struct Node {
+    scored: Scored,
+    /// Random heap priority, which keeps the tree balanced in expectation.
+    priority: u32,
+    /// Number of members in the subtree rooted here.
+    size: usize,
+    left: Link,
+    right: Link,
+}
+
+impl RankedTree {
+    fn insert(&mut self, scored: Scored) {
+        let node = Box::new(Node {
+            scored,
+            priority: rand::random(),
+            size: 1,
+            left: None,
+            right: None,
+        });
+        insert(&mut self.root, node);
+    }
+
+    fn remove(&mut self, scored: &Scored) -> Option<Scored> {
+        remove(&mut self.root, scored)
+    }
+
+    /// Removes the lowest member, or the highest when `highest` is set.
+    fn pop(&mut self, highest: bool) -> Option<Scored> {
+        let mut node = self.root.as_ref()?;
+        while let Some(next) = if highest { &node.right } else { &node.left } {
+            node = next;
+        }
+        let scored = node.scored.clone();
+        self.remove(&scored)
+    }
+
+    /// Number of members ordered before `scored`.
+    fn rank(&self, scored: &Scored) -> usize {
+        self.count_while(|node| node < scored)
+    }
+
+    /// Number of members for which `before` holds, where it must hold for every member up to some point and for none
+    /// after it.
+    fn count_while(&self, before: impl Fn(&Scored) -> bool) -> usize {
+        let mut rank = 0;
+        let mut link = &self.root;
+        while let Some(node) = link {
+            if before(&node.scored) {
+                rank += size(&node.left) + 1;
+                link = &node.right;
+            } else {
+                link = &node.left;
+            }
+        }
+        rank
+    }
+
+    /// Members from position `index` on, counting from the highest member downwards when `rev` is set.
+    fn iter_from(&self, mut index: usize, rev: bool) -> Iter<'_> {
+        let mut stack = Vec::new();
+        let mut link = &self.root;
+        while let Some(node) = link {
+            let (before, after) = if rev {
+                (&node.right, &node.left)
+            } else {
+                (&node.left, &node.right)
+            };
+            let skipped = size(before);
+            if index < skipped {
+                stack.push(node.as_ref());
+                link = before;
+            } else if index == skipped {
+                stack.push(node.as_ref());
+                break;
+            } else {
+                index -= skipped + 1;
+                link = after;
+            }
+        }
+        Iter { stack, rev }
+    }
+}
+
+/// In-order walk over a `RankedTree`, holding the members still to visit whose subtrees towards the walk's direction
+/// were already visited.
+struct Iter<'a> {
+    stack: Vec<&'a Node>,
+    rev: bool,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Scored;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        let mut link = if self.rev { &node.left } else { &node.right };
+        while let Some(next) = link {
+            self.stack.push(next);
+            link = if self.rev { &next.right } else { &next.left };
+        }
+        Some(&node.scored)
+    }
+}
+
+fn size(link: &Link) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+fn insert(link: &mut Link, mut node: Box<Node>) {
+    match link {
+        Some(current) if current.priority >= node.priority => {
+            current.size += 1;
+            if node.scored < current.scored {
+                insert(&mut current.left, node);
+            } else {
+                insert(&mut current.right, node);
+            }
+        }
+        _ => {
+            let (left, right) = split(link.take(), &node.scored);
+            node.size = 1 + size(&left) + size(&right);
+            node.left = left;
+            node.right = right;
+            *link = Some(node);
+        }
+    }
+}
+
+fn remove(link: &mut Link, scored: &Scored) -> Option<Scored> {
+    let node = link.as_mut()?;
+    let removed = match scored.cmp(&node.scored) {
+        Ordering::Less => remove(&mut node.left, scored),
+        Ordering::Greater => remove(&mut node.right, scored),
+        Ordering::Equal => {
+            let node = *link.take()?;
+            *link = merge(node.left, node.right);
+            return Some(node.scored);
+        }
+    };
+    if removed.is_some() {
+        node.size -= 1;
+    }
+    removed
+}
+
+/// Splits a subtree into the members ordered before `scored` and the others.
+fn split(link: Link, scored: &Scored) -> (Link, Link) {
+    let Some(mut node) = link else {
+        return (None, None);
+    };
+    if node.scored < *scored {
+        let (left, right) = split(node.right.take(), scored);
+        node.right = left;
+        node.size = 1 + size(&node.left) + size(&node.right);
+        (Some(node), right)
+    } else {
+        let (left, right) = split(node.left.take(), scored);
+        node.left = right;
+        node.size = 1 + size(&node.left) + size(&node.right);
+        (left, Some(node))
+    }
+}
+
+/// Joins two subtrees where every member of `left` is ordered before those of `right`.
+fn merge(left: Link, right: Link) -> Link {
+    match (left, right) {
+        (None, link) | (link, None) => link,
+        (Some(mut left), Some(mut right)) => {
+            if left.priority >= right.priority {
+                left.right = merge(left.right.take(), Some(right));
+                left.size = 1 + size(&left.left) + size(&left.right);
+                Some(left)
+            } else {
+                right.left = merge(Some(left), right.left.take());
+                right.size = 1 + size(&right.left) + size(&right.right);
+                Some(right)
+            }
+        }
+    }
+}
+
+/// One end of a lexicographical range. `Min` and `Max` are the `-` and `+` of `ZRANGEBYLEX`: the smallest and the
+/// largest possible strings.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    Min,
+    Max,
+    Included(Bytes),
+    Excluded(Bytes),
+}
+
+impl LexBound {
+    /// Whether `member` lies at or above this bound used as the minimum of a range.
+    fn admits_from_below(&self, member: &[u8]) -> bool {
+        match self {
+            Self::Min => true,
+            Self::Max => false,
+            Self::Included(min) => member >= min.as_ref(),
+            Self::Excluded(min) => member > min.as_ref(),
+        }
+    }
+
+    /// Whether `member` lies at or below this bound used as the maximum of a range.
+    fn admits_from_above(&self, member: &[u8]) -> bool {
+        match self {
+            Self::Min => false,
+            Self::Max => true,
+            Self::Included(max) => member <= max.as_ref(),
+            Self::Excluded(max) => member < max.as_ref(),
+        }
+    }
+}
+
+fn above(score: f64, min: Bound<f64>) -> bool {
+    match min {
+        Bound::Included(min) => score >= min,
+        Bound::Excluded(min) => score > min,
+        Bound::Unbounded => true,
+    }
+}
+
+fn below(score: f64, max: Bound<f64>) -> bool {
+    match max {
+        Bound::Included(max) => score <= max,
+        Bound::Excluded(max) => score < max,
+        Bound::Unbounded => true,
+    }
+}
@@ -1,22 +1,137 @@
 use crate::{
+    blocking::BlockedClients,
+    command::{CommandError, CommandInfo},
+    hash::Hash,
     protocol::rdb::Rdb,
+    set::Set,
+    sorted_set::SortedSet,
     stream::{Stream, StreamData, StreamId},
+    utils::clamp_range,
 };
 use anyhow::{anyhow, Result};
+use bytes::Bytes;
 use std::{
-    collections::HashMap,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 pub trait EntryValue {
     fn value_type(&self) -> String;
 }
 
+/// The largest string a command may build, Redis' default `proto-max-bulk-len`.
+pub const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+/// The value of a string key. Values that are the canonical form of a 64-bit integer are kept as the integer itself,
+/// like Redis' `int` encoding, so counters take no allocation and increment without parsing. Other values are kept in
+/// a growable buffer that `APPEND`, `SETRANGE` and the bit commands edit in place.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringValue {
+    Int(i64),
+    Raw(Vec<u8>),
+}
+
+impl StringValue {
+    /// A copy of the value, to reply with or store elsewhere.
+    pub fn to_bytes(&self) -> Bytes {
+        Bytes::copy_from_slice(&self.as_bytes())
+    }
+
+    /// The bytes of the value, borrowed unless it is an integer.
+    pub fn as_bytes(&self) -> Cow<'_, [u8]> {
+        match self {
+            Self::Int(value) => Cow::Owned(value.to_string().into_bytes()),
+            Self::Raw(bytes) => Cow::Borrowed(bytes),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Int(value) => value.to_string().len(),
+            Self::Raw(bytes) => bytes.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The value as an integer, if it is one. Edited values stay raw-encoded, so raw bytes count when they hold the
+    /// canonical form of an integer.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Self::Int(value) => Some(*value),
+            Self::Raw(bytes) => canonical_int(bytes),
+        }
+    }
+
+    /// Runs `f` on the bytes of the value, which is left raw-encoded.
+    pub fn edit<R>(&mut self, f: impl FnOnce(&mut Vec<u8>) -> R) -> R {
+        if let Self::Int(value) = self {
+            *self = Self::Raw(value.to_string().into_bytes());
+        }
+        match self {
+            Self::Raw(bytes) => f(bytes),
+            Self::Int(_) => unreachable!("the integer was converted above"),
+        }
+    }
+
+    /// Appends `suffix`, returning the new length.
+    pub fn append(&mut self, suffix: &[u8]) -> usize {
+        self.edit(|bytes| {
+            bytes.extend_from_slice(suffix);
+            bytes.len()
+        })
+    }
+
+    /// The bytes from `start` to `end`, both inclusive. Negative indexes count from the end, and out of range indexes
+    /// are clamped like `GETRANGE` does.
+    pub fn range(&self, start: i64, end: i64) -> Bytes {
+        let bytes = self.as_bytes();
+        match clamp_range(start, end, bytes.len()) {
+            Some((start, end)) => Bytes::copy_from_slice(&bytes[start..=end]),
+            None => Bytes::new(),
+        }
+    }
+
+    /// Overwrites the bytes from `offset` on with `bytes`, padding with zero bytes when `offset` lies past the end.
+    /// Returns the new length.
+    pub fn set_range(&mut self, offset: usize, bytes: &[u8]) -> usize {
+        self.edit(|value| {
+            let end = offset + bytes.len();
+            if value.len() < end {
+                value.resize(end, 0);
+            }
+            value[offset..end].copy_from_slice(bytes);
+            value.len()
+        })
+    }
+}
+
+impl From<Bytes> for StringValue {
+    fn from(bytes: Bytes) -> Self {
+        match canonical_int(&bytes) {
+            Some(value) => Self::Int(value),
+            None => Self::Raw(bytes.into()),
+        }
+    }
+}
+
+/// Parses `bytes` as an integer only if that is its exact canonical form, so that converting it back yields the same
+/// bytes.
+fn canonical_int(bytes: &[u8]) -> Option<i64> {
+    // Longer strings cannot be a 64-bit integer, and are not worth scanning.
+    if bytes.len() > 20 {
+        return None;
+    }
+    let value: i64 = std::str::from_utf8(bytes).ok()?.parse().ok()?;
+    (value.to_string().as_bytes() == bytes).then_some(value)
+}
+
 #[derive(Debug, Clone)]
 pub struct Entry {
-    pub value: String,
-    pub expiry_time: Option<Duration>,
-    pub expiry_at: Option<SystemTime>,
+    pub value: StringValue,
 }
 
 impl EntryValue for Entry {
@@ -26,22 +141,8 @@ impl EntryValue for Entry {
 }
 
 impl Entry {
-    pub fn new(value: String, expiry: Option<Duration>) -> Self {
-        if expiry.is_some() {
-            let current_time = SystemTime::now();
-            let expiry_time = current_time + expiry.unwrap();
-            Self {
-                value,
-                expiry_time: expiry,
-                expiry_at: Some(expiry_time),
-            }
-        } else {
-            Self {
-                value,
-                expiry_at: None,
-                expiry_time: None,
-            }
-        }
+    pub fn new(value: Bytes) -> Self {
+        Self { value: value.into() }
     }
 }
 
@@ -49,6 +150,10 @@ impl Entry {
 pub enum StoreItem {
     KeyValueEntry(Entry),
     Stream(Stream),
+    List(VecDeque<Bytes>),
+    Hash(Hash),
+    Set(Set),
+    SortedSet(SortedSet),
 }
 
 impl EntryValue for StoreItem {
@@ -56,13 +161,56 @@ impl EntryValue for StoreItem {
         match self {
             Self::KeyValueEntry(x) => x.value_type(),
             Self::Stream(_) => "stream".to_string(),
+            Self::List(_) => "list".to_string(),
+            Self::Hash(_) => "hash".to_string(),
+            Self::Set(_) => "set".to_string(),
+            Self::SortedSet(_) => "zset".to_string(),
+        }
+    }
+}
+
+impl StoreItem {
+    /// Collections are removed from the keyspace as soon as they become empty.
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::List(list) => list.is_empty(),
+            Self::Hash(hash) => hash.is_empty(),
+            Self::Set(set) => set.is_empty(),
+            Self::SortedSet(zset) => zset.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Name of the internal representation, as reported by `OBJECT ENCODING`.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Self::KeyValueEntry(entry) => match &entry.value {
+                StringValue::Int(_) => "int",
+                StringValue::Raw(bytes) if bytes.len() <= 44 => "embstr",
+                StringValue::Raw(_) => "raw",
+            },
+            Self::Stream(_) => "stream",
+            Self::List(_) => "quicklist",
+            Self::Hash(_) => "hashtable",
+            Self::Set(set) => set.encoding(),
+            Self::SortedSet(_) => "skiplist",
         }
     }
 }
 
 #[derive(Debug)]
 pub struct Store {
-    pub data: HashMap<String, StoreItem>,
+    pub data: HashMap<Bytes, StoreItem>,
+    /// Absolute expiry times of the keys that have one, like Redis' `expires` dictionary. Expired keys are hidden, and
+    /// deleted once a command touches them or the active expire cycle finds them. Ordered, so the cycle can resume its
+    /// walk after the last key it checked.
+    pub expires: BTreeMap<Bytes, SystemTime>,
+    /// Keys of the hashes with fields that have an expiry time, so the active expire cycle can reclaim fields nobody
+    /// reads. Keys that no longer hold such a hash are dropped once the cycle reaches them.
+    pub expiring_hashes: BTreeSet<Bytes>,
+    pub blocked: BlockedClients,
+    /// Deletions made by the expiry machinery that still have to be sent to replicas.
+    pub deletions: Vec<CommandInfo>,
 }
 
 impl Default for Store {
@@ -73,35 +221,133 @@ impl Default for Store {
 
 impl Store {
     pub fn new() -> Self {
-        Self { data: HashMap::new() }
+        Self {
+            data: HashMap::new(),
+            expires: BTreeMap::new(),
+            expiring_hashes: BTreeSet::new(),
+            blocked: BlockedClients::default(),
+            deletions: Vec::new(),
+        }
     }
-    pub fn set_kv(&mut self, key: String, entry: Entry) -> Result<()> {
-        self.data.insert(key, StoreItem::KeyValueEntry(entry));
+    /// Writes a string at `key`, replacing whatever the key held along with its expiry.
+    pub fn set_kv(&mut self, key: Bytes, entry: Entry) -> Result<()> {
+        self.set_item(key, StoreItem::KeyValueEntry(entry));
         Ok(())
     }
 
-    pub fn get_kv(&self, key: &str) -> Option<&Entry> {
-        let store_item = self.data.get(key)?;
-        let entry = if let StoreItem::KeyValueEntry(e) = store_item {
-            e
-        } else {
-            return None;
-        };
+    /// Writes `item` at `key`, replacing whatever the key held along with its expiry.
+    pub fn set_item(&mut self, key: Bytes, item: StoreItem) {
+        self.expires.remove(&key);
+        self.data.insert(key.clone(), item);
+        self.track_expiring_fields(&key);
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<StoreItem> {
+        self.expires.remove(key);
+        self.expiring_hashes.remove(key);
+        self.data.remove(key)
+    }
+
+    /// The expiry time of `key`, if it exists and has one.
+    pub fn expiry(&self, key: &[u8]) -> Option<SystemTime> {
+        self.live(key)?;
+        self.expires.get(key).copied()
+    }
 
-        if let Some(expiry_date_time) = entry.expiry_at {
-            if SystemTime::now() > expiry_date_time {
-                return None;
+    /// Sets or clears the expiry time of `key`, which must exist.
+    pub fn set_expiry(&mut self, key: &[u8], expiry_at: Option<SystemTime>) {
+        let Some((key, _)) = self.data.get_key_value(key) else {
+            return;
+        };
+        match expiry_at {
+            Some(expiry_at) => {
+                self.expires.insert(key.clone(), expiry_at);
+            }
+            None => {
+                self.expires.remove(key);
             }
         }
-        Some(entry)
     }
 
-    pub fn get_store_item(&self, key: &str) -> Option<&StoreItem> {
-        self.data.get(key)
+    /// Whether `key` has an expiry time that has passed.
+    fn is_expired(&self, key: &[u8]) -> bool {
+        self.expires
+            .get(key)
+            .is_some_and(|expiry_at| SystemTime::now() > *expiry_at)
+    }
+
+    /// The item at `key`, unless it has expired.
+    fn live(&self, key: &[u8]) -> Option<&StoreItem> {
+        self.data.get(key).filter(|_| !self.is_expired(key))
+    }
+
+    /// Deletes `key` if it has expired, queueing a `DEL` for the replicas. Returns whether it was deleted.
+    pub fn expire_if_needed(&mut self, key: &[u8]) -> bool {
+        if !self.is_expired(key) {
+            return false;
+        }
+        self.remove(key);
+        let key = Bytes::copy_from_slice(key);
+        self.deletions.push(CommandInfo::new("DEL".to_string(), vec![key]));
+        true
+    }
+
+    /// The item at `key` for writing. An expired key is deleted first, so writes start from a missing key.
+    fn live_mut(&mut self, key: &[u8]) -> Option<&mut StoreItem> {
+        self.expire_if_needed(key);
+        self.data.get_mut(key)
     }
 
-    pub fn get_stream(&mut self, key: &str) -> Option<&mut Stream> {
-        let item = self.data.get_mut(key)?;
+    /// The item at `key` for writing, created with `create` when the key is missing or has expired.
+    fn live_or_insert(&mut self, key: Bytes, create: impl FnOnce() -> StoreItem) -> &mut StoreItem {
+        self.expire_if_needed(&key);
+        self.data.entry(key).or_insert_with(create)
+    }
+
+    pub fn get_kv(&self, key: &[u8]) -> Option<&Entry> {
+        match self.live(key)? {
+            StoreItem::KeyValueEntry(entry) => Some(entry),
+            _ => None,
+        }
+    }
+
+    /// Whether `key` exists, not counting keys that have expired.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.live(key).is_some()
+    }
+
+    /// Like `get_kv`, but reports keys holding another type instead of treating them as missing.
+    pub fn get_string(&self, key: &[u8]) -> Result<Option<&Entry>> {
+        match self.live(key) {
+            Some(StoreItem::KeyValueEntry(entry)) => Ok(Some(entry)),
+            Some(_) => Err(CommandError::WrongType.into()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_string_mut(&mut self, key: &[u8]) -> Result<Option<&mut Entry>> {
+        match self.live_mut(key) {
+            Some(StoreItem::KeyValueEntry(entry)) => Ok(Some(entry)),
+            Some(_) => Err(CommandError::WrongType.into()),
+            None => Ok(None),
+        }
+    }
+
+    /// The string at `key`, created empty when missing.
+    pub fn get_or_create_string(&mut self, key: Bytes) -> Result<&mut Entry> {
+        match self.live_or_insert(key, || StoreItem::KeyValueEntry(Entry::new(Bytes::new()))) {
+            StoreItem::KeyValueEntry(entry) => Ok(entry),
+            _ => Err(CommandError::WrongType.into()),
+        }
+    }
+
+    pub fn get_store_item(&mut self, key: &[u8]) -> Option<&StoreItem> {
+        self.remove_expired_fields(key);
+        self.live_mut(key).map(|item| &*item)
+    }
+
+    pub fn get_stream(&mut self, key: &[u8]) -> Option<&mut Stream> {
+        let item = self.live_mut(key)?;
         if let StoreItem::Stream(stream) = item {
             Some(stream)
         } else {
@@ -109,19 +355,160 @@ impl Store {
         }
     }
 
-    pub fn set_stream(&mut self, key: String, id: String, stream_data: StreamData) -> Result<()> {
-        let stream = if let Some(stream) = self.get_stream(&key) {
-            stream
-        } else {
-            self.data.insert(key.clone(), StoreItem::Stream(Stream::empty()));
-            self.get_stream(&key).unwrap()
+    pub fn get_list(&self, key: &[u8]) -> Result<Option<&VecDeque<Bytes>>> {
+        match self.live(key) {
+            Some(StoreItem::List(list)) => Ok(Some(list)),
+            Some(_) => Err(CommandError::WrongType.into()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_list_mut(&mut self, key: &[u8]) -> Result<Option<&mut VecDeque<Bytes>>> {
+        match self.live_mut(key) {
+            Some(StoreItem::List(list)) => Ok(Some(list)),
+            Some(_) => Err(CommandError::WrongType.into()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_or_create_list(&mut self, key: Bytes) -> Result<&mut VecDeque<Bytes>> {
+        match self.live_or_insert(key, || StoreItem::List(VecDeque::new())) {
+            StoreItem::List(list) => Ok(list),
+            _ => Err(CommandError::WrongType.into()),
+        }
+    }
+
+    pub fn get_hash(&mut self, key: &[u8]) -> Result<Option<&Hash>> {
+        self.remove_expired_fields(key);
+        match self.live(key) {
+            Some(StoreItem::Hash(hash)) => Ok(Some(hash)),
+            Some(_) => Err(CommandError::WrongType.into()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_hash_mut(&mut self, key: &[u8]) -> Result<Option<&mut Hash>> {
+        self.remove_expired_fields(key);
+        match self.live_mut(key) {
+            Some(StoreItem::Hash(hash)) => Ok(Some(hash)),
+            Some(_) => Err(CommandError::WrongType.into()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_or_create_hash(&mut self, key: Bytes) -> Result<&mut Hash> {
+        self.remove_expired_fields(&key);
+        match self.live_or_insert(key, || StoreItem::Hash(Hash::new())) {
+            StoreItem::Hash(hash) => Ok(hash),
+            _ => Err(CommandError::WrongType.into()),
+        }
+    }
+
+    pub fn get_set(&self, key: &[u8]) -> Result<Option<&Set>> {
+        match self.live(key) {
+            Some(StoreItem::Set(set)) => Ok(Some(set)),
+            Some(_) => Err(CommandError::WrongType.into()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_set_mut(&mut self, key: &[u8]) -> Result<Option<&mut Set>> {
+        match self.live_mut(key) {
+            Some(StoreItem::Set(set)) => Ok(Some(set)),
+            Some(_) => Err(CommandError::WrongType.into()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_or_create_set(&mut self, key: Bytes) -> Result<&mut Set> {
+        match self.live_or_insert(key, || StoreItem::Set(Set::new())) {
+            StoreItem::Set(set) => Ok(set),
+            _ => Err(CommandError::WrongType.into()),
+        }
+    }
+
+    pub fn get_sorted_set(&self, key: &[u8]) -> Result<Option<&SortedSet>> {
+        match self.live(key) {
+            Some(StoreItem::SortedSet(zset)) => Ok(Some(zset)),
+            Some(_) => Err(CommandError::WrongType.into()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_sorted_set_mut(&mut self, key: &[u8]) -> Result<Option<&mut SortedSet>> {
+        match self.live_mut(key) {
+            Some(StoreItem::SortedSet(zset)) => Ok(Some(zset)),
+            Some(_) => Err(CommandError::WrongType.into()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_or_create_sorted_set(&mut self, key: Bytes) -> Result<&mut SortedSet> {
+        match self.live_or_insert(key, || StoreItem::SortedSet(SortedSet::new())) {
+            StoreItem::SortedSet(zset) => Ok(zset),
+            _ => Err(CommandError::WrongType.into()),
+        }
+    }
+
+    /// Reclaims the expired fields of the hash at `key`, queueing their deletion for the replicas. Returns whether
+    /// any field was reclaimed.
+    pub fn remove_expired_fields(&mut self, key: &[u8]) -> bool {
+        let Some(StoreItem::Hash(hash)) = self.live_mut(key) else {
+            return false;
+        };
+        let expired = hash.remove_expired();
+        if expired.is_empty() {
+            return false;
+        }
+        let key = Bytes::copy_from_slice(key);
+        self.remove_if_empty(&key);
+        let mut args = vec![key];
+        args.extend(expired);
+        self.deletions.push(CommandInfo::new("HDEL".to_string(), args));
+        true
+    }
+
+    /// Lets the active expire cycle find the hash at `key` if it has fields with an expiry time.
+    pub fn track_expiring_fields(&mut self, key: &Bytes) {
+        if let Some(StoreItem::Hash(hash)) = self.data.get(key) {
+            if hash.has_expiring_fields() {
+                self.expiring_hashes.insert(key.clone());
+            }
+        }
+    }
+
+    /// Reclaims the expired fields of the hash at `key` for the active expire cycle, and stops tracking the key once
+    /// it no longer holds a hash with expiring fields. Returns whether any field was reclaimed.
+    pub fn reclaim_expired_fields(&mut self, key: &[u8]) -> bool {
+        let reclaimed = self.remove_expired_fields(key);
+        if !matches!(self.data.get(key), Some(StoreItem::Hash(hash)) if hash.has_expiring_fields()) {
+            self.expiring_hashes.remove(key);
+        }
+        reclaimed
+    }
+
+    pub fn take_deletions(&mut self) -> Vec<CommandInfo> {
+        std::mem::take(&mut self.deletions)
+    }
+
+    /// Deletes `key` if it holds a collection that has become empty.
+    pub fn remove_if_empty(&mut self, key: &[u8]) {
+        if self.data.get(key).is_some_and(|item| item.is_empty()) {
+            self.remove(key);
+        }
+    }
+
+    pub fn set_stream(&mut self, key: Bytes, id: String, stream_data: StreamData) -> Result<()> {
+        let stream = match self.live_or_insert(key, || StoreItem::Stream(Stream::empty())) {
+            StoreItem::Stream(stream) => stream,
+            _ => return Err(CommandError::WrongType.into()),
         };
         let stream_id = StreamId::from(id.as_str());
         stream.entries.push((stream_id, stream_data));
         Ok(())
     }
 
-    pub fn validate_stream(&mut self, key: &str, id: &str) -> Result<()> {
+    pub fn validate_stream(&mut self, key: &[u8], id: &str) -> Result<()> {
         let stream = match self.get_stream(key) {
             Some(stream) => stream,
             None => return Ok(()),
@@ -151,7 +538,7 @@ impl Store {
         Ok(())
     }
 
-    pub fn generate_stream_id(&mut self, key: &str, id_pattern: &str) -> Option<String> {
+    pub fn generate_stream_id(&mut self, key: &[u8], id_pattern: &str) -> Option<String> {
         if let Some(stream) = self.get_stream(key) {
             let last_entry = stream.entries.last().map(|(last_entry, _)| last_entry);
             build_stream_id(id_pattern, last_entry)
@@ -160,23 +547,29 @@ impl Store {
         }
     }
 
+    /// The number of keys that exist, leaving out those that have expired but were not reclaimed yet.
     pub fn len(&self) -> usize {
-        self.data.len()
+        self.data.keys().filter(|key| !self.is_expired(key)).count()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.len() == 0
+        self.data.keys().all(|key| self.is_expired(key))
     }
 
-    pub fn keys(&self) -> Vec<String> {
-        self.data.keys().cloned().collect()
+    /// The keys that exist, leaving out those that have expired but were not reclaimed yet.
+    pub fn keys(&self) -> Vec<Bytes> {
+        self.data.keys().filter(|key| !self.is_expired(key)).cloned().collect()
     }
 
     pub fn import_rdb(&mut self, data: &[u8]) -> Result<()> {
         Rdb::parse_rdb(self, data)
     }
 
-    pub fn get_stream_range(&mut self, key: &str, start: Option<StreamId>, end: Option<StreamId>) -> Option<Stream> {
+    pub fn export_rdb(&self) -> Vec<u8> {
+        Rdb::dump(self)
+    }
+
+    pub fn get_stream_range(&mut self, key: &[u8], start: Option<StreamId>, end: Option<StreamId>) -> Option<Stream> {
         let stream = self.get_stream(key)?;
         let mut range_entries: Vec<(StreamId, StreamData)> = Vec::new();
 
@@ -197,7 +590,7 @@ impl Store {
         Some(Stream { entries: range_entries })
     }
 
-    pub fn get_stream_after_id(&mut self, key: &str, id: &StreamId) -> Option<Stream> {
+    pub fn get_stream_after_id(&mut self, key: &[u8], id: &StreamId) -> Option<Stream> {
         let stream = self.get_stream(key)?;
         let mut read_entries = Vec::new();
         for (entry_id, entry_data) in &stream.entries {
@@ -211,7 +604,7 @@ impl Store {
         Some(Stream::new(read_entries))
     }
 
-    pub fn get_lastest_stream(&mut self, key: &str) -> Option<&StreamId> {
+    pub fn get_lastest_stream(&mut self, key: &[u8]) -> Option<&StreamId> {
         let stream = self.get_stream(key)?;
         let last_entry = stream.entries.last()?;
         let (last_id, _) = last_entry;
@@ -247,7 +640,7 @@ fn build_stream_id(pattern: &str, last_stream_entry: Option<&StreamId>) -> Optio
     let auto_generate_seq = cur_id_seq == "*";
     if let Some(last_id_seq) = relevant_stream_entry {
         if auto_generate_seq {
-            let next_seq = last_id_seq + 1;
+            let next_seq = last_id_seq.checked_add(1)?;
             id_seq = next_seq.to_string();
         }
     } else if auto_generate_seq {
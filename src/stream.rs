@@ -1,6 +1,7 @@
 use crate::{
     args::CliArgs, message::Message, protocol::rdb::RdbConfig, replica::ReplicaHandle, utils::random_sha1_hex,
 };
+use bytes::Bytes;
 use core::fmt;
 use std::{
     collections::HashMap,
@@ -36,17 +37,17 @@ pub struct StreamInfo {
 
 #[derive(Debug, Clone)]
 pub struct StreamData {
-    pub data: HashMap<String, String>,
+    pub data: HashMap<Bytes, Bytes>,
 }
 
 impl StreamData {
-    pub fn flatten(&self) -> Vec<String> {
-        let mut result = Vec::with_capacity(self.data.len() * 2);
-        for (key, value) in self.data.iter() {
-            result.push(key.clone());
-            result.push(value.clone());
-        }
-        result
+    pub fn to_message(&self) -> Message {
+        let fields = self
+            .data
+            .iter()
+            .map(|(key, value)| (Message::Bulk(key.clone()), Message::Bulk(value.clone())))
+            .collect();
+        Message::Map(fields)
     }
 }
 
@@ -56,6 +57,17 @@ pub struct StreamId {
     pub seq: u64,
 }
 
+impl StreamId {
+    /// Parses an ID given by a client, which unlike the ones the server builds itself may be malformed.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (ms, seq) = value.split_once('-')?;
+        Some(Self {
+            ms: ms.parse().ok()?,
+            seq: seq.parse().ok()?,
+        })
+    }
+}
+
 impl From<&str> for StreamId {
     fn from(value: &str) -> Self {
         let (ms_str, seq_str) = value.split_once('-').unwrap_or(("0", "0"));
@@ -88,14 +100,9 @@ impl Stream {
     pub fn to_message(&self) -> Message {
         let mut message_content = Vec::new();
         for (id, data) in &self.entries {
-            let mut message_data = Vec::new();
-            for (key, value) in data.data.iter() {
-                message_data.push(Message::Bulk(key.clone()));
-                message_data.push(Message::Bulk(value.clone()));
-            }
             message_content.push(Message::Array(vec![
-                Message::Bulk(id.to_string()),
-                Message::Array(message_data),
+                Message::Bulk(id.to_string().into()),
+                data.to_message(),
             ]));
         }
         Message::Array(message_content)
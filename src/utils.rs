@@ -1,5 +1,10 @@
+use bytes::Bytes;
 use rand::Rng;
 use sha1::{Digest, Sha1};
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 pub fn current_time_ms() -> u128 {
     std::time::SystemTime::now()
@@ -8,6 +13,11 @@ pub fn current_time_ms() -> u128 {
         .as_millis()
 }
 
+/// Milliseconds from the Unix epoch to `time`, or zero for earlier times.
+pub fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
 pub fn random_sha1_hex() -> String {
     let mut rng = rand::thread_rng();
     let mut sha1 = Sha1::new();
@@ -16,3 +26,141 @@ pub fn random_sha1_hex() -> String {
     let hash_bytes = sha1.finalize();
     hex::encode(hash_bytes)
 }
+
+/// Resolves a Redis-style inclusive `start..=stop` range, where negative indexes count from the end, against a
+/// sequence of `len` elements. Returns `None` when the range selects nothing.
+pub fn normalize_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+    if start > stop || start >= len {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+/// Resolves an inclusive `start..=end` range the way the string commands do, which unlike [`normalize_range`] clamp
+/// an `end` before the first element to the first element. Returns `None` when the range selects nothing.
+pub fn clamp_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    if start < 0 && end < 0 && start > end {
+        return None;
+    }
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let end = if end < 0 { (len + end).max(0) } else { end.min(len - 1) };
+    if len == 0 || start > end {
+        return None;
+    }
+    Some((start as usize, end as usize))
+}
+
+/// Formats a float like C's `%.17g`, which is how Redis prints the results of `INCRBYFLOAT` and `HINCRBYFLOAT`:
+/// 17 significant digits without trailing zeros, in exponent form only for very large or very small numbers.
+pub fn format_float(value: f64) -> String {
+    const DIGITS: i32 = 17;
+    let scientific = format!("{:.*e}", DIGITS as usize - 1, value.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or_default();
+    let digits = mantissa.replace('.', "");
+    let digits = digits.trim_end_matches('0');
+    let digits = if digits.is_empty() { "0" } else { digits };
+
+    let formatted = if !(-4..DIGITS).contains(&exponent) {
+        let (head, tail) = digits.split_at(1);
+        let point = if tail.is_empty() { "" } else { "." };
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{head}{point}{tail}e{sign}{:02}", exponent.abs())
+    } else if exponent < 0 {
+        format!("0.{}{digits}", "0".repeat((-exponent - 1) as usize))
+    } else {
+        let integer_len = exponent as usize + 1;
+        match digits.len() > integer_len {
+            true => format!("{}.{}", &digits[..integer_len], &digits[integer_len..]),
+            false => format!("{digits}{}", "0".repeat(integer_len - digits.len())),
+        }
+    };
+    match value.is_sign_negative() {
+        true => format!("-{formatted}"),
+        false => formatted,
+    }
+}
+
+/// Matches `string` against a glob-style `pattern` the way Redis does for `KEYS` and the `SCAN` family: `*`, `?`,
+/// `[...]` classes with ranges and `^` negation, and `\` escapes.
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => string.is_empty(),
+        Some((b'*', rest)) => (0..=string.len()).any(|skip| glob_match(rest, &string[skip..])),
+        Some((b'?', rest)) => !string.is_empty() && glob_match(rest, &string[1..]),
+        Some((b'[', rest)) => {
+            let Some((&first, _)) = string.split_first() else {
+                return false;
+            };
+            let (negate, mut class) = match rest.split_first() {
+                Some((b'^', class)) => (true, class),
+                _ => (false, rest),
+            };
+            let mut matched = false;
+            loop {
+                match class {
+                    [] => break,
+                    [b']', tail @ ..] => {
+                        class = tail;
+                        break;
+                    }
+                    [b'\\', escaped, tail @ ..] => {
+                        matched |= *escaped == first;
+                        class = tail;
+                    }
+                    [start, b'-', end, tail @ ..] if *end != b']' => {
+                        let (low, high) = if start <= end { (start, end) } else { (end, start) };
+                        matched |= (*low..=*high).contains(&first);
+                        class = tail;
+                    }
+                    [single, tail @ ..] => {
+                        matched |= *single == first;
+                        class = tail;
+                    }
+                }
+            }
+            matched != negate && glob_match(class, &string[1..])
+        }
+        Some((b'\\', [escaped, rest @ ..])) => string.first() == Some(escaped) && glob_match(rest, &string[1..]),
+        Some((literal, rest)) => string.first() == Some(literal) && glob_match(rest, &string[1..]),
+    }
+}
+
+/// Walks an unordered collection in the order of a stable hash of each element's key, returning up to `count`
+/// elements at or after `cursor` and the cursor to continue from (`0` once done). Elements present for the whole
+/// iteration are returned exactly once, however the collection changes between calls.
+pub fn scan<'a, T>(
+    items: impl Iterator<Item = (&'a Bytes, T)>,
+    cursor: u64,
+    count: usize,
+) -> (u64, Vec<(&'a Bytes, T)>) {
+    let mut items: Vec<(u64, &Bytes, T)> = items
+        .map(|(key, value)| (scan_hash(key), key, value))
+        .filter(|(hash, _, _)| *hash >= cursor)
+        .collect();
+    items.sort_by_key(|(hash, _, _)| *hash);
+
+    let mut batch = Vec::new();
+    let mut items = items.into_iter().peekable();
+    while let Some((hash, key, value)) = items.next() {
+        batch.push((key, value));
+        // Elements sharing a hash would share a cursor, so they are returned together.
+        if batch.len() >= count && items.peek().is_none_or(|(next, _, _)| *next != hash) {
+            break;
+        }
+    }
+    let next_cursor = items.next().map_or(0, |(hash, _, _)| hash);
+    (next_cursor, batch)
+}
+
+fn scan_hash(key: &[u8]) -> u64 {
+    // The default hasher uses fixed keys, so cursors stay valid for the lifetime of the process.
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    // Cursor 0 starts an iteration, so no element may hash to it.
+    hasher.finish().max(1)
+}